@@ -0,0 +1,49 @@
+use crate::commands::commit::list_files_with_metadata_in_bucket;
+use crate::commands::expect::load_expectations;
+use crate::commands::list::load_buckets;
+use crate::data::bucket::Bucket;
+use crate::data::expectation::ExpectationContext;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use std::env;
+
+/// Checks every expectation of the current bucket. Returns `Ok(false)` when an expectation is not met.
+pub(crate) fn execute() -> Result<bool, BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let conn = get_db_conn()?;
+
+    let expectations = load_expectations(&conn, &bucket)?;
+    if expectations.is_empty() {
+        println!("Bucket {} has no expectations.", bucket.name);
+        return Ok(true);
+    }
+
+    let bucket_names: Vec<String> = load_buckets(&conn)?.into_iter().map(|b| b.name).collect();
+    let files: Vec<String> = list_files_with_metadata_in_bucket(&bucket)?
+        .files
+        .into_iter()
+        .map(|f| f.name)
+        .collect();
+    let context = ExpectationContext {
+        bucket_names: &bucket_names,
+        files: &files,
+    };
+
+    let mut missing = 0;
+    for stored in expectations.iter() {
+        if stored.expectation.is_satisfied(&context) {
+            println!("satisfied: {}", stored.expectation);
+        } else {
+            println!("missing:   {}", stored.expectation);
+            missing += 1;
+        }
+    }
+
+    if missing > 0 {
+        println!("{} of {} expectations are not met.", missing, expectations.len());
+        return Ok(false);
+    }
+
+    println!("All expectations are met.");
+    Ok(true)
+}
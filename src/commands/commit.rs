@@ -1,26 +1,29 @@
 use std::string::String;
-use crate::data::commit::{Commit, CommittedFile};
-use crate::utils::config::{RepositoryConfig};
+use crate::data::commit::{commit_id, is_single_line, ChangeKind, Commit, CommittedFile};
+use crate::utils::config::{open_db, DeltaConfig, RepositoryConfig};
 use crate::utils::errors::BucketError;
 use blake3::{Hash, Hasher};
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::{env, io};
 use log::{debug, error};
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
 use uuid::Uuid;
 use walkdir::{DirEntry, WalkDir};
-use zstd::Encoder;
-use zstd::stream::copy_encode;
+use crate::commands::history::load_commit_files;
+use crate::commands::pack::auto_pack;
 use crate::data::bucket::Bucket;
+use crate::data::manifest::{file_name, Manifest, ManifestEntry};
 use crate::utils::checks;
 use crate::utils::checks::find_bucket_repo;
+use crate::utils::glob;
+use crate::utils::storage::{add_object_ref, storage_path, store_blob, Chunk, StoreOptions};
 
 // Execute the `commit` command
-pub(crate) fn execute(message: &String) -> Result<(), BucketError> {
+pub(crate) fn execute(message: &str) -> Result<(), BucketError> {
     // read repo config file
-    #[allow(unused_variables)]
     let repo_config = RepositoryConfig::from_file(env::current_dir().unwrap())?;
 
     let bucket = match Bucket::from_meta_data(env::current_dir()?) {
@@ -39,26 +42,29 @@ pub(crate) fn execute(message: &String) -> Result<(), BucketError> {
     }
 
     // Load the previous commit, if it exists
-    match load_last_commit(&bucket) {
-        Ok(None) => {
-            // There is no previous commit; Process all files in the current commit
-            process_files(bucket.id, &bucket.relative_bucket_path, &current_commit.files, message)?;
-        }
-        Ok(Some(previous_commit)) => {
-            // Compare the current commit with the previous commit
-            if let Some(changes) = current_commit.compare(&previous_commit) {
-                // Process the files that have changed
-                let full_bucket_path = get_full_bucket_path(&bucket);
-                process_files(bucket.id, &full_bucket_path, &changes, message)?;
-            } else {
-                // if there are no difference with previous commit cancel commit
-                println!("No changes detected. Commit cancelled.");
-                return Ok(());
-            }
-        }
+    let previous_commit = match load_last_commit(&bucket) {
+        Ok(previous_commit) => previous_commit,
         Err(_) => {
             error!("Failed to load previous commit.");
-            return Err(BucketError::from(io::Error::new(io::ErrorKind::Other, "Failed to load previous commit.")));
+            return Err(BucketError::from(io::Error::other("Failed to load previous commit.")));
+        }
+    };
+    let previous_commit = previous_commit.unwrap_or_else(|| Commit::empty(&bucket.name));
+
+    // Compare the current commit with the previous commit, without a previous commit every file is added
+    match current_commit.compare(&previous_commit) {
+        Some(changes) => {
+            // Process the files that have changed
+            let full_bucket_path = get_full_bucket_path(&bucket);
+            let author = repo_config.author();
+            process_files(bucket.id, &full_bucket_path, &changes, message, &author, &previous_commit)?;
+
+            // Gather the loose blobs into a packfile once there are too many of them
+            auto_pack(&full_bucket_path, &repo_config.packing);
+        }
+        None => {
+            // if there are no difference with previous commit cancel commit
+            println!("No changes detected. Commit cancelled.");
         }
     }
 
@@ -68,15 +74,21 @@ pub(crate) fn execute(message: &String) -> Result<(), BucketError> {
 /// Processes a list of files by inserting commit and file data into a database and optionally handling file storage.
 ///
 /// This function coordinates several operations essential for version control management:
-/// - It opens a database connection using a path derived from `bucket_path`.
-/// - It inserts a new commit record into the database.
-/// - It processes each file in the provided list by inserting file metadata into the database and handling
-///   physical file storage and compression as necessary.
+/// - It compresses and stores the content of each changed file, every blob is durable before it is referenced.
+/// - It opens a database connection using a path derived from `bucket_path` and starts a transaction.
+/// - It stores the manifest with the complete snapshot of the bucket, reusing trees and manifests that already exist.
+/// - It inserts a new commit record into the database that references the manifest.
+/// - It inserts file metadata for each changed file in the provided list into the database.
+/// - It commits the transaction, so either the whole commit is recorded or nothing is.
 ///
 /// # Arguments
 /// * `bucket_id` - The UUID of the bucket under which these files and commit are categorized.
 /// * `bucket_path` - The file system path to the bucket, used to determine the database location and storage paths.
 /// * `files` - A slice of `CommittedFile` structs representing the files to be processed.
+/// * `message` - The commit message.
+/// * `author` - The author of the commit.
+/// * `previous` - The current head commit of the bucket, an empty commit for the first commit. Modified files
+///   matching the delta patterns of the repository are stored as a delta against their version in it.
 ///
 /// # Returns
 /// Returns a `Result<(), BucketError>` indicating the success or failure of the processing operations:
@@ -101,56 +113,152 @@ pub(crate) fn execute(message: &String) -> Result<(), BucketError> {
 ///         id: Uuid::new_v4(),
 ///         name: "example.txt".to_string(),
 ///         hash: "dummy_hash".to_string(),
-///         new: false,
-///         changed: false,
+///         change: ChangeKind::Added,
 ///     },
 /// ];
 ///
-/// match process_files(bucket_id, bucket_path, &files) {
+/// match process_files(bucket_id, bucket_path, &files, "message", "artist", &Commit::empty("bucket")) {
 ///     Ok(_) => println!("Files processed successfully."),
 ///     Err(e) => eprintln!("Failed to process files: {}", e),
 /// }
 /// ```
 // Process the files in the commit
-fn process_files(bucket_id: Uuid, bucket_path: &PathBuf, files: &[CommittedFile], message: &String) -> Result<(), BucketError> {
+fn process_files(bucket_id: Uuid, bucket_path: &Path, files: &[CommittedFile], message: &str, author: &str, previous: &Commit) -> Result<(), BucketError> {
+    // Store the content of every changed file first, unchanged and deleted files are already stored.
+    // Blobs are only referenced by the database once the transaction below commits, so a crash before
+    // that point leaves unreferenced blobs behind but never a commit that points at a missing blob.
+    let storage_path = storage_path(bucket_path)?;
+    let config = RepositoryConfig::from_file(bucket_path.to_path_buf())?;
+    let previous_hashes: HashMap<&str, &Hash> = previous.files.iter().map(|f| (f.name.as_str(), &f.hash)).collect();
+    let mut chunk_lists = Vec::new();
+    for file in files.iter().filter(|file| file.change != ChangeKind::Unchanged && file.change != ChangeKind::Deleted) {
+        debug!("Storing file: {} {}", file.name, file.hash);
+        let options = StoreOptions {
+            chunking: &config.chunking,
+            codec: config.codec_for(&file.name),
+            delta_base: delta_base(file, &previous_hashes, &config.delta),
+            max_delta_depth: config.delta.max_chain_depth,
+            max_delta_size: config.delta.max_file_size,
+        };
+        if let Some(chunks) = store_blob(&storage_path, bucket_path.join(&file.name).as_path(), &file.hash, &options)? {
+            chunk_lists.push((file.hash, chunks));
+        }
+    }
+
     // Open the database connection
-    let db_location = checks::db_location(bucket_path.as_path());
-    let conn = rusqlite::Connection::open(db_location)?;
+    let db_location = checks::db_location(bucket_path);
+    let mut conn = open_db(&db_location)?;
+    let tx = conn.transaction()?;
+
+    // Store the snapshot of the bucket
+    let manifest = build_manifest(bucket_path, files)?;
+    let manifest_id = store_manifest(&tx, &manifest)?;
+    for (hash, chunks) in chunk_lists.iter() {
+        store_chunk_list(&tx, &bucket_id.to_string().to_uppercase(), hash, chunks)?;
+    }
 
     // Insert the commit into the database
     debug!("bucket id: {}", bucket_id.to_string().to_uppercase());
-    let commit_id = insert_commit(&conn, bucket_id, message)?;
+    let commit_id = insert_commit(&tx, bucket_id, message, author, previous.id.as_deref(), &manifest_id)?;
+
+    // Record each changed file in the commit, unchanged files are part of the manifest
+    for file in files.iter().filter(|file| file.change != ChangeKind::Unchanged) {
+        insert_file(&tx, &commit_id, file)?;
+        if file.change != ChangeKind::Deleted {
+            add_object_ref(&tx, &bucket_id.to_string().to_uppercase(), &file.hash)?;
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// The blob a modified file is stored as a delta against, its version in the previous commit.
+///
+/// Only files matching one of the delta patterns of the repository are stored as a delta.
+fn delta_base(file: &CommittedFile, previous: &HashMap<&str, &Hash>, delta: &DeltaConfig) -> Option<Hash> {
+    if file.change != ChangeKind::Modified || !delta.patterns.iter().any(|pattern| glob::matches(pattern, &file.name)) {
+        return None;
+    }
+    previous.get(file.name.as_str()).map(|hash| **hash)
+}
 
-    // Create the storage directory
-    let storage_path = bucket_path.join(".b").join("storage");
+/// Builds the manifest of a commit from the files in the working directory, deleted files are left out.
+fn build_manifest(bucket_path: &Path, files: &[CommittedFile]) -> io::Result<Manifest> {
+    let mut entries = Vec::new();
+    for file in files.iter().filter(|file| file.change != ChangeKind::Deleted) {
+        let metadata = std::fs::metadata(bucket_path.join(&file.name))?;
+        entries.push(ManifestEntry {
+            path: file.name.clone(),
+            hash: file.hash,
+            size: metadata.len(),
+            mode: file_mode(&metadata),
+        });
+    }
+
+    Ok(Manifest::new(entries))
+}
 
-    // Process each file in the commit
-    for file in files {
-        debug!("Processing file: {} {}", file.name, file.hash);
-        let output = storage_path.join(&file.hash.to_string());
+/// Stores a manifest and its trees, returning the id of the manifest.
+///
+/// Manifests and trees are identified by the hash of their content, when an identical manifest or tree
+/// has been stored by an earlier commit it is reused instead of stored again.
+pub(crate) fn store_manifest(conn: &Connection, manifest: &Manifest) -> Result<String, BucketError> {
+    let manifest_id = manifest.id().to_string();
+    let inserted = conn.execute("INSERT OR IGNORE INTO manifests (id) VALUES (?1)", params![manifest_id])?;
+    if inserted == 0 {
+        return Ok(manifest_id);
+    }
 
-        // Insert the file into the database
-        insert_file(&conn, &commit_id, &file.name, &file.hash.to_string())?;
+    for tree in manifest.trees() {
+        let tree_id = tree.id().to_string();
+        if conn.execute("INSERT OR IGNORE INTO trees (id) VALUES (?1)", params![tree_id])? > 0 {
+            for entry in tree.entries.iter() {
+                conn.execute(
+                    "INSERT INTO tree_entries (tree_id, name, hash, size, mode) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![tree_id, file_name(&entry.path), entry.hash.to_string(), entry.size as i64, entry.mode],
+                )?;
+            }
+        }
 
-        // TODO: Replace unwrap with proper error handling
-        // Compress and store the file
-        compress_and_store_file(&file.name, output.as_path(), 0)?;
+        conn.execute(
+            "INSERT INTO manifest_trees (manifest_id, directory, tree_id) VALUES (?1, ?2, ?3)",
+            params![manifest_id, tree.directory, tree_id],
+        )?;
     }
+
+    Ok(manifest_id)
+}
+
+/// Stores the chunk list of a file that was split into chunks, as part of the manifest.
+///
+/// Chunk lists are stored per file hash and shared by every manifest with the same file. The bucket
+/// references every chunk, so chunks are kept as long as a bucket needs them.
+pub(crate) fn store_chunk_list(conn: &Connection, bucket_id: &str, hash: &Hash, chunks: &[Chunk]) -> Result<(), BucketError> {
+    for (position, chunk) in chunks.iter().enumerate() {
+        conn.execute(
+            "INSERT OR IGNORE INTO file_chunks (hash, position, chunk_hash, size) VALUES (?1, ?2, ?3, ?4)",
+            params![hash.to_string(), position as i64, chunk.hash.to_string(), chunk.size as i64],
+        )?;
+        add_object_ref(conn, bucket_id, &chunk.hash)?;
+    }
+
     Ok(())
 }
 
 /// Inserts file metadata into the `files` table of the database.
 ///
-/// This function adds a new record to the `files` table with the specified `commit_id`, `file_path`, and `hash`.
-/// It is designed to store metadata about files associated with a specific commit in a version control system.
+/// This function adds a new record to the `files` table with the specified `commit_id` and the path, hash and
+/// change kind of the file. It is designed to store metadata about files associated with a specific commit in a
+/// version control system.
 ///
 /// # Arguments
 /// * `conn` - A reference to an open SQLite `Connection`. This connection must be to a database that has the
 ///   `files` table configured correctly.
-/// * `commit_id` - A string slice that holds the UUID of the commit this file is associated with. This ID must
+/// * `commit_id` - A string slice that holds the id of the commit this file is associated with. This ID must
 ///   correspond to a valid commit ID already present in the `commits` table.
-/// * `file_path` - A string slice representing the path of the file relative to the repository root.
-/// * `hash` - A string slice representing the hash of the file content, used to verify file integrity.
+/// * `file` - The file to record, its path is relative to the bucket root and its hash is used to verify file
+///   integrity. The change kind records how the file changed compared to the parent commit.
 ///
 /// # Returns
 /// Returns a `Result<(), BucketError>`:
@@ -169,52 +277,59 @@ fn process_files(bucket_id: Uuid, bucket_path: &PathBuf, files: &[CommittedFile]
 /// use rusqlite::Connection;
 ///
 /// let conn = Connection::open("my_database.db").unwrap();
-/// let commit_id = "1b4e28ba-2fa1-11d2-883f-0016d3cca427";
-/// let file_path = "src/main.rs";
-/// let hash = "c3ab8ff13720e8ad9047dd39466b3c894bdfa1a3";
-/// match insert_file(&conn, commit_id, file_path, hash) {
+/// let commit_id = "3fa9c2d0c4ad7b1bd0f8c2a1d87bd8e3d2b0c0b7fd6a8d8ed4e2c4f5b1a3e9d7";
+/// let file = CommittedFile {
+///     id: Default::default(),
+///     name: "textures/wall.png".to_string(),
+///     hash: blake3::hash(b"wall"),
+///     change: ChangeKind::Added,
+/// };
+/// match insert_file(&conn, commit_id, &file) {
 ///     Ok(_) => println!("File metadata inserted successfully."),
 ///     Err(e) => eprintln!("Failed to insert file metadata: {}", e),
 /// }
 /// ```
-fn insert_file(conn: &Connection, commit_id: &str, file_path: &str, hash: &str) -> Result<(), BucketError> {
+pub(crate) fn insert_file(conn: &Connection, commit_id: &str, file: &CommittedFile) -> Result<(), BucketError> {
     conn.execute(
-        "INSERT INTO files (commit_id, file_path, hash) VALUES (?1, ?2, ?3)",
-        [commit_id, file_path, hash],
+        "INSERT INTO files (commit_id, file_path, hash, change_kind, renamed_from) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![commit_id, file.name, file.hash.to_string(), file.change.as_str(), file.change.renamed_from()],
     )
         .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Error inserting into database: {}, commit id: {}, file path: {}, hash: {}", e, commit_id, file_path, hash),
+            std::io::Error::other(
+                format!("Error inserting into database: {}, commit id: {}, file path: {}, hash: {}", e, commit_id, file.name, file.hash),
             )
         })?;
     Ok(())
 }
 
-/// Inserts a new commit record into the database with the specified `bucket_id` and retrieves the auto-generated commit ID.
+/// Inserts a new commit record into the database with the specified `bucket_id` and returns its commit ID.
 ///
 /// This function performs an SQL INSERT operation to create a new commit record associated with a given bucket.
-/// The commit ID is auto-generated by the database. After inserting, the function fetches the generated ID
-/// by querying the database using the last inserted row's rowid, which is unique to each session.
+/// The commit ID is content addressed, it is the blake3 hash over the manifest, the parent, the author, the
+/// timestamp and the message of the commit. The new commit records `parent_id` as its parent and becomes the
+/// head commit of the bucket.
 ///
 /// # Arguments
 /// * `conn` - A reference to an open SQLite `Connection`. This connection must be to a database that has the
 ///   `commits` table configured correctly.
 /// * `bucket_id` - The `Uuid` of the bucket to which this commit belongs. This UUID should already exist in the
 ///   database under the `buckets` table or the relevant foreign key table.
+/// * `message` - The commit message.
+/// * `author` - The author of the commit.
+/// * `parent_id` - The id of the commit this commit is based on, `None` for the first commit of the bucket.
+/// * `manifest_id` - The id of the manifest with the snapshot of the bucket.
 ///
 /// # Returns
 /// Returns a `Result<String, BucketError>`:
-/// - `Ok(String)`: The function returns the UUID string of the newly inserted commit if the operation is successful.
-/// - `Err(BucketError)`: If any errors occur during the SQL execution or while fetching the commit ID, a `BucketError`
-///   is returned detailing the nature of the error.
+/// - `Ok(String)`: The function returns the hex encoded id of the newly inserted commit if the operation is successful.
+/// - `Err(BucketError)`: If any errors occur during the SQL execution, a `BucketError` is returned detailing the
+///   nature of the error.
 ///
 /// # Errors
 /// Errors can arise from:
 /// - SQL execution failure: If the INSERT statement fails (due to reasons like SQL syntax errors, database locks,
 ///   or foreign key constraints).
-/// - Failure in fetching the auto-generated ID: If there are issues retrieving the last insert rowid or querying it
-///   to get the commit ID.
+/// - Failure to move the head of the bucket to the new commit.
 ///
 /// # Example Usage
 /// ```
@@ -223,156 +338,102 @@ fn insert_file(conn: &Connection, commit_id: &str, file_path: &str, hash: &str)
 ///
 /// let conn = Connection::open("my_database.db").unwrap();
 /// let bucket_id = Uuid::parse_str("1b4e28ba-2fa1-11d2-883f-0016d3cca427").unwrap();
-/// match insert_commit(&conn, bucket_id) {
+/// match insert_commit(&conn, bucket_id, "message", "artist", None, &manifest_id) {
 ///     Ok(commit_id) => println!("Inserted commit with ID: {}", commit_id),
 ///     Err(e) => eprintln!("Failed to insert commit: {}", e),
 /// }
 /// ```
-fn insert_commit(conn: &Connection, bucket_id: Uuid, message: &String) -> Result<String, BucketError> {
-    // Perform the insert operation without specifying an ID, which will trigger the auto-generation.
+fn insert_commit(conn: &Connection, bucket_id: Uuid, message: &str, author: &str, parent_id: Option<&str>, manifest_id: &str) -> Result<String, BucketError> {
+    if !is_single_line(author) {
+        return Err(BucketError::from(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Author {:?} can not contain a line break", author),
+        )));
+    }
+    let created_at = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+    let commit_id = commit_id(manifest_id, parent_id, author, &created_at, message).to_string();
+
     conn.execute(
-        "INSERT INTO commits (bucket_id, message) VALUES (?1, ?2)",
-        [bucket_id.to_string().to_uppercase(), message.parse().unwrap()],
+        "INSERT INTO commits (id, bucket_id, parent_id, manifest_id, author, message, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![commit_id, bucket_id.to_string().to_uppercase(), parent_id, manifest_id, author, message, created_at],
     )
         .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error inserting into database: {}, bucket id: {}", e, bucket_id.to_string().to_uppercase()),
             )
         })?;
 
-    // Retrieve the last insert rowid, which is a feature of SQLite to get the rowid of the last inserted row.
-    let last_row_id = conn.last_insert_rowid();
-
-    // Now query back the `id` using the `rowid`
-    let mut stmt = conn.prepare("SELECT id FROM commits WHERE rowid = ?1")?;
-    let mut rows = stmt.query(params![last_row_id])?;
+    // Move the head of the bucket to the new commit
+    conn.execute(
+        "UPDATE buckets SET head_commit_id = ?1 WHERE id = ?2",
+        params![commit_id, bucket_id.to_string().to_uppercase()],
+    )?;
 
-    if let Some(row) = rows.next()? {
-        Ok(row.get(0)?)
-    } else {
-        Err(BucketError::from(rusqlite::Error::QueryReturnedNoRows))
-    }
+    Ok(commit_id)
 }
 
-/// Loads the most recent commit and its associated files from the database for a specified bucket.
+/// Loads the head commit and its associated files from the database for a specified bucket.
 ///
-/// This function accesses the SQLite database located at the path determined by the `bucket_path`.
-/// It retrieves all files associated with the most recent commit in the specified bucket. The files' metadata
+/// Every bucket keeps track of its own head, the commit that was made last in that bucket. This function
+/// looks up the head of the given bucket and retrieves all files associated with it. The files' metadata
 /// and the commit details are returned as a `Commit` struct wrapped in an `Option`. If there are no commits
-/// yet, it returns `None`.
+/// in the bucket yet, it returns `None`.
 ///
 /// # Arguments
-/// * `bucket_path` - A reference to a `Path` that specifies the directory containing the bucket.
-/// * `bucket_config` - A reference to a `BucketConfig` struct containing configuration details of the bucket,
-///   such as its name.
+/// * `bucket` - A reference to the `Bucket` whose head commit should be loaded.
 ///
 /// # Returns
 /// Returns a `Result` wrapping an `Option<Commit>`. On success, it contains:
-/// - `Some(Commit)`: A `Commit` struct containing details of the latest commit and its files.
-/// - `None`: If no commits are found in the database.
+/// - `Some(Commit)`: A `Commit` struct containing details of the head commit and its files.
+/// - `None`: If no commits are found for the bucket.
 ///
 /// # Errors
 /// Returns a `BucketError` if any errors occur during database access, query execution, or while reading
 /// the data from the database. This can include:
 /// - Database connection failures.
 /// - SQL preparation or execution errors.
-/// - Data parsing errors, such as failing to parse UUIDs or hexadecimal strings.
+/// - Data parsing errors, such as failing to parse hexadecimal strings.
 ///
 /// # Example Usage
 /// ```
-/// use std::path::Path;
-/// let bucket_path = Path::new("/path/to/bucket");
-/// let bucket_config = BucketConfig { name: "example_bucket".to_string() };
-/// match load_previous_commit(bucket_path, &bucket_config) {
+/// let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+/// match load_last_commit(&bucket) {
 ///     Ok(Some(commit)) => println!("Loaded commit with {} files.", commit.files.len()),
 ///     Ok(None) => println!("No commits found."),
 ///     Err(e) => eprintln!("Error loading commits: {}", e),
 /// }
 /// ```
-fn load_last_commit(bucket: &Bucket) -> Result<Option<Commit>, BucketError> {
+pub(crate) fn load_last_commit(bucket: &Bucket) -> Result<Option<Commit>, BucketError> {
     let full_bucket_path = get_full_bucket_path(bucket);
     let db_location = checks::db_location(full_bucket_path.as_path());
-    let conn = rusqlite::Connection::open(db_location)?;
-
-    // todo: query all commits from a specific bucket
-    let mut stmt = conn.prepare("SELECT f.id, f.file_path, f.hash
-                                               FROM files f
-                                               JOIN commits c ON f.commit_id = c.id
-                                WHERE c.created_at = (SELECT MAX(created_at) FROM commits)")?;
-
-    let mut rows = stmt.query([])?;
-
-    let mut files = Vec::new();
-    while let Some(row) = rows.next()? {
-        let uuid_string: String = row.get(0)?;
-        let hex_string: String = row.get(2)?;
-
-        files.push(CommittedFile {
-            id: uuid::Uuid::parse_str(&uuid_string).unwrap(),
-            name: row.get(1)?,
-            hash: Hash::from_hex(&hex_string).unwrap(),
-            changed: false,
-            new: false,
-        });
-    }
+    let conn = open_db(&db_location)?;
+
+    let head = conn
+        .query_row(
+            "SELECT c.id, c.parent_id, c.created_at
+             FROM buckets b
+             JOIN commits c ON c.id = b.head_commit_id
+             WHERE b.id = ?1",
+            params![bucket.id.to_string().to_uppercase()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?)),
+        )
+        .optional()?;
+
+    let (id, parent, created_at) = match head {
+        Some(head) => head,
+        None => return Ok(None),
+    };
 
     Ok(Some(Commit {
+        files: load_commit_files(&conn, &id)?,
+        id: Some(id),
+        parent,
         bucket: bucket.name.clone(),
-        files,
-        timestamp: "".to_string(),
-        previous: None,
-        next: None,
+        timestamp: created_at.unwrap_or_default(),
     }))
 }
 
-/// Compresses a file from a specified input path and stores the compressed data at an output path.
-///
-/// This function reads a file from `input_path`, compresses it using the specified `compression_level`,
-/// and writes the compressed data to a file located at `output_path`. The compression is handled using
-/// a zstd encoder, which is capable of high compression ratios and fast processing speeds suitable for
-/// a wide range of data types.
-///
-/// # Arguments
-/// * `input_path` - A string slice that holds the path to the input file to be compressed.
-/// * `output_path` - A reference to a `Path` that specifies where the compressed file should be stored.
-/// * `compression_level` - An integer specifying the compression level to be used by the encoder.
-///    A higher value results in better compression at the cost of speed. Typical values range from
-///    1 (fastest, less compression) to 22 (slowest, best compression).
-///
-/// # Returns
-/// This function returns an `io::Result<()>`. On successful execution, it returns `Ok(())`. If it encounters
-/// any I/O errors during file operations or compression, it returns an `Err` variant containing an `io::Error`.
-///
-/// # Errors
-/// Errors can arise from:
-/// - Problems opening the input file, such as file not found, lacking read permissions, or the file being locked.
-/// - Issues creating the output file, like inadequate write permissions or disk space issues.
-/// - Failures during the read, write, or compression processes, such as corrupted data or an interrupted process.
-///
-/// # Example Usage
-/// ```
-/// use std::path::Path;
-/// let result = compress_and_store_file("path/to/input/file.txt", Path::new("path/to/output/file.zst"), 3);
-/// match result {
-///     Ok(_) => println!("File compressed and stored successfully."),
-///     Err(e) => eprintln!("Failed to compress and store file: {}", e),
-/// }
-/// ```
-fn compress_and_store_file(input_path: &str, output_path: &Path, compression_level: i32) -> io::Result<()> {
-    let input_file = File::open(input_path)?;
-    let output_file = File::create(output_path)?;
-    let reader = BufReader::new(input_file);
-    let writer = BufWriter::new(output_file);
-
-    // Compress the file data and write it to the output file
-    let mut encoder = Encoder::new(writer, compression_level)?;
-    copy_encode(reader, &mut encoder, compression_level)?;
-    encoder.finish()?;
-
-    Ok(())
-}
-
 /// Generates metadata for a commit based on files found in a directory, excluding top-level `.b` directory files.
 ///
 /// This function traverses a specified directory and constructs metadata for a new commit. It includes
@@ -405,25 +466,24 @@ fn compress_and_store_file(input_path: &str, output_path: &Path, compression_lev
 ///     Err(e) => eprintln!("Error generating commit metadata: {}", e),
 /// }
 /// ```
-fn list_files_with_metadata_in_bucket(bucket: &Bucket) -> io::Result<Commit> {
+pub(crate) fn list_files_with_metadata_in_bucket(bucket: &Bucket) -> io::Result<Commit> {
     let mut files = Vec::new();
 
     let full_bucket_path = get_full_bucket_path(bucket);
 
 
     for entry in find_files_excluding_top_level_b(full_bucket_path.as_path()) {
-        let path = entry.as_path();
+        let path = full_bucket_path.join(&entry);
 
         if path.is_file() {
-            match hash_file(path) {
+            match hash_file(&path) {
                 Ok(hash) => {
                     //println!("BLAKE3 hash: {}", hash);
                     files.push(CommittedFile {
                         id: Default::default(),
-                        name: path.to_string_lossy().into_owned(),
-                        hash: hash,
-                        new: false,
-                        changed: false,
+                        name: entry.to_string_lossy().into_owned(),
+                        hash,
+                        change: ChangeKind::Unchanged,
                     });
                 }
                 Err(e) => {
@@ -440,18 +500,46 @@ fn list_files_with_metadata_in_bucket(bucket: &Bucket) -> io::Result<Commit> {
         bucket: "".to_string(),
         files,
         timestamp: chrono::Utc::now().to_rfc3339(),
-        previous: None,
-        next: None,
+        id: None,
+        parent: None,
     })
 }
 
-fn get_full_bucket_path(bucket: &Bucket) -> PathBuf {
+pub(crate) fn get_full_bucket_path(bucket: &Bucket) -> PathBuf {
     let current_dir = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
-    let full_bucket_path = find_bucket_repo(&current_dir.as_path()).unwrap().parent().unwrap().join(bucket.relative_bucket_path.as_path());
+    let full_bucket_path = find_bucket_repo(current_dir.as_path()).unwrap().parent().unwrap().join(bucket.relative_bucket_path.as_path());
     full_bucket_path
 }
 
-fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<Hash> {
+/// Returns the permission bits of a file. Platforms without Unix permissions only distinguish read-only files.
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+/// Returns the permission bits of a file. Platforms without Unix permissions only distinguish read-only files.
+#[cfg(not(unix))]
+fn file_mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() { 0o444 } else { 0o644 }
+}
+
+/// Sets the permission bits of a restored file to the mode recorded in the manifest.
+#[cfg(unix)]
+pub(crate) fn set_file_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Sets the permission bits of a restored file to the mode recorded in the manifest, only read-only is kept.
+#[cfg(not(unix))]
+pub(crate) fn set_file_mode(path: &Path, mode: u32) -> io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o222 == 0);
+    std::fs::set_permissions(path, permissions)
+}
+
+pub(crate) fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<Hash> {
     let mut file = File::open(path)?;
     let mut hasher = Hasher::new();
     let mut buffer = [0; 1024]; // Buffer for reading chunks
@@ -468,18 +556,21 @@ fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<Hash> {
 }
 
 fn find_files_excluding_top_level_b(dir: &Path) -> Vec<PathBuf> {
-
-    WalkDir::new(dir)
+    let mut files: Vec<PathBuf> = WalkDir::new(dir)
         .into_iter()
         .filter_map(Result::ok)
-        .filter(|entry| is_valid_file(entry, &dir))
-        .filter_map(|entry| make_relative_path(entry.path(), &dir))
-        .collect()
+        .filter(|entry| is_valid_file(entry, dir))
+        .filter_map(|entry| make_relative_path(entry.path(), dir))
+        .collect();
+
+    // Directory iteration order depends on the file system, sort to keep output stable
+    files.sort();
+    files
 }
 
 fn is_valid_file(entry: &DirEntry, root_dir: &Path) -> bool {
     let is_top_level_b = entry.depth() == 1 && entry.file_name() == ".b";
-    let is_inside_top_level_b = entry.path().starts_with(&root_dir.join(".b"));
+    let is_inside_top_level_b = entry.path().starts_with(root_dir.join(".b"));
 
     entry.file_type().is_file() && !is_top_level_b && !is_inside_top_level_b
 }
@@ -503,7 +594,7 @@ mod tests {
         fs::create_dir(temp_dir.path().join(".buckets")).unwrap();
         fs::create_dir(temp_dir.path().join("test_bucket")).unwrap();
 
-        std::env::set_current_dir(&temp_dir.path().join("test_bucket"))?;
+        std::env::set_current_dir(temp_dir.path().join("test_bucket"))?;
 
         let file_path = temp_dir.path().join("test_bucket").join("test_file.txt");
         let mut commited_file = File::create(&file_path)?;
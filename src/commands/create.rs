@@ -1,5 +1,5 @@
 use crate::utils::checks;
-use crate::utils::config::{get_db_conn, RepositoryConfig};
+use crate::utils::config::{get_db_conn, RepositoryConfig, StorageMode};
 use crate::utils::errors::BucketError;
 use std::env;
 use std::fs::create_dir_all;
@@ -7,16 +7,13 @@ use std::path::{Path, PathBuf};
 use crate::data::bucket::Bucket;
 
 pub fn execute(bucket_name: &String) -> Result<(), BucketError> {
-    #[allow(unused_variables)]
-        let repo_config = RepositoryConfig::from_file(env::current_dir()?).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
+    let repo_config = RepositoryConfig::from_file(env::current_dir()?).map_err(|e| {
+        std::io::Error::other(
             format!("Error reading repository config: {}", e),
         )
     })?;
     let db_conn = get_db_conn().map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
+        std::io::Error::other(
             format!("Error opening database: {}", e),
         )
     })?;
@@ -41,30 +38,31 @@ pub fn execute(bucket_name: &String) -> Result<(), BucketError> {
 
     // create bucket with given name
     create_dir_all(path.as_path()).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
+        std::io::Error::other(
             format!("Error creating bucket: {}", e),
         )
     })?;
 
-    // create storage directory
-    create_dir_all(path.join("storage")).map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Error creating storage directory: {}", e),
-        )
-    })?;
+    // create storage directory, buckets share the object store of the repository in repository mode
+    if repo_config.storage == StorageMode::Bucket {
+        create_dir_all(path.join("storage")).map_err(|e| {
+            std::io::Error::other(
+                format!("Error creating storage directory: {}", e),
+            )
+        })?;
+    }
 
-    let relative_path = to_relative_path(current_path.as_path(), path.as_path().parent().unwrap()).unwrap();
+    // bucket paths are stored relative to the top level of the repository
+    let repo_root = checks::find_bucket_repo(current_path.as_path()).unwrap().parent().unwrap().to_path_buf();
+    let relative_path = to_relative_path(repo_root.as_path(), path.as_path().parent().unwrap()).unwrap();
 
     db_conn
         .execute(
             "INSERT INTO buckets (name, path) VALUES (?1, ?2)",
-            &[&bucket_name, relative_path.to_str().unwrap()],
+            [bucket_name, relative_path.to_str().unwrap()],
         )
         .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error inserting into database: {}", e),
             )
         })?;
@@ -72,19 +70,17 @@ pub fn execute(bucket_name: &String) -> Result<(), BucketError> {
     let mut stmt = db_conn
         .prepare("SELECT id FROM buckets WHERE name = ?1 AND path = ?2")
         .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error preparing statement: {}", e),
             )
         })?;
 
     let bucket_id_str: String = stmt
-        .query_row(&[&bucket_name, relative_path.to_str().unwrap()], |row| {
+        .query_row([bucket_name, relative_path.to_str().unwrap()], |row| {
             row.get(0)
         })
         .map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error querying statement: {}", e),
             )
         })?;
@@ -92,7 +88,7 @@ pub fn execute(bucket_name: &String) -> Result<(), BucketError> {
     // add info to bucket hidden directory
     let bucket_id = uuid::Uuid::parse_str(&bucket_id_str).unwrap();
     let config = Bucket::default(bucket_id, bucket_name, &relative_path);
-    config.write_bucket_info();
+    config.write_bucket_info(path.parent().unwrap());
 
     Ok(())
 }
@@ -0,0 +1,102 @@
+use crate::data::bucket::Bucket;
+use crate::data::expectation::Expectation;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use rusqlite::{params, Connection};
+use std::env;
+use std::io;
+use uuid::Uuid;
+
+/// The actions of the `expect` command.
+pub(crate) enum ExpectAction {
+    Add(Expectation),
+    List,
+    Remove(usize),
+}
+
+/// An expectation of a bucket together with its database id.
+pub(crate) struct StoredExpectation {
+    pub id: String,
+    pub expectation: Expectation,
+}
+
+pub(crate) fn execute(action: &ExpectAction) -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let conn = get_db_conn()?;
+
+    match action {
+        ExpectAction::Add(expectation) => {
+            let existing = load_expectations(&conn, &bucket)?;
+            if existing.iter().any(|e| e.expectation == *expectation) {
+                println!("Bucket {} already expects a {}", bucket.name, expectation);
+                return Ok(());
+            }
+            insert_expectation(&conn, &bucket, expectation)?;
+            println!("Bucket {} expects a {}", bucket.name, expectation);
+        }
+        ExpectAction::List => {
+            let expectations = load_expectations(&conn, &bucket)?;
+            if expectations.is_empty() {
+                println!("Bucket {} has no expectations.", bucket.name);
+            }
+            for (i, stored) in expectations.iter().enumerate() {
+                println!("{}. {}", i + 1, stored.expectation);
+            }
+        }
+        ExpectAction::Remove(number) => {
+            let expectations = load_expectations(&conn, &bucket)?;
+            let stored = number
+                .checked_sub(1)
+                .and_then(|i| expectations.get(i))
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("Expectation {} not found, see `bucket expect list`.", number),
+                    )
+                })?;
+            conn.execute("DELETE FROM expectations WHERE id = ?1", params![stored.id])?;
+            println!("Removed expectation: {}", stored.expectation);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn insert_expectation(conn: &Connection, bucket: &Bucket, expectation: &Expectation) -> Result<(), BucketError> {
+    conn.execute(
+        "INSERT INTO expectations (id, bucket_id, kind, subject, directory) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            Uuid::new_v4().to_string().to_uppercase(),
+            bucket.id.to_string().to_uppercase(),
+            expectation.kind(),
+            expectation.subject(),
+            expectation.directory()
+        ],
+    )?;
+    Ok(())
+}
+
+/// Loads the expectations of a bucket in the order they were set.
+pub(crate) fn load_expectations(conn: &Connection, bucket: &Bucket) -> Result<Vec<StoredExpectation>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, kind, subject, directory
+         FROM expectations
+         WHERE bucket_id = ?1
+         ORDER BY created_at, rowid",
+    )?;
+
+    let mut expectations = Vec::new();
+    let mut rows = stmt.query(params![bucket.id.to_string().to_uppercase()])?;
+    while let Some(row) = rows.next()? {
+        let kind: String = row.get(1)?;
+        let expectation = Expectation::from_columns(&kind, row.get(2)?, row.get(3)?).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("Unknown expectation kind {}", kind))
+        })?;
+        expectations.push(StoredExpectation {
+            id: row.get(0)?,
+            expectation,
+        });
+    }
+
+    Ok(expectations)
+}
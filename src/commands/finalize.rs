@@ -0,0 +1,206 @@
+use crate::commands::commit::{get_full_bucket_path, list_files_with_metadata_in_bucket, load_last_commit};
+use crate::commands::expect::load_expectations;
+use crate::commands::link::load_links;
+use crate::commands::list::load_buckets;
+use crate::commands::status::BucketStatus;
+use crate::data::bucket::Bucket;
+use crate::data::commit::{Commit, CommittedFile};
+use crate::data::version::Version;
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use crate::utils::storage::{restore_blob, storage_path, PARTIAL_PREFIX};
+use rusqlite::{params, Connection};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::{env, fs, io};
+use tempfile::TempDir;
+
+/// Directory in a downstream bucket where the output of upstream buckets is delivered.
+pub(crate) const INPUTS_DIRECTORY: &str = "inputs";
+
+/// A finalized version of a bucket, pointing at the commit it was finalized from.
+pub(crate) struct FinalizedVersion {
+    pub version: String,
+    pub commit_id: String,
+    pub created_at: String,
+}
+
+/// Finalizes the head commit of the bucket as a version and delivers its output downstream.
+///
+/// The version is recorded in the same transaction as the deliveries, which only commits once the output
+/// of every downstream bucket is written to a temporary directory. The deliveries replace the inputs of the
+/// downstream buckets after the commit, so a failed delivery leaves every downstream bucket as it was and
+/// finalizing the version again delivers it again.
+pub(crate) fn execute(version: Option<&str>) -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let mut conn = get_db_conn()?;
+
+    // Only a committed and unchanged bucket can be finalized
+    let head = match load_last_commit(&bucket)? {
+        Some(head) => head,
+        None => {
+            return Err(BucketError::from(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No commits found in bucket, commit before finalizing.",
+            )))
+        }
+    };
+    let current = list_files_with_metadata_in_bucket(&bucket)?;
+    if !BucketStatus::from_commits(&current, &head).is_clean() {
+        return Err(BucketError::UncommittedChanges);
+    }
+
+    let existing = load_finalized_versions(&conn, &bucket)?;
+    let version = match version {
+        Some(version) => version.parse::<Version>().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+        None => match existing.last() {
+            Some(last) => last
+                .version
+                .parse::<Version>()
+                .map(|v| v.next())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            None => Version::Integer(1),
+        },
+    };
+
+    if existing.iter().any(|v| v.version == version.to_string()) {
+        return Err(BucketError::from(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Version {} of bucket {} already exists.", version, bucket.name),
+        )));
+    }
+
+    let head_id = head.id.clone().unwrap_or_default();
+    let tx = conn.transaction()?;
+    let deliveries = propagate(&tx, &bucket, &version, &head)?;
+    tx.execute(
+        "INSERT INTO finalized_versions (bucket_id, version, commit_id) VALUES (?1, ?2, ?3)",
+        params![bucket.id.to_string().to_uppercase(), version.to_string(), head_id],
+    )?;
+    tx.commit()?;
+
+    println!("Finalized {} version {} at commit {}", bucket.name, version, head_id);
+    for delivery in deliveries {
+        if delivery.inputs_path.is_dir() {
+            fs::remove_dir_all(&delivery.inputs_path)?;
+        }
+        fs::rename(delivery.staged.path(), &delivery.inputs_path)?;
+        println!(
+            "Delivered {} file(s) of {} version {} to {}",
+            delivery.files, bucket.name, version, delivery.downstream
+        );
+    }
+
+    Ok(())
+}
+
+/// The output of a version written to a temporary directory next to the inputs it replaces.
+struct Delivery {
+    downstream: String,
+    inputs_path: PathBuf,
+    staged: TempDir,
+    files: usize,
+}
+
+/// Writes the output of a finalized version for every downstream bucket and records it in the `inputs` table.
+///
+/// The output of a bucket are the committed files matching its file expectations. They are restored from
+/// the storage of the bucket into a temporary directory in `inputs/` of each linked downstream bucket, which
+/// replaces `inputs/<bucket name>/` once the version is recorded.
+fn propagate(conn: &Connection, bucket: &Bucket, version: &Version, commit: &Commit) -> Result<Vec<Delivery>, BucketError> {
+    let bucket_id = bucket.id.to_string().to_uppercase();
+    let downstream: Vec<String> = load_links(conn)?
+        .into_iter()
+        .filter(|l| l.from == bucket_id)
+        .map(|l| l.to)
+        .collect();
+    if downstream.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let names: Vec<String> = commit.files.iter().map(|f| f.name.clone()).collect();
+    let files: HashMap<&str, &CommittedFile> = commit.files.iter().map(|f| (f.name.as_str(), f)).collect();
+    let mut outputs: Vec<&CommittedFile> = Vec::new();
+    let mut added: HashSet<&str> = HashSet::new();
+    for stored in load_expectations(conn, bucket)? {
+        for name in stored.expectation.matching_files(&names) {
+            if let Some(file) = files.get(name.as_str()) {
+                if added.insert(file.name.as_str()) {
+                    outputs.push(file);
+                }
+            }
+        }
+    }
+    if outputs.is_empty() {
+        println!("No files match the expectations of {}, nothing to deliver downstream.", bucket.name);
+        return Ok(Vec::new());
+    }
+
+    let full_bucket_path = get_full_bucket_path(bucket);
+    let repo_root = find_repo_root(&full_bucket_path).ok_or(BucketError::NotInBucketRepo)?;
+    let storage_path = storage_path(&full_bucket_path)?;
+
+    let mut deliveries = Vec::new();
+    for record in load_buckets(conn)?.iter().filter(|b| downstream.contains(&b.id)) {
+        let downstream_path = repo_root.join(&record.path);
+        if !downstream_path.join(".b").is_dir() {
+            eprintln!("Skipping downstream bucket {}, it is missing on disk.", record.name);
+            continue;
+        }
+
+        let inputs_directory = downstream_path.join(INPUTS_DIRECTORY);
+        fs::create_dir_all(&inputs_directory)?;
+        let staged = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempdir_in(&inputs_directory)?;
+
+        // Replace whatever an earlier version of this bucket delivered
+        conn.execute(
+            "DELETE FROM inputs WHERE bucket_id = ?1 AND upstream_bucket_id = ?2",
+            params![record.id, bucket_id],
+        )?;
+        for file in outputs.iter() {
+            restore_blob(&storage_path, &file.hash, &staged.path().join(&file.name))?;
+            conn.execute(
+                "INSERT INTO inputs (bucket_id, upstream_bucket_id, version, file_path, hash) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    record.id,
+                    bucket_id,
+                    version.to_string(),
+                    PathBuf::from(INPUTS_DIRECTORY).join(&bucket.name).join(&file.name).to_string_lossy(),
+                    file.hash.to_string()
+                ],
+            )?;
+        }
+
+        deliveries.push(Delivery {
+            downstream: record.name.clone(),
+            inputs_path: inputs_directory.join(&bucket.name),
+            staged,
+            files: outputs.len(),
+        });
+    }
+
+    Ok(deliveries)
+}
+
+/// Loads every finalized version of a bucket, oldest first.
+pub(crate) fn load_finalized_versions(conn: &Connection, bucket: &Bucket) -> Result<Vec<FinalizedVersion>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT version, commit_id, created_at
+         FROM finalized_versions
+         WHERE bucket_id = ?1
+         ORDER BY created_at, rowid",
+    )?;
+
+    let versions = stmt
+        .query_map(params![bucket.id.to_string().to_uppercase()], |row| {
+            Ok(FinalizedVersion {
+                version: row.get(0)?,
+                commit_id: row.get(1)?,
+                created_at: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(versions)
+}
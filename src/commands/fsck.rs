@@ -0,0 +1,280 @@
+use crate::commands::commit::hash_file;
+use crate::commands::gc::{move_to_quarantine, quarantine_packed, quarantine_path, reachable_blobs, report_recovered, storages};
+use crate::commands::list::{disk_state, load_buckets, BucketRecord, DiskState};
+use crate::data::bucket::Bucket;
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{get_db_conn, RepositoryConfig, DEFAULT_CODEC};
+use crate::utils::errors::BucketError;
+use crate::utils::packs::{list_loose_blobs, list_packs, pack_storage, read_index, unpack};
+use crate::utils::storage::{blob_exists, list_stored_blobs, recover_repository, restore_blob, store_blob, verify_blob, StoreOptions, PARTIAL_PREFIX};
+use blake3::Hash;
+use rusqlite::{params, Connection};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+use uuid::Uuid;
+
+/// The kinds of problems `bucket fsck` looks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Category {
+    /// A blob the database references is not stored
+    MissingBlob,
+    /// A stored blob doesn't decode to the content its name is the hash of
+    CorruptBlob,
+    /// The `.b/info` of a bucket is missing or doesn't match its database row
+    BucketInfo,
+    /// A commit belongs to a bucket that is not in the database
+    OrphanedCommit,
+}
+
+const CATEGORIES: [Category; 4] = [
+    Category::MissingBlob,
+    Category::CorruptBlob,
+    Category::BucketInfo,
+    Category::OrphanedCommit,
+];
+
+impl Display for Category {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Category::MissingBlob => write!(f, "Missing blobs"),
+            Category::CorruptBlob => write!(f, "Corrupt blobs"),
+            Category::BucketInfo => write!(f, "Bucket info"),
+            Category::OrphanedCommit => write!(f, "Orphaned commits"),
+        }
+    }
+}
+
+struct Problem {
+    category: Category,
+    description: String,
+    /// What was done to repair the problem, `None` when it is not repaired
+    repair: Option<String>,
+}
+
+/// Cross-checks the repository database against the files on disk and reports every problem by category.
+///
+/// Every blob referenced by a commit or stash has to be stored, every stored blob has to decode and hash to
+/// its name, every bucket needs a `.b/info` that agrees with its row and every commit needs its bucket. With
+/// `repair` corrupt blobs are moved to `.buckets/quarantine`, missing blobs are restored from another storage
+/// directory, the quarantine or an unchanged file in a bucket, and a missing `.b/info` is written again.
+/// Temporary files that interrupted commands left in the storage are removed as well.
+///
+/// Returns `Ok(false)` when a problem remains that was not repaired.
+pub(crate) fn execute(repair: bool) -> Result<bool, BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+    let conn = get_db_conn()?;
+    let quarantine = quarantine_path(&repo_root);
+    let relative = |path: &Path| path.strip_prefix(&repo_root).unwrap_or(path).display().to_string();
+
+    if repair {
+        report_recovered(recover_repository(&repo_root)?);
+    }
+
+    let mut problems = Vec::new();
+
+    for record in load_buckets(&conn)?.iter() {
+        let state = disk_state(&repo_root, record);
+        if state == DiskState::Ok {
+            continue;
+        }
+        let bucket_path = repo_root.join(&record.path);
+        let repairable = matches!(state, DiskState::Missing | DiskState::Unreadable) && bucket_path.is_dir();
+        problems.push(Problem {
+            category: Category::BucketInfo,
+            description: format!("{} at {}: {}", record.name, record.path.display(), state),
+            repair: if repair && repairable {
+                write_bucket_info(&bucket_path, record)?;
+                Some("wrote .b/info from the database".to_string())
+            } else {
+                None
+            },
+        });
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.bucket_id FROM commits c
+         LEFT JOIN buckets b ON b.id = c.bucket_id
+         WHERE b.id IS NULL
+         ORDER BY c.created_at",
+    )?;
+    let orphaned = stmt
+        .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (commit_id, bucket_id) in orphaned {
+        problems.push(Problem {
+            category: Category::OrphanedCommit,
+            description: format!("{} references missing bucket {}", commit_id, bucket_id),
+            repair: None,
+        });
+    }
+
+    let storages = storages(&conn, &repo_root, &config)?;
+    for (storage_path, bucket_id) in storages.iter() {
+        let mut corrupt = Vec::new();
+        for (hash, _) in list_stored_blobs(storage_path)? {
+            if !verify_blob(storage_path, &hash) {
+                corrupt.push(hash);
+            }
+        }
+        if repair && !corrupt.is_empty() {
+            quarantine_blobs(storage_path, &corrupt, &quarantine, config.packing.max_blob_size)?;
+        }
+        for hash in corrupt.iter() {
+            problems.push(Problem {
+                category: Category::CorruptBlob,
+                description: format!("{} in {}", hash, relative(storage_path)),
+                repair: repair.then(|| format!("moved to {}", relative(&quarantine))),
+            });
+        }
+
+        let mut missing = Vec::new();
+        for hash in reachable_blobs(&conn, storage_path, bucket_id.as_deref())? {
+            if !blob_exists(storage_path, &hash)? {
+                missing.push(hash);
+            }
+        }
+        missing.sort_by(|a, b| a.as_bytes().cmp(b.as_bytes()));
+
+        // Copies of a blob can be in the other storage directories or the quarantine
+        let sources: Vec<&Path> = storages
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .filter(|path| *path != storage_path)
+            .chain([quarantine.as_path()])
+            .collect();
+        for hash in missing {
+            let files = referencing_files(&conn, &repo_root, &hash, bucket_id.as_deref())?;
+            let description = match files.first() {
+                Some((_, file)) => format!("{} of {} in {}", hash, relative(file), relative(storage_path)),
+                None => format!("{} in {}", hash, relative(storage_path)),
+            };
+            let repair = if repair {
+                restore_missing(&config, storage_path, &hash, &sources, &files)?.map(|source| format!("restored from {}", relative(&source)))
+            } else {
+                None
+            };
+            problems.push(Problem {
+                category: Category::MissingBlob,
+                description,
+                repair,
+            });
+        }
+    }
+
+    for category in CATEGORIES {
+        let found: Vec<&Problem> = problems.iter().filter(|problem| problem.category == category).collect();
+        println!("{}: {}", category, found.len());
+        for problem in found {
+            match &problem.repair {
+                Some(repair) => println!("  {}, {}", problem.description, repair),
+                None => println!("  {}", problem.description),
+            }
+        }
+    }
+
+    let repaired = problems.iter().filter(|problem| problem.repair.is_some()).count();
+    if problems.is_empty() {
+        println!("No problems found.");
+    } else if repair {
+        println!("Found {} problem(s), repaired {}.", problems.len(), repaired);
+    } else {
+        println!("Found {} problem(s), run `bucket fsck --repair` to repair what can be repaired.", problems.len());
+    }
+
+    Ok(repaired == problems.len())
+}
+
+// Writes the .b/info of a bucket from its database row
+fn write_bucket_info(bucket_path: &Path, record: &BucketRecord) -> Result<(), BucketError> {
+    let id = Uuid::parse_str(&record.id).map_err(|_| BucketError::NotAValidBucket)?;
+    fs::create_dir_all(bucket_path.join(".b"))?;
+    Bucket::default(id, &record.name, &record.path).write_bucket_info(bucket_path);
+    Ok(())
+}
+
+// Moves blobs out of a storage directory into the quarantine, packed blobs are dropped from their pack
+fn quarantine_blobs(storage_path: &Path, hashes: &[Hash], quarantine: &Path, max_blob_size: u64) -> Result<(), BucketError> {
+    for (hash, path) in list_loose_blobs(storage_path)? {
+        if hashes.contains(&hash) {
+            move_to_quarantine(&path, quarantine, &hash)?;
+        }
+    }
+
+    let mut unpacked = false;
+    for pack_path in list_packs(storage_path)? {
+        let blobs: Vec<_> = read_index(&pack_path)?.into_iter().filter(|blob| hashes.contains(&blob.hash)).collect();
+        if blobs.is_empty() {
+            continue;
+        }
+        for blob in blobs.iter() {
+            quarantine_packed(&pack_path, blob, quarantine)?;
+        }
+        unpack(storage_path, &pack_path, |hash| !hashes.contains(hash))?;
+        unpacked = true;
+    }
+    if unpacked {
+        pack_storage(storage_path, max_blob_size)?;
+    }
+
+    Ok(())
+}
+
+// The files in the buckets that a commit or stash stored as the blob with hash, as the path relative to their
+// bucket and the path on disk
+fn referencing_files(conn: &Connection, repo_root: &Path, hash: &Hash, bucket_id: Option<&str>) -> Result<Vec<(String, PathBuf)>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT b.path, f.file_path FROM files f
+         JOIN commits c ON c.id = f.commit_id
+         JOIN buckets b ON b.id = c.bucket_id
+         WHERE f.hash = ?1 AND (?2 IS NULL OR c.bucket_id = ?2)
+         UNION
+         SELECT b.path, sf.file_path FROM stash_files sf
+         JOIN stashes s ON s.id = sf.stash_id
+         JOIN buckets b ON b.id = s.bucket_id
+         WHERE sf.hash = ?1 AND (?2 IS NULL OR s.bucket_id = ?2)",
+    )?;
+    let files = stmt
+        .query_map(params![hash.to_string(), bucket_id], |row| {
+            let file_path: String = row.get(1)?;
+            let path = repo_root.join(row.get::<_, String>(0)?).join(&file_path);
+            Ok((file_path, path))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(files)
+}
+
+// Stores a missing blob again from an intact copy in another storage directory or from a file that still has
+// the content, returns where the blob was restored from
+fn restore_missing(config: &RepositoryConfig, storage_path: &Path, hash: &Hash, sources: &[&Path], files: &[(String, PathBuf)]) -> Result<Option<PathBuf>, BucketError> {
+    let codec = files.first().map_or(DEFAULT_CODEC, |(file_path, _)| config.codec_for(file_path));
+    let options = StoreOptions {
+        chunking: &config.chunking,
+        codec,
+        delta_base: None,
+        max_delta_depth: config.delta.max_chain_depth,
+        max_delta_size: config.delta.max_file_size,
+    };
+
+    for source in sources {
+        if !blob_exists(source, hash)? || !verify_blob(source, hash) {
+            continue;
+        }
+        fs::create_dir_all(storage_path)?;
+        let content = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(storage_path)?;
+        restore_blob(source, hash, content.path())?;
+        store_blob(storage_path, content.path(), hash, &options)?;
+        return Ok(Some(source.to_path_buf()));
+    }
+
+    for (_, file) in files {
+        if file.is_file() && hash_file(file)? == *hash {
+            store_blob(storage_path, file, hash, &options)?;
+            return Ok(Some(file.clone()));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,237 @@
+use crate::commands::list::load_buckets;
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{get_db_conn, RepositoryConfig, StorageMode};
+use crate::utils::errors::BucketError;
+use crate::utils::packs::{list_loose_blobs, list_packs, open_packed, pack_storage, read_index, unpack, PackedBlob};
+use crate::utils::storage::{blob_exists, bucket_storage_path, delta_header, objects_path, read_chunk_list, recover_repository, write_atomically};
+use blake3::Hash;
+use rusqlite::{params, Connection};
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use std::{env, fs, io};
+
+/// Directory in `.buckets` that unreachable blobs are moved to with `--quarantine`.
+const QUARANTINE_DIRECTORY: &str = "quarantine";
+
+pub(crate) struct GcOptions {
+    /// Only report what would be removed
+    pub dry_run: bool,
+    /// Move unreachable blobs to `.buckets/quarantine` instead of deleting them
+    pub quarantine: bool,
+    /// Overrides `grace_period_days` of the `[gc]` section of the config
+    pub grace_period_days: Option<u64>,
+}
+
+/// What garbage collection found in, or removed from, the storage of the repository.
+#[derive(Debug, Default)]
+struct GcSummary {
+    blobs: usize,
+    bytes: u64,
+    young: usize,
+}
+
+/// Removes the blobs that no commit, stash or finalized version references anymore.
+///
+/// Every hash reachable from the database is marked, together with the chunks of chunked files and the bases
+/// of deltas. Unreachable blobs older than the grace period are deleted, or moved to `.buckets/quarantine`.
+/// A packfile holding unreachable blobs is unpacked and its remaining blobs are packed again. Temporary files
+/// that interrupted commands left in the storage are removed first.
+pub(crate) fn execute(options: &GcOptions) -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+    let conn = get_db_conn()?;
+
+    let grace_period_days = options.grace_period_days.unwrap_or(config.gc.grace_period_days);
+    let cutoff = SystemTime::now() - Duration::from_secs(grace_period_days * 24 * 60 * 60);
+    let quarantine = quarantine_path(&repo_root);
+
+    if !options.dry_run {
+        report_recovered(recover_repository(&repo_root)?);
+    }
+
+    let mut summary = GcSummary::default();
+    for (storage_path, bucket_id) in storages(&conn, &repo_root, &config)?.iter() {
+        let reachable = reachable_blobs(&conn, storage_path, bucket_id.as_deref())?;
+        let mut removed = Vec::new();
+
+        for (hash, path) in list_loose_blobs(storage_path)? {
+            if reachable.contains(&hash) {
+                continue;
+            }
+            let metadata = fs::metadata(&path)?;
+            if metadata.modified()? > cutoff {
+                summary.young += 1;
+                continue;
+            }
+            summary.blobs += 1;
+            summary.bytes += metadata.len();
+            if !options.dry_run {
+                if options.quarantine {
+                    move_to_quarantine(&path, &quarantine, &hash)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+                removed.push(hash);
+            }
+        }
+
+        let mut unpacked = false;
+        for pack_path in list_packs(storage_path)? {
+            let unreachable: Vec<_> = read_index(&pack_path)?
+                .into_iter()
+                .filter(|blob| !reachable.contains(&blob.hash))
+                .collect();
+            if unreachable.is_empty() {
+                continue;
+            }
+            // A pack is younger than every blob in it, so its age is a safe bound for the age of the blobs
+            if fs::metadata(&pack_path)?.modified()? > cutoff {
+                summary.young += unreachable.len();
+                continue;
+            }
+            summary.blobs += unreachable.len();
+            summary.bytes += unreachable.iter().map(|blob| blob.length).sum::<u64>();
+            if options.dry_run {
+                continue;
+            }
+
+            if options.quarantine {
+                for blob in unreachable.iter() {
+                    quarantine_packed(&pack_path, blob, &quarantine)?;
+                }
+            }
+            unpack(storage_path, &pack_path, |hash| reachable.contains(hash))?;
+            removed.extend(unreachable.iter().map(|blob| blob.hash));
+            unpacked = true;
+        }
+
+        if unpacked {
+            pack_storage(storage_path, config.packing.max_blob_size)?;
+        }
+        remove_object_refs(&conn, &removed, bucket_id.as_deref())?;
+    }
+
+    if summary.blobs == 0 {
+        println!("No unreachable blobs to remove.");
+    } else if options.dry_run {
+        println!("{} unreachable blob(s) of {} bytes can be reclaimed.", summary.blobs, summary.bytes);
+    } else if options.quarantine {
+        println!(
+            "Moved {} unreachable blob(s) of {} bytes to {}.",
+            summary.blobs,
+            summary.bytes,
+            quarantine.strip_prefix(&repo_root).unwrap_or(&quarantine).display()
+        );
+    } else {
+        println!("Removed {} unreachable blob(s), reclaimed {} bytes.", summary.blobs, summary.bytes);
+    }
+    if summary.young > 0 {
+        println!("Kept {} unreachable blob(s) younger than {} day(s).", summary.young, grace_period_days);
+    }
+
+    Ok(())
+}
+
+/// Reports the temporary files of interrupted commands that were removed from the storage.
+pub(crate) fn report_recovered(removed: usize) {
+    if removed > 0 {
+        println!("Removed {} partially written file(s) left by interrupted commands.", removed);
+    }
+}
+
+/// The storage directories of the repository, each with the bucket whose blobs it holds or `None` for the object
+/// store that holds the blobs of every bucket.
+pub(crate) fn storages(conn: &Connection, repo_root: &Path, config: &RepositoryConfig) -> Result<Vec<(PathBuf, Option<String>)>, BucketError> {
+    Ok(match config.storage {
+        StorageMode::Repository => vec![(objects_path(repo_root), None)],
+        StorageMode::Bucket => load_buckets(conn)?
+            .into_iter()
+            .map(|record| (bucket_storage_path(&repo_root.join(&record.path)), Some(record.id)))
+            .collect(),
+    })
+}
+
+/// The directory in `.buckets` that blobs are moved to instead of deleting them.
+pub(crate) fn quarantine_path(repo_root: &Path) -> PathBuf {
+    repo_root.join(".buckets").join(QUARANTINE_DIRECTORY)
+}
+
+/// Marks every blob in a storage directory that the database references: the files of every commit that was
+/// not pruned, stash and finalized version of the bucket, of every bucket when `bucket_id` is `None`. Chunked
+/// files reference their chunks and deltas reference their base, those blobs are reachable as well.
+pub(crate) fn reachable_blobs(conn: &Connection, storage_path: &Path, bucket_id: Option<&str>) -> Result<HashSet<Hash>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT te.hash FROM commits c
+         JOIN manifest_trees mt ON mt.manifest_id = c.manifest_id
+         JOIN tree_entries te ON te.tree_id = mt.tree_id
+         WHERE (?1 IS NULL OR c.bucket_id = ?1) AND c.pruned_at IS NULL
+         UNION
+         SELECT f.hash FROM files f
+         JOIN commits c ON c.id = f.commit_id
+         WHERE (?1 IS NULL OR c.bucket_id = ?1) AND c.pruned_at IS NULL AND f.change_kind IS NOT 'deleted'
+         UNION
+         SELECT sf.hash FROM stash_files sf
+         JOIN stashes s ON s.id = sf.stash_id
+         WHERE ?1 IS NULL OR s.bucket_id = ?1
+         UNION
+         SELECT te.hash FROM finalized_versions fv
+         JOIN commits c ON c.id = fv.commit_id
+         JOIN manifest_trees mt ON mt.manifest_id = c.manifest_id
+         JOIN tree_entries te ON te.tree_id = mt.tree_id
+         WHERE ?1 IS NULL OR fv.bucket_id = ?1",
+    )?;
+    let hashes = stmt
+        .query_map(params![bucket_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut reachable: HashSet<Hash> = hashes.iter().filter_map(|hash| Hash::from_hex(hash).ok()).collect();
+    let mut pending: Vec<Hash> = reachable.iter().copied().collect();
+    while let Some(hash) = pending.pop() {
+        if !blob_exists(storage_path, &hash)? {
+            continue;
+        }
+        let linked = match read_chunk_list(storage_path, &hash)? {
+            Some(chunks) => chunks.into_iter().map(|chunk| chunk.hash).collect(),
+            None => delta_header(storage_path, &hash)?.map(|header| header.base).into_iter().collect::<Vec<_>>(),
+        };
+        for hash in linked {
+            if reachable.insert(hash) {
+                pending.push(hash);
+            }
+        }
+    }
+
+    Ok(reachable)
+}
+
+/// Moves a loose blob to the quarantine directory, copying it when the quarantine is on another file system.
+pub(crate) fn move_to_quarantine(blob_path: &Path, quarantine: &Path, hash: &Hash) -> io::Result<()> {
+    fs::create_dir_all(quarantine)?;
+    let target = quarantine.join(hash.to_string());
+    if fs::rename(blob_path, &target).is_err() {
+        fs::copy(blob_path, &target)?;
+        fs::remove_file(blob_path)?;
+    }
+    Ok(())
+}
+
+/// Copies a packed blob to the quarantine directory, the pack itself is left as it is.
+pub(crate) fn quarantine_packed(pack_path: &Path, blob: &PackedBlob, quarantine: &Path) -> io::Result<()> {
+    fs::create_dir_all(quarantine)?;
+    let mut data = Vec::new();
+    open_packed(pack_path, blob)?.read_to_end(&mut data)?;
+    write_atomically(&quarantine.join(blob.hash.to_string()), &data)
+}
+
+// Forgets the references of removed blobs, of the bucket whose storage they were removed from
+fn remove_object_refs(conn: &Connection, removed: &[Hash], bucket_id: Option<&str>) -> Result<(), BucketError> {
+    for hash in removed {
+        conn.execute(
+            "DELETE FROM object_refs WHERE hash = ?1 AND (?2 IS NULL OR bucket_id = ?2)",
+            params![hash.to_string(), bucket_id],
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,300 @@
+use crate::commands::status::BucketStatus;
+use crate::data::bucket::Bucket;
+use crate::data::commit::{commit_id, is_single_line, ChangeKind, Commit, CommittedFile};
+use crate::data::manifest::{join, Manifest, ManifestEntry};
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use blake3::Hash;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+/// A commit of the current bucket as stored in the database.
+pub(crate) struct HistoryEntry {
+    pub id: String,
+    pub manifest_id: String,
+    pub author: String,
+    pub message: String,
+    pub created_at: String,
+    /// When retention rules pruned the files of the commit, its blobs may no longer be stored
+    pub pruned_at: Option<String>,
+    /// The commit without its files, which are loaded with `load_commit_files` when they are needed
+    pub commit: Commit,
+}
+
+/// Options for the `history` command.
+pub(crate) struct HistoryOptions {
+    pub limit: Option<usize>,
+    pub show_files: bool,
+}
+
+pub(crate) fn execute(options: &HistoryOptions) -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let conn = get_db_conn()?;
+
+    let entries = load_history(&conn, &bucket, options.limit)?;
+    if entries.is_empty() {
+        println!("No commits found in bucket {}.", bucket.name);
+        return Ok(());
+    }
+
+    for entry in entries.iter() {
+        let status = BucketStatus::from_changes(&load_commit_changes(&conn, &entry.id)?);
+        let changed = status.len();
+
+        println!("commit {}", entry.id);
+        println!("Author:  {}", entry.author);
+        println!("Date:    {}", entry.created_at);
+        println!("Changed: {} file(s)", changed);
+        if let Some(pruned_at) = entry.pruned_at.as_deref() {
+            println!("Pruned:  {}", pruned_at);
+        }
+        if !entry.message.is_empty() {
+            println!("\n    {}", entry.message);
+        }
+
+        if options.show_files {
+            println!();
+            print_changes(&status);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+/// Prints the changed files of a commit, one line per file.
+pub(crate) fn print_changes(status: &BucketStatus) {
+    for file in status.added.iter() {
+        println!("    added:    {}", file);
+    }
+    for file in status.modified.iter() {
+        println!("    modified: {}", file);
+    }
+    for file in status.deleted.iter() {
+        println!("    deleted:  {}", file);
+    }
+    for (from, to) in status.renamed.iter() {
+        println!("    renamed:  {} -> {}", from, to);
+    }
+}
+
+/// Loads the commits of a bucket without their files, newest commit first.
+///
+/// The history is found by following the parent of each commit, starting at the head of the bucket, and
+/// stops after `limit` commits. A commit that is reached twice means the parents form a cycle, which is
+/// reported as damaged data instead of being followed forever.
+pub(crate) fn load_history(conn: &Connection, bucket: &Bucket, limit: Option<usize>) -> Result<Vec<HistoryEntry>, BucketError> {
+    let mut next: Option<String> = conn
+        .query_row(
+            "SELECT head_commit_id FROM buckets WHERE id = ?1",
+            params![bucket.id.to_string().to_uppercase()],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+
+    let mut stmt = conn.prepare(
+        "SELECT id, parent_id, message, created_at, author, manifest_id, pruned_at
+         FROM commits
+         WHERE bucket_id = ?1 AND id = ?2",
+    )?;
+
+    let mut entries = Vec::new();
+    let mut visited = HashSet::new();
+    while let Some(id) = next.take() {
+        if limit.is_some_and(|limit| entries.len() >= limit) {
+            break;
+        }
+        if !visited.insert(id.clone()) {
+            return Err(BucketError::from(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Commit {} of bucket {} is its own ancestor, the history has a cycle.", id, bucket.name),
+            )));
+        }
+
+        let mut rows = stmt.query(params![bucket.id.to_string().to_uppercase(), id])?;
+        let row = match rows.next()? {
+            Some(row) => row,
+            None => {
+                return Err(BucketError::from(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("Commit {} of bucket {} is missing from the database.", id, bucket.name),
+                )))
+            }
+        };
+
+        let entry = history_entry(bucket, row)?;
+        next = entry.commit.parent.clone();
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+impl HistoryEntry {
+    /// Checks that the id of the commit still matches the content it was computed from, `manifest` is the
+    /// manifest of the commit as loaded from the database.
+    pub(crate) fn is_intact(&self, manifest: &Manifest) -> bool {
+        let fields = [Some(self.manifest_id.as_str()), self.commit.parent.as_deref(), Some(&self.author), Some(&self.created_at)];
+        if !fields.into_iter().flatten().all(is_single_line) {
+            return false;
+        }
+        let id = commit_id(
+            &self.manifest_id,
+            self.commit.parent.as_deref(),
+            &self.author,
+            &self.created_at,
+            &self.message,
+        );
+        id.to_string() == self.id && manifest.id().to_string() == self.manifest_id
+    }
+}
+
+/// Finds a commit of a bucket by its id or by an unambiguous prefix of its id.
+pub(crate) fn find_commit(conn: &Connection, bucket: &Bucket, id_prefix: &str) -> Result<HistoryEntry, BucketError> {
+    if id_prefix.is_empty() || !id_prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(BucketError::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Commit id {} is not a hexadecimal commit id or prefix.", id_prefix),
+        )));
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, parent_id, message, created_at, author, manifest_id, pruned_at
+         FROM commits
+         WHERE bucket_id = ?1 AND substr(id, 1, length(?2)) = ?2",
+    )?;
+
+    let mut rows = stmt.query(params![
+        bucket.id.to_string().to_uppercase(),
+        id_prefix.to_lowercase()
+    ])?;
+
+    let entry = match rows.next()? {
+        Some(row) => history_entry(bucket, row)?,
+        None => {
+            return Err(BucketError::from(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Commit {} not found in bucket {}.", id_prefix, bucket.name),
+            )))
+        }
+    };
+
+    if rows.next()?.is_some() {
+        return Err(BucketError::from(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Commit id {} is ambiguous.", id_prefix),
+        )));
+    }
+
+    Ok(entry)
+}
+
+/// Builds a history entry from a row with the columns `id, parent_id, message, created_at, author, manifest_id,
+/// pruned_at`.
+fn history_entry(bucket: &Bucket, row: &Row) -> Result<HistoryEntry, BucketError> {
+    let id: String = row.get(0)?;
+    let created_at: Option<String> = row.get(3)?;
+
+    Ok(HistoryEntry {
+        manifest_id: row.get(5)?,
+        author: row.get(4)?,
+        message: row.get(2)?,
+        created_at: created_at.clone().unwrap_or_default(),
+        pruned_at: row.get(6)?,
+        commit: Commit {
+            id: Some(id.clone()),
+            parent: row.get(1)?,
+            bucket: bucket.name.clone(),
+            files: Vec::new(),
+            timestamp: created_at.unwrap_or_default(),
+        },
+        id,
+    })
+}
+
+/// Loads the files of a commit, which is a snapshot of the bucket at the time of the commit.
+pub(crate) fn load_commit_files(conn: &Connection, commit_id: &str) -> Result<Vec<CommittedFile>, BucketError> {
+    let manifest = load_manifest(conn, commit_id)?;
+    Ok(manifest
+        .entries
+        .into_iter()
+        .map(|entry| CommittedFile {
+            id: Default::default(),
+            name: entry.path,
+            hash: entry.hash,
+            change: ChangeKind::Unchanged,
+        })
+        .collect())
+}
+
+/// Loads the manifest of a commit with the path, hash, size and mode of every file in the snapshot.
+/// Loads the permission bits of every file in the snapshot of a commit, by path.
+pub(crate) fn load_file_modes(conn: &Connection, commit_id: &str) -> Result<HashMap<String, u32>, BucketError> {
+    Ok(load_manifest(conn, commit_id)?
+        .entries
+        .into_iter()
+        .map(|entry| (entry.path, entry.mode))
+        .collect())
+}
+
+pub(crate) fn load_manifest(conn: &Connection, commit_id: &str) -> Result<Manifest, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT mt.directory, te.name, te.hash, te.size, te.mode
+         FROM commits c
+         JOIN manifest_trees mt ON mt.manifest_id = c.manifest_id
+         JOIN tree_entries te ON te.tree_id = mt.tree_id
+         WHERE c.id = ?1",
+    )?;
+
+    let mut entries = Vec::new();
+    let mut rows = stmt.query(params![commit_id])?;
+    while let Some(row) = rows.next()? {
+        let directory: String = row.get(0)?;
+        let name: String = row.get(1)?;
+        let hex_string: String = row.get(2)?;
+        let size: i64 = row.get(3)?;
+
+        entries.push(ManifestEntry {
+            path: join(&directory, &name),
+            hash: Hash::from_hex(&hex_string).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+            })?,
+            size: size as u64,
+            mode: row.get(4)?,
+        });
+    }
+
+    Ok(Manifest::new(entries))
+}
+
+/// Loads the files that changed in a commit with their change kind, including the files it deleted.
+pub(crate) fn load_commit_changes(conn: &Connection, commit_id: &str) -> Result<Vec<CommittedFile>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, file_path, hash, change_kind, renamed_from
+         FROM files
+         WHERE commit_id = ?1
+         ORDER BY file_path",
+    )?;
+
+    let mut files = Vec::new();
+    let mut rows = stmt.query(params![commit_id])?;
+    while let Some(row) = rows.next()? {
+        let uuid_string: String = row.get(0)?;
+        let hex_string: String = row.get(2)?;
+        let change_kind: String = row.get(3)?;
+
+        files.push(CommittedFile {
+            id: uuid::Uuid::parse_str(&uuid_string).unwrap_or_default(),
+            name: row.get(1)?,
+            hash: Hash::from_hex(&hex_string).map_err(|e| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string())
+            })?,
+            change: ChangeKind::from_columns(&change_kind, row.get(4)?)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?,
+        });
+    }
+
+    Ok(files)
+}
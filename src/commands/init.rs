@@ -1,12 +1,14 @@
 use crate::utils::checks;
-use crate::utils::config::create_default_config;
+use crate::utils::config::{create_config, RepositoryConfig, StorageMode};
 use crate::utils::errors::BucketError;
+use crate::utils::config::open_db;
+use crate::utils::migrations;
+use crate::utils::storage::objects_path;
 use crate::utils::errors::BucketError::BucketAlreadyExists;
-use rusqlite::Connection;
 use std::path::Path;
 use std::{env, fs};
 
-pub fn execute(repo_name: &String) -> Result<(), BucketError> {
+pub fn execute(repo_name: &String, storage: StorageMode) -> Result<(), BucketError> {
     println!("Initialising bucket repository");
 
     let current_path = match env::current_dir() {
@@ -17,11 +19,8 @@ pub fn execute(repo_name: &String) -> Result<(), BucketError> {
         }
     };
 
-    match checks::find_bucket_repo(current_path.as_path()) {
-        Some(_found_path) => {
-            return Err(BucketError::InBucketRepo);
-        }
-        _ => {}
+    if let Some(_found_path) = checks::find_bucket_repo(current_path.as_path()) {
+        return Err(BucketError::InBucketRepo);
     }
 
     // Check if directory with the same name exists
@@ -35,7 +34,16 @@ pub fn execute(repo_name: &String) -> Result<(), BucketError> {
     fs::create_dir_all(&init_dir_path)?;
 
     // Create the buckets.conf file
-    create_default_config(init_dir_path.as_path());
+    let config = RepositoryConfig {
+        storage,
+        ..Default::default()
+    };
+    create_config(init_dir_path.as_path(), &config);
+
+    // Create the object store shared by all buckets
+    if storage == StorageMode::Repository {
+        fs::create_dir_all(objects_path(&repo_path))?;
+    }
 
     // Create the database
     create_database(init_dir_path.as_path())?;
@@ -46,92 +54,13 @@ pub fn execute(repo_name: &String) -> Result<(), BucketError> {
     Ok(())
 }
 
-fn create_database(location: &Path) -> Result<(), rusqlite::Error> {
+/// Creates the database of a new repository with the current schema.
+fn create_database(location: &Path) -> Result<(), BucketError> {
     let db_path = location.join("buckets.db");
-    let conn = Connection::open(db_path)?;
-
-    conn.execute(
-        "CREATE TABLE buckets (
-            id CHAR(36) PRIMARY KEY,
-            name TEXT NOT NULL,
-            path TEXT NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE commits (
-            id CHAR(36) PRIMARY KEY,
-            bucket_id INTEGER NOT NULL,
-            message TEXT NOT NULL,
-            created_at TEXT,
-            FOREIGN KEY (bucket_id) REFERENCES buckets (id)
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TRIGGER set_timestamp_after_insert
-         AFTER INSERT ON commits
-         BEGIN
-             UPDATE commits SET created_at = CURRENT_TIMESTAMP WHERE rowid = NEW.rowid;
-         END;",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE files (
-            id CHAR(36) PRIMARY KEY,
-            commit_id INTEGER NOT NULL,
-            file_path TEXT NOT NULL,
-            hash TEXT NOT NULL,
-            FOREIGN KEY (commit_id) REFERENCES commits (id),
-            UNIQUE (commit_id, file_path, hash)
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TRIGGER AutoGenBucketsGUID
-             AFTER INSERT ON buckets
-             FOR EACH ROW
-             WHEN (NEW.id IS NULL)
-             BEGIN
-               UPDATE buckets SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
-                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
-                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
-                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
-             END;",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TRIGGER AutoGenCommitsGUID
-             AFTER INSERT ON commits
-             FOR EACH ROW
-             WHEN (NEW.id IS NULL)
-             BEGIN
-               UPDATE commits SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
-                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
-                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
-                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
-             END;",
-        [],
-    )?;
+    let mut conn = open_db(&db_path)?;
+    let repo_root = location.parent().unwrap_or(location);
 
-    conn.execute(
-        "CREATE TRIGGER AutoGenFilesGUID
-             AFTER INSERT ON files
-             FOR EACH ROW
-             WHEN (NEW.id IS NULL)
-             BEGIN
-               UPDATE files SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
-                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
-                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
-                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
-             END;",
-        [],
-    )?;
+    migrations::migrate(&mut conn, repo_root)?;
 
     Ok(())
 }
@@ -139,6 +68,7 @@ fn create_database(location: &Path) -> Result<(), rusqlite::Error> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rusqlite::Connection;
     use std::io::Error;
     use tempfile::tempdir;
     use uuid::Uuid;
@@ -148,22 +78,20 @@ mod tests {
         let temp_dir = tempdir()?;
         let db_path = temp_dir.path().join("buckets.db");
 
-        create_database(&temp_dir.path()).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+        create_database(temp_dir.path()).map_err(|e| {
+            std::io::Error::other(
                 format!("Error creating database: {}", e),
             )
         })?;
 
         let conn = Connection::open(&db_path).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error opening database: {}", e),
             )
         })?;
 
         // Verify that tables exist
-        let tables = ["buckets", "commits", "files"];
+        let tables = ["buckets", "commits", "files", "stashes", "stash_files", "finalized_versions", "expectations", "links", "inputs", "manifests", "manifest_trees", "trees", "tree_entries"];
         for table in tables.iter() {
             let mut stmt = conn
                 .prepare(&format!(
@@ -171,15 +99,13 @@ mod tests {
                     table
                 ))
                 .map_err(|e| {
-                    std::io::Error::new(
-                        std::io::ErrorKind::Other,
+                    std::io::Error::other(
                         format!("Error preparing statement: {}", e),
                     )
                 })?;
 
             let mut rows = stmt.query([]).map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                std::io::Error::other(
                     format!("Error querying statement: {}", e),
                 )
             })?;
@@ -195,15 +121,13 @@ mod tests {
         let temp_dir = tempdir()?;
         let db_path = temp_dir.path().join("buckets.db");
 
-        create_database(&temp_dir.path()).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+        create_database(temp_dir.path()).map_err(|e| {
+            std::io::Error::other(
                 format!("Error creating database: {}", e),
             )
         })?;
         let conn = rusqlite::Connection::open(db_path).map_err(|e| {
-            std::io::Error::new(
-                std::io::ErrorKind::Other,
+            std::io::Error::other(
                 format!("Error opening database: {}", e),
             )
         })?;
@@ -218,8 +142,7 @@ mod tests {
         let mut stmt = conn
             .prepare("SELECT id, name, path FROM buckets")
             .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                std::io::Error::other(
                     format!("Error preparing statement: {}", e),
                 )
             })?;
@@ -232,8 +155,7 @@ mod tests {
                 })
             })
             .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                std::io::Error::other(
                     format!("Error querying statement: {}", e),
                 )
             })?;
@@ -262,8 +184,7 @@ mod tests {
             [],
         )
             .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                std::io::Error::other(
                     format!("Error inserting into database: {}", e),
                 )
             })?;
@@ -283,8 +204,7 @@ mod tests {
             [],
         )
             .map_err(|e| {
-                std::io::Error::new(
-                    std::io::ErrorKind::Other,
+                std::io::Error::other(
                     format!("Error inserting into database: {}", e),
                 )
             })?;
@@ -0,0 +1,146 @@
+use crate::commands::list::{load_buckets, BucketRecord};
+use crate::data::bucket::{read_bucket_info, Bucket};
+use crate::data::link::{creates_cycle, Link};
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::env;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+/// The actions of the `link`, `unlink` and `links` commands.
+pub(crate) enum LinkAction {
+    Link { from: String, to: String },
+    Unlink { from: String, to: String },
+    Show,
+}
+
+pub(crate) fn execute(action: &LinkAction) -> Result<(), BucketError> {
+    let current_path = env::current_dir()?;
+    let conn = get_db_conn()?;
+    let buckets = load_buckets(&conn)?;
+
+    match action {
+        LinkAction::Link { from, to } => {
+            let from = resolve_bucket(&buckets, &current_path, from)?;
+            let to = resolve_bucket(&buckets, &current_path, to)?;
+
+            let links = load_links(&conn)?;
+            if links.iter().any(|l| l.from == from.id && l.to == to.id) {
+                println!("Bucket {} is already linked to {}", from.name, to.name);
+                return Ok(());
+            }
+            if creates_cycle(&links, &from.id, &to.id) {
+                return Err(BucketError::from(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Linking {} to {} would create a cycle in the workflow.", from.name, to.name),
+                )));
+            }
+
+            conn.execute(
+                "INSERT INTO links (from_bucket_id, to_bucket_id) VALUES (?1, ?2)",
+                params![from.id, to.id],
+            )?;
+            println!("Linked {} to {}", from.name, to.name);
+        }
+        LinkAction::Unlink { from, to } => {
+            let from = resolve_bucket(&buckets, &current_path, from)?;
+            let to = resolve_bucket(&buckets, &current_path, to)?;
+
+            let removed = conn.execute(
+                "DELETE FROM links WHERE from_bucket_id = ?1 AND to_bucket_id = ?2",
+                params![from.id, to.id],
+            )?;
+            if removed == 0 {
+                return Err(BucketError::from(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("Bucket {} is not linked to {}.", from.name, to.name),
+                )));
+            }
+            println!("Unlinked {} from {}", from.name, to.name);
+        }
+        LinkAction::Show => {
+            let bucket = Bucket::from_meta_data(current_path)?;
+            let id = bucket.id.to_string().to_uppercase();
+            let links = load_links(&conn)?;
+            let name_of = |id: &str| {
+                buckets
+                    .iter()
+                    .find(|b| b.id == id)
+                    .map(|b| b.name.clone())
+                    .unwrap_or_else(|| id.to_string())
+            };
+
+            println!("Upstream of {}:", bucket.name);
+            for link in links.iter().filter(|l| l.to == id) {
+                match received_version(&conn, &id, &link.from)? {
+                    Some(version) => println!("    {} (received version {})", name_of(&link.from), version),
+                    None => println!("    {}", name_of(&link.from)),
+                }
+            }
+            println!("Downstream of {}:", bucket.name);
+            for link in links.iter().filter(|l| l.from == id) {
+                println!("    {}", name_of(&link.to));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Loads every link between buckets in the repository.
+pub(crate) fn load_links(conn: &Connection) -> Result<Vec<Link>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT from_bucket_id, to_bucket_id
+         FROM links
+         ORDER BY created_at, rowid",
+    )?;
+
+    let links = stmt
+        .query_map([], |row| {
+            Ok(Link {
+                from: row.get(0)?,
+                to: row.get(1)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(links)
+}
+
+/// The finalized version of an upstream bucket whose output was last delivered to a bucket.
+pub(crate) fn received_version(conn: &Connection, bucket_id: &str, upstream_id: &str) -> Result<Option<String>, BucketError> {
+    let version = conn
+        .query_row(
+            "SELECT version FROM inputs WHERE bucket_id = ?1 AND upstream_bucket_id = ?2 LIMIT 1",
+            params![bucket_id, upstream_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(version)
+}
+
+/// Finds a bucket by its directory, relative to the current directory, or by its name.
+pub(crate) fn resolve_bucket<'a>(
+    buckets: &'a [BucketRecord],
+    current_path: &Path,
+    bucket: &str,
+) -> Result<&'a BucketRecord, BucketError> {
+    let bucket_path = current_path.join(bucket);
+    let found = if bucket_path.join(".b").join("info").is_file() {
+        let info = read_bucket_info(&bucket_path)?;
+        buckets
+            .iter()
+            .find(|b| Uuid::parse_str(&b.id).map(|id| id == info.id).unwrap_or(false))
+    } else {
+        buckets.iter().find(|b| b.name == bucket)
+    };
+
+    found.ok_or_else(|| {
+        BucketError::from(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Unknown bucket {}.", bucket),
+        ))
+    })
+}
@@ -0,0 +1,166 @@
+use crate::data::bucket::{read_bucket_info, Bucket};
+use crate::utils::checks::find_bucket_repo;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use rusqlite::Connection;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// A bucket as registered in the `buckets` table of the repository database.
+pub(crate) struct BucketRecord {
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+    pub last_commit: Option<String>,
+}
+
+/// Whether the `.b/info` file on disk agrees with the database row of a bucket.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DiskState {
+    Ok,
+    Missing,
+    Unreadable,
+    IdMismatch,
+    NameMismatch,
+}
+
+impl Display for DiskState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiskState::Ok => write!(f, "ok"),
+            DiskState::Missing => write!(f, "missing"),
+            DiskState::Unreadable => write!(f, "unreadable info"),
+            DiskState::IdMismatch => write!(f, "id mismatch"),
+            DiskState::NameMismatch => write!(f, "name mismatch"),
+        }
+    }
+}
+
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let current_path = env::current_dir()?;
+    let repo_root = match find_bucket_repo(current_path.as_path()) {
+        Some(path) => path.parent().unwrap().to_path_buf(),
+        None => return Err(BucketError::NotInBucketRepo),
+    };
+
+    let conn = get_db_conn()?;
+    let records = load_buckets(&conn)?;
+    if records.is_empty() {
+        println!("No buckets found in repository.");
+        return Ok(());
+    }
+
+    let name_width = records.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+    let path_width = records
+        .iter()
+        .map(|r| r.path.to_string_lossy().len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+
+    println!(
+        "{:<name_width$}  {:<path_width$}  {:<36}  {:<19}  STATE",
+        "NAME", "PATH", "ID", "LAST COMMIT"
+    );
+    for record in records.iter() {
+        let state = disk_state(&repo_root, record);
+        println!(
+            "{:<name_width$}  {:<path_width$}  {:<36}  {:<19}  {}",
+            record.name,
+            record.path.to_string_lossy(),
+            record.id,
+            record.last_commit.as_deref().unwrap_or("-"),
+            state
+        );
+    }
+
+    Ok(())
+}
+
+/// Loads every bucket of the repository with the time of its last commit, ordered by name.
+pub(crate) fn load_buckets(conn: &Connection) -> Result<Vec<BucketRecord>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT b.id, b.name, b.path, MAX(c.created_at)
+         FROM buckets b
+         LEFT JOIN commits c ON c.bucket_id = b.id
+         GROUP BY b.id
+         ORDER BY b.name",
+    )?;
+
+    let records = stmt
+        .query_map([], |row| {
+            let path: String = row.get(2)?;
+            Ok(BucketRecord {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                path: PathBuf::from(path),
+                last_commit: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(records)
+}
+
+/// Compares the `.b/info` file of a bucket against its database row.
+pub(crate) fn disk_state(repo_root: &Path, record: &BucketRecord) -> DiskState {
+    let bucket_path = repo_root.join(&record.path);
+    if !bucket_path.join(".b").join("info").is_file() {
+        return DiskState::Missing;
+    }
+
+    let bucket: Bucket = match read_bucket_info(&bucket_path) {
+        Ok(bucket) => bucket,
+        Err(_) => return DiskState::Unreadable,
+    };
+
+    match Uuid::parse_str(&record.id) {
+        Ok(id) if id == bucket.id => {}
+        _ => return DiskState::IdMismatch,
+    }
+
+    if bucket.name != record.name {
+        return DiskState::NameMismatch;
+    }
+
+    DiskState::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+    use tempfile::tempdir;
+
+    fn record(id: Uuid, name: &str) -> BucketRecord {
+        BucketRecord {
+            id: id.to_string().to_uppercase(),
+            name: name.to_string(),
+            path: PathBuf::from(name),
+            last_commit: None,
+        }
+    }
+
+    #[test]
+    fn test_disk_state() {
+        let temp_dir = tempdir().unwrap();
+        let repo_root = temp_dir.path();
+        let id = Uuid::new_v4();
+
+        assert_eq!(disk_state(repo_root, &record(id, "test_bucket")), DiskState::Missing);
+
+        let bucket_path = repo_root.join("test_bucket");
+        create_dir_all(bucket_path.join(".b")).unwrap();
+        let bucket = Bucket::default(id, &"test_bucket".to_string(), &bucket_path);
+        bucket.write_bucket_info(&bucket_path);
+
+        assert_eq!(disk_state(repo_root, &record(id, "test_bucket")), DiskState::Ok);
+        assert_eq!(disk_state(repo_root, &record(Uuid::new_v4(), "test_bucket")), DiskState::IdMismatch);
+
+        let mut renamed = record(id, "test_bucket");
+        renamed.name = "other_bucket".to_string();
+        assert_eq!(disk_state(repo_root, &renamed), DiskState::NameMismatch);
+    }
+}
@@ -0,0 +1,21 @@
+use crate::utils::checks::find_repo_root;
+use crate::utils::errors::BucketError;
+use crate::utils::migrations::{migrate_repository, SCHEMA_VERSION};
+use std::env;
+
+/// Brings the database of the current repository up to the schema of this version of buckets.
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+
+    let applied = migrate_repository(&repo_root)?;
+    if applied.is_empty() {
+        println!("Repository database is up to date at schema version {}.", SCHEMA_VERSION);
+        return Ok(());
+    }
+
+    for (version, description) in applied {
+        println!("Applied migration {}: {}", version, description);
+    }
+
+    Ok(())
+}
@@ -1,5 +1,23 @@
+pub(crate) mod check;
 pub(crate) mod commit;
 pub(crate) mod create;
+pub(crate) mod expect;
+pub(crate) mod finalize;
+pub(crate) mod fsck;
+pub(crate) mod gc;
+pub(crate) mod history;
 pub mod init;
+pub(crate) mod link;
+pub(crate) mod list;
+pub(crate) mod migrate;
+pub(crate) mod pack;
+pub(crate) mod prune;
+pub(crate) mod revert;
+pub(crate) mod rollback;
+pub(crate) mod show;
+pub(crate) mod stash;
+pub(crate) mod stats;
+pub(crate) mod storage;
 pub mod version;
+pub(crate) mod versions;
 pub(crate) mod status;
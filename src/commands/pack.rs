@@ -0,0 +1,57 @@
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{PackingConfig, RepositoryConfig};
+use crate::utils::errors::BucketError;
+use crate::utils::packs::{list_loose_blobs, pack_storage};
+use crate::utils::storage::{storage_path, storage_paths};
+use std::env;
+use std::path::Path;
+
+/// Gathers the loose blobs of the repository into packfiles, the storage of every bucket is packed or the
+/// object store shared by all buckets.
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+
+    let mut packed = 0;
+    for storage_path in storage_paths(&repo_root)? {
+        let summary = pack_storage(&storage_path, config.packing.max_blob_size)?;
+        if let Some(pack_path) = summary.pack_path {
+            let pack_path = pack_path.strip_prefix(&repo_root).unwrap_or(&pack_path).display();
+            if summary.blobs > 0 {
+                println!("Packed {} blob(s) of {} bytes into {}", summary.blobs, summary.bytes, pack_path);
+            }
+            if summary.merged_packs > 0 {
+                println!("Merged {} packfile(s) into {}", summary.merged_packs, pack_path);
+            }
+            packed += summary.blobs + summary.merged_packs;
+        }
+    }
+
+    if packed == 0 {
+        println!("No loose blobs to pack.");
+    }
+
+    Ok(())
+}
+
+/// Packs the storage of a bucket when it holds more loose blobs than `packing.auto_pack`.
+///
+/// The commit that triggers this is already stored, so a failure is reported without failing the command.
+pub(crate) fn auto_pack(bucket_path: &Path, packing: &PackingConfig) {
+    if packing.auto_pack == 0 {
+        return;
+    }
+
+    let result = storage_path(bucket_path).and_then(|storage_path| {
+        if list_loose_blobs(&storage_path)?.len() < packing.auto_pack {
+            return Ok(None);
+        }
+        pack_storage(&storage_path, packing.max_blob_size).map(Some)
+    });
+
+    match result {
+        Ok(Some(summary)) if summary.blobs > 0 => println!("Packed {} loose blob(s) into a packfile.", summary.blobs),
+        Ok(_) => {}
+        Err(e) => eprintln!("Can not pack storage: {}", e),
+    }
+}
@@ -0,0 +1,181 @@
+use crate::commands::gc::reachable_blobs;
+use crate::commands::list::load_buckets;
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{get_db_conn, RepositoryConfig, RetentionRule};
+use crate::utils::errors::BucketError;
+use crate::utils::storage::storage_path;
+use blake3::Hash;
+use rusqlite::{params, Connection};
+use std::env;
+use std::path::Path;
+
+/// A commit of a bucket as far as retention rules are concerned.
+struct RetainedCommit {
+    id: String,
+    created_at: String,
+    pruned: bool,
+    finalized: bool,
+    head: bool,
+}
+
+/// Prunes the commits of every bucket that its retention rule no longer keeps.
+///
+/// A pruned commit keeps its row, its manifest and its changed files so the history still shows it, but it no
+/// longer references its blobs. The blobs that only pruned commits referenced are removed by `bucket gc`.
+pub(crate) fn execute(dry_run: bool) -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+    let mut conn = get_db_conn()?;
+
+    let now = chrono::Utc::now();
+    let mut pruned = 0;
+    for record in load_buckets(&conn)? {
+        let rule = match config.retention_for(&record.name) {
+            Some(rule) => rule,
+            None => continue,
+        };
+
+        let commits = load_commits(&conn, &record.id)?;
+        let expired: Vec<&RetainedCommit> = expired_commits(&commits, rule, now).into_iter().filter(|c| !c.pruned).collect();
+        if expired.is_empty() {
+            continue;
+        }
+        let kept = commits.iter().filter(|c| !c.pruned).count() - expired.len();
+
+        if dry_run {
+            println!("{}: {} commit(s) can be pruned, keeping {}.", record.name, expired.len(), kept);
+            for commit in expired.iter() {
+                println!("    {}  {}", commit.id, commit.created_at);
+            }
+        } else {
+            let pruned_at = now.format("%Y-%m-%d %H:%M:%S%.3f").to_string();
+            let storage_path = storage_path(&repo_root.join(&record.path))?;
+
+            let tx = conn.transaction()?;
+            for commit in expired.iter() {
+                tx.execute("UPDATE commits SET pruned_at = ?1 WHERE id = ?2", params![pruned_at, commit.id])?;
+            }
+            remove_unreachable_refs(&tx, &storage_path, &record.id)?;
+            tx.commit()?;
+
+            println!("{}: pruned {} commit(s), keeping {}.", record.name, expired.len(), kept);
+        }
+        pruned += expired.len();
+    }
+
+    if pruned == 0 {
+        println!("No commits to prune.");
+    } else if !dry_run {
+        println!("Run `bucket gc` to remove the blobs that are no longer referenced.");
+    }
+
+    Ok(())
+}
+
+// Loads the commits of a bucket, newest first
+fn load_commits(conn: &Connection, bucket_id: &str) -> Result<Vec<RetainedCommit>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT c.id, c.created_at, c.pruned_at IS NOT NULL,
+                EXISTS (SELECT 1 FROM finalized_versions fv WHERE fv.commit_id = c.id),
+                c.id IS b.head_commit_id
+         FROM commits c
+         JOIN buckets b ON b.id = c.bucket_id
+         WHERE c.bucket_id = ?1
+         ORDER BY c.created_at DESC, c.rowid DESC",
+    )?;
+    let commits = stmt
+        .query_map(params![bucket_id], |row| {
+            Ok(RetainedCommit {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                pruned: row.get(2)?,
+                finalized: row.get(3)?,
+                head: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(commits)
+}
+
+// The commits, newest first, that a retention rule doesn't keep at the time now
+fn expired_commits<'a>(commits: &'a [RetainedCommit], rule: &RetentionRule, now: chrono::DateTime<chrono::Utc>) -> Vec<&'a RetainedCommit> {
+    if rule.keep_last.is_none() && rule.keep_days.is_none() {
+        return Vec::new();
+    }
+
+    // Commit times are UTC and formatted so they sort as text
+    let cutoff = rule
+        .keep_days
+        .map(|days| (now - chrono::Duration::days(days as i64)).format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+
+    commits
+        .iter()
+        .enumerate()
+        .filter(|(position, commit)| {
+            let recent = rule.keep_last.is_some_and(|keep_last| *position < keep_last);
+            let young = cutoff.as_ref().is_some_and(|cutoff| commit.created_at.as_str() > cutoff.as_str());
+            !(commit.finalized || commit.head || recent || young)
+        })
+        .map(|(_, commit)| commit)
+        .collect()
+}
+
+// Forgets the references of a bucket to blobs that none of its remaining commits and stashes reference
+fn remove_unreachable_refs(conn: &Connection, storage_path: &Path, bucket_id: &str) -> Result<(), BucketError> {
+    let reachable = reachable_blobs(conn, storage_path, Some(bucket_id))?;
+
+    let mut stmt = conn.prepare("SELECT hash FROM object_refs WHERE bucket_id = ?1")?;
+    let referenced = stmt
+        .query_map(params![bucket_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for hash in referenced {
+        if Hash::from_hex(&hash).is_ok_and(|hash| !reachable.contains(&hash)) {
+            conn.execute("DELETE FROM object_refs WHERE hash = ?1 AND bucket_id = ?2", params![hash, bucket_id])?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit(id: &str, created_at: &str) -> RetainedCommit {
+        RetainedCommit {
+            id: id.to_string(),
+            created_at: created_at.to_string(),
+            pruned: false,
+            finalized: false,
+            head: false,
+        }
+    }
+
+    #[test]
+    fn test_expired_commits() {
+        let now = chrono::DateTime::parse_from_rfc3339("2024-06-30T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let mut commits = vec![
+            commit("e", "2024-06-29 12:00:00.000"),
+            commit("d", "2024-06-20 12:00:00.000"),
+            commit("c", "2024-06-10 12:00:00.000"),
+            commit("b", "2024-06-01 12:00:00"),
+            commit("a", "2024-05-01 12:00:00.000"),
+        ];
+        commits[0].head = true;
+        commits[3].finalized = true;
+
+        let ids = |rule: &RetentionRule| expired_commits(&commits, rule, now).iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+        let rule = |keep_last, keep_days| RetentionRule {
+            bucket: "*".to_string(),
+            keep_last,
+            keep_days,
+        };
+
+        assert_eq!(ids(&rule(Some(2), None)), vec!["c", "a"]);
+        assert_eq!(ids(&rule(None, Some(15))), vec!["c", "a"]);
+        assert_eq!(ids(&rule(Some(1), Some(30))), vec!["a"]);
+        assert_eq!(ids(&rule(Some(0), None)), vec!["d", "c", "a"]);
+        assert!(ids(&rule(None, None)).is_empty());
+    }
+}
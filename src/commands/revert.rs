@@ -0,0 +1,200 @@
+use crate::commands::commit::{get_full_bucket_path, hash_file, load_last_commit, set_file_mode};
+use crate::commands::history::load_file_modes;
+use crate::data::bucket::Bucket;
+use crate::data::commit::{Commit, CommittedFile};
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use crate::utils::storage::{restore_blob, storage_path};
+use blake3::Hash;
+use std::collections::HashMap;
+use std::io;
+use std::{env, fs};
+use std::io::{BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// Which files of the bucket the `revert` command restores.
+pub(crate) enum RevertTarget {
+    All,
+    File(String),
+}
+
+pub(crate) fn execute(target: &RevertTarget, force: bool) -> Result<(), BucketError> {
+    let current_path = env::current_dir()?;
+    let bucket = Bucket::from_meta_data(current_path.clone())?;
+    let full_bucket_path = get_full_bucket_path(&bucket);
+
+    let last_commit = match load_last_commit(&bucket)? {
+        Some(commit) if !commit.files.is_empty() => commit,
+        _ => {
+            return Err(BucketError::from(io::Error::new(
+                io::ErrorKind::NotFound,
+                "No commits found in bucket.",
+            )))
+        }
+    };
+
+    let files: Vec<&CommittedFile> = match target {
+        RevertTarget::All => last_commit.files.iter().collect(),
+        RevertTarget::File(file) => {
+            let name = bucket_relative_name(&full_bucket_path, &current_path, file)?;
+            match last_commit.files.iter().find(|f| f.name == name) {
+                Some(committed) => vec![committed],
+                None => {
+                    return Err(BucketError::from(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("File {} not found in last commit.", name),
+                    )))
+                }
+            }
+        }
+    };
+
+    let modes = match last_commit.id.as_deref() {
+        Some(id) => load_file_modes(&get_db_conn()?, id)?,
+        None => HashMap::new(),
+    };
+    restore_files(&full_bucket_path, &files, &modes, &[], &last_commit, force)
+}
+
+/// Restores committed files into the working directory of a bucket.
+///
+/// Files which already match the committed version are left alone and files named in `delete` are
+/// removed. A restored file gets the permission bits recorded for it in `modes`, a file without a recorded
+/// mode keeps the permissions it was restored with. If a file which would be overwritten or removed has local modifications compared to
+/// `last_commit`, the user is asked to confirm first, unless `force` is set.
+pub(crate) fn restore_files(
+    full_bucket_path: &Path,
+    files: &[&CommittedFile],
+    modes: &HashMap<String, u32>,
+    delete: &[String],
+    last_commit: &Commit,
+    force: bool,
+) -> Result<(), BucketError> {
+    let committed: HashMap<&str, &Hash> = last_commit.files.iter().map(|f| (f.name.as_str(), &f.hash)).collect();
+    let mut to_restore = Vec::new();
+    let mut modified = Vec::new();
+    for file in files {
+        let working_path = full_bucket_path.join(&file.name);
+        if !working_path.is_file() {
+            to_restore.push(*file);
+            continue;
+        }
+
+        let working_hash = hash_file(&working_path)?;
+        if working_hash != file.hash {
+            if !is_committed(&committed, &file.name, &working_hash) {
+                modified.push(file.name.as_str());
+            }
+            to_restore.push(*file);
+        }
+    }
+
+    let mut to_delete = Vec::new();
+    for name in delete {
+        let working_path = full_bucket_path.join(name);
+        if working_path.is_file() {
+            if !is_committed(&committed, name, &hash_file(&working_path)?) {
+                modified.push(name.as_str());
+            }
+            to_delete.push(name);
+        }
+    }
+
+    if to_restore.is_empty() && to_delete.is_empty() {
+        println!("Nothing to restore.");
+        return Ok(());
+    }
+
+    if !modified.is_empty() && !force {
+        println!("The following files have local modifications which will be lost:");
+        for name in modified.iter() {
+            println!("    {}", name);
+        }
+        if !confirm("Discard local modifications?")? {
+            println!("Restore cancelled.");
+            return Ok(());
+        }
+    }
+
+    let storage_path = storage_path(full_bucket_path)?;
+    for file in to_restore {
+        let working_path = full_bucket_path.join(&file.name);
+        restore_blob(&storage_path, &file.hash, &working_path)?;
+        if let Some(mode) = modes.get(&file.name) {
+            set_file_mode(&working_path, *mode)?;
+        }
+        println!("Restored {}", file.name);
+    }
+    for name in to_delete {
+        fs::remove_file(full_bucket_path.join(name))?;
+        println!("Deleted {}", name);
+    }
+
+    Ok(())
+}
+
+// A working file can be replaced without asking when its content is safely stored in the last commit
+fn is_committed(committed: &HashMap<&str, &Hash>, name: &str, hash: &Hash) -> bool {
+    committed.get(name) == Some(&hash)
+}
+
+/// Asks a yes/no question on stdin, defaulting to no.
+pub(crate) fn confirm(question: &str) -> io::Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    println!();
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Converts a path given on the command line into the name under which it is stored in a commit,
+/// which is relative to the top of the bucket.
+pub(crate) fn bucket_relative_name(full_bucket_path: &Path, current_path: &Path, file: &str) -> io::Result<String> {
+    let absolute = normalize(&current_path.join(file));
+    match absolute.strip_prefix(normalize(full_bucket_path)) {
+        Ok(relative) if !relative.as_os_str().is_empty() => Ok(relative.to_string_lossy().into_owned()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not inside the bucket.", file),
+        )),
+    }
+}
+
+// Resolves `.` and `..` without touching the file system, the file might not exist anymore
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other.as_os_str()),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_relative_name() {
+        let bucket = Path::new("/repo/bucket");
+
+        assert_eq!(bucket_relative_name(bucket, bucket, "file.txt").unwrap(), "file.txt");
+        assert_eq!(
+            bucket_relative_name(bucket, &bucket.join("subdir"), "file.txt").unwrap(),
+            "subdir/file.txt"
+        );
+        assert_eq!(
+            bucket_relative_name(bucket, &bucket.join("subdir"), "../file.txt").unwrap(),
+            "file.txt"
+        );
+        assert!(bucket_relative_name(bucket, bucket, "../other/file.txt").is_err());
+    }
+}
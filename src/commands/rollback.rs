@@ -0,0 +1,74 @@
+use crate::commands::commit::{get_full_bucket_path, list_files_with_metadata_in_bucket, load_last_commit};
+use crate::commands::history::{find_commit, load_commit_files, load_file_modes};
+use crate::commands::revert::{bucket_relative_name, restore_files};
+use crate::data::bucket::Bucket;
+use crate::data::commit::{Commit, CommittedFile};
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use std::collections::HashSet;
+use std::env;
+use std::io;
+
+/// Which files of the bucket the `rollback` command replaces.
+pub(crate) enum RollbackTarget {
+    All,
+    File(String),
+}
+
+/// Options for the `rollback` command.
+pub(crate) struct RollbackOptions {
+    pub commit_id: String,
+    /// Delete working files which did not exist in the commit, only used when rolling back all files
+    pub delete: bool,
+    pub force: bool,
+}
+
+pub(crate) fn execute(target: &RollbackTarget, options: &RollbackOptions) -> Result<(), BucketError> {
+    let current_path = env::current_dir()?;
+    let bucket = Bucket::from_meta_data(current_path.clone())?;
+    let full_bucket_path = get_full_bucket_path(&bucket);
+    let conn = get_db_conn()?;
+
+    let mut commit = find_commit(&conn, &bucket, &options.commit_id)?;
+    if let Some(pruned_at) = commit.pruned_at.as_deref() {
+        return Err(BucketError::from(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Commit {} was pruned at {}, its files are no longer stored.", commit.id, pruned_at),
+        )));
+    }
+    commit.commit.files = load_commit_files(&conn, &commit.id)?;
+    let last_commit = load_last_commit(&bucket)?.unwrap_or_else(|| Commit::empty(&bucket.name));
+
+    let (files, delete): (Vec<&CommittedFile>, Vec<String>) = match target {
+        RollbackTarget::All => {
+            let delete = if options.delete {
+                let committed: HashSet<&str> = commit.commit.files.iter().map(|f| f.name.as_str()).collect();
+                list_files_with_metadata_in_bucket(&bucket)?
+                    .files
+                    .into_iter()
+                    .filter(|f| !committed.contains(f.name.as_str()))
+                    .map(|f| f.name)
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            (commit.commit.files.iter().collect(), delete)
+        }
+        RollbackTarget::File(file) => {
+            let name = bucket_relative_name(&full_bucket_path, &current_path, file)?;
+            match commit.commit.files.iter().find(|f| f.name == name) {
+                Some(committed) => (vec![committed], Vec::new()),
+                None => {
+                    return Err(BucketError::from(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("File {} not found in commit {}.", name, commit.id),
+                    )))
+                }
+            }
+        }
+    };
+
+    println!("Rolling back to commit {}", commit.id);
+    let modes = load_file_modes(&conn, &commit.id)?;
+    restore_files(&full_bucket_path, &files, &modes, &delete, &last_commit, options.force)
+}
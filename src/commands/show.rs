@@ -0,0 +1,43 @@
+use crate::commands::history::{find_commit, load_commit_changes, load_manifest, print_changes};
+use crate::commands::status::BucketStatus;
+use crate::data::bucket::Bucket;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use std::env;
+
+/// Shows a commit of the current bucket, found by its id or an unambiguous prefix of its id.
+pub(crate) fn execute(id_prefix: &str) -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let conn = get_db_conn()?;
+
+    let entry = find_commit(&conn, &bucket, id_prefix)?;
+    let manifest = load_manifest(&conn, &entry.id)?;
+    if !entry.is_intact(&manifest) {
+        eprintln!("Warning: commit {} does not match its content, it may have been tampered with.", entry.id);
+    }
+
+    println!("commit {}", entry.id);
+    if let Some(parent) = entry.commit.parent.as_deref() {
+        println!("Parent:  {}", parent);
+    }
+    println!("Author:  {}", entry.author);
+    println!("Date:    {}", entry.created_at);
+    if let Some(pruned_at) = entry.pruned_at.as_deref() {
+        println!("Pruned:  {}", pruned_at);
+    }
+    if !entry.message.is_empty() {
+        println!("\n    {}", entry.message);
+    }
+
+    let status = BucketStatus::from_changes(&load_commit_changes(&conn, &entry.id)?);
+    println!("\nChanged: {} file(s)", status.len());
+    print_changes(&status);
+
+    let width = manifest.entries.iter().map(|e| e.size.to_string().len()).max().unwrap_or(0);
+    println!("\nFiles:   {} file(s)", manifest.entries.len());
+    for entry in manifest.entries.iter() {
+        println!("    {:o}  {:>width$}  {}", entry.mode, entry.size, entry.path);
+    }
+
+    Ok(())
+}
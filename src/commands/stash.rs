@@ -0,0 +1,310 @@
+use crate::commands::commit::{get_full_bucket_path, list_files_with_metadata_in_bucket, load_last_commit, store_chunk_list};
+use crate::commands::history::{load_commit_files, load_file_modes};
+use crate::commands::revert::restore_files;
+use crate::commands::status::BucketStatus;
+use crate::data::bucket::Bucket;
+use crate::data::commit::{ChangeKind, Commit, CommittedFile};
+use crate::utils::config::{get_db_conn, RepositoryConfig};
+use crate::utils::errors::BucketError;
+use crate::utils::storage::{add_object_ref, storage_path, store_blob, StoreOptions};
+use blake3::Hash;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io;
+use uuid::Uuid;
+
+/// The actions of the `stash` command.
+pub(crate) enum StashAction {
+    Save(Option<String>),
+    Restore(Option<String>),
+    List,
+    Drop(String),
+}
+
+/// A saved working state of a bucket.
+pub(crate) struct Stash {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    /// The commit the stash was saved on, `None` when the bucket had no commits yet
+    pub commit_id: Option<String>,
+}
+
+pub(crate) fn execute(action: &StashAction) -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let mut conn = get_db_conn()?;
+
+    match action {
+        StashAction::Save(name) => save(&mut conn, &bucket, name.as_deref()),
+        StashAction::Restore(name) => restore(&mut conn, &bucket, name.as_deref()),
+        StashAction::List => list(&conn, &bucket),
+        StashAction::Drop(name) => {
+            let stash = find_stash(&conn, &bucket, Some(name))?;
+            drop_stash(&mut conn, &stash.id)?;
+            println!("Dropped stash {}", stash.name);
+            Ok(())
+        }
+    }
+}
+
+/// Saves the uncommitted state of the bucket and resets the working directory to the last commit.
+///
+/// The stash is recorded in a single transaction, which commits before the working directory is reset, so
+/// the working files are only touched once the stash is complete.
+fn save(conn: &mut Connection, bucket: &Bucket, name: Option<&str>) -> Result<(), BucketError> {
+    let full_bucket_path = get_full_bucket_path(bucket);
+    let last_commit = last_commit_or_empty(bucket)?;
+    let current = list_files_with_metadata_in_bucket(bucket)?;
+
+    if BucketStatus::from_commits(&current, &last_commit).is_clean() {
+        println!("No local changes to stash.");
+        return Ok(());
+    }
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => next_stash_name(conn, bucket)?,
+    };
+
+    // Store the content of every working file before anything is removed from the working directory
+    let storage_path = storage_path(&full_bucket_path)?;
+    let config = RepositoryConfig::from_file(full_bucket_path.clone())?;
+    let mut chunk_lists = Vec::new();
+    for file in current.files.iter() {
+        let options = StoreOptions {
+            chunking: &config.chunking,
+            codec: config.codec_for(&file.name),
+            delta_base: None,
+            max_delta_depth: config.delta.max_chain_depth,
+            max_delta_size: config.delta.max_file_size,
+        };
+        if let Some(chunks) = store_blob(&storage_path, &full_bucket_path.join(&file.name), &file.hash, &options)? {
+            chunk_lists.push((file.hash, chunks));
+        }
+    }
+
+    let tx = conn.transaction()?;
+    let stash_id = Uuid::new_v4().to_string().to_uppercase();
+    tx.execute(
+        "INSERT INTO stashes (id, bucket_id, name, commit_id) VALUES (?1, ?2, ?3, ?4)",
+        params![stash_id, bucket.id.to_string().to_uppercase(), name, last_commit.id],
+    )
+    .map_err(|e| match e {
+        rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => {
+            BucketError::from(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("Stash {} already exists.", name),
+            ))
+        }
+        e => BucketError::from(e),
+    })?;
+    for file in current.files.iter() {
+        tx.execute(
+            "INSERT INTO stash_files (stash_id, file_path, hash) VALUES (?1, ?2, ?3)",
+            params![stash_id, file.name, file.hash.to_string()],
+        )?;
+        add_object_ref(&tx, &bucket.id.to_string().to_uppercase(), &file.hash)?;
+    }
+    for (hash, chunks) in chunk_lists.iter() {
+        store_chunk_list(&tx, &bucket.id.to_string().to_uppercase(), hash, chunks)?;
+    }
+    tx.commit()?;
+
+    // Reset the working directory to the last commit
+    let committed: HashSet<&str> = last_commit.files.iter().map(|f| f.name.as_str()).collect();
+    let delete: Vec<String> = current
+        .files
+        .iter()
+        .filter(|f| !committed.contains(f.name.as_str()))
+        .map(|f| f.name.clone())
+        .collect();
+    let files: Vec<&CommittedFile> = last_commit.files.iter().collect();
+    let modes = match last_commit.id.as_deref() {
+        Some(id) => load_file_modes(conn, id)?,
+        None => HashMap::new(),
+    };
+    restore_files(&full_bucket_path, &files, &modes, &delete, &last_commit, true)?;
+
+    println!("Saved local changes as stash {}", name);
+    Ok(())
+}
+
+/// Reapplies a stash to the working directory and drops it.
+///
+/// Only the changes the stash saved, compared to the commit it was saved on, are applied, so files committed
+/// since then are kept. A file changed by the stash and by a later commit is a conflict and nothing is restored.
+fn restore(conn: &mut Connection, bucket: &Bucket, name: Option<&str>) -> Result<(), BucketError> {
+    let stash = find_stash(conn, bucket, name)?;
+
+    let full_bucket_path = get_full_bucket_path(bucket);
+    let last_commit = last_commit_or_empty(bucket)?;
+    let current = list_files_with_metadata_in_bucket(bucket)?;
+
+    if !BucketStatus::from_commits(&current, &last_commit).is_clean() {
+        return Err(BucketError::UncommittedChanges);
+    }
+
+    let stash_files = load_stash_files(conn, &stash.id)?;
+    let base_files = match stash.commit_id.as_deref() {
+        Some(commit_id) => load_commit_files(conn, commit_id)?,
+        None => Vec::new(),
+    };
+
+    let stashed: HashMap<&str, &CommittedFile> = stash_files.iter().map(|f| (f.name.as_str(), f)).collect();
+    let base: HashMap<&str, &Hash> = base_files.iter().map(|f| (f.name.as_str(), &f.hash)).collect();
+    let head: HashMap<&str, &Hash> = last_commit.files.iter().map(|f| (f.name.as_str(), &f.hash)).collect();
+
+    let mut names: Vec<&str> = stashed.keys().chain(base.keys()).copied().collect();
+    names.sort();
+    names.dedup();
+
+    let (mut files, mut delete, mut conflicts) = (Vec::new(), Vec::new(), Vec::new());
+    for name in names {
+        let stashed_hash = stashed.get(name).map(|f| &f.hash);
+        let base_hash = base.get(name).copied();
+        let head_hash = head.get(name).copied();
+        if stashed_hash == base_hash || stashed_hash == head_hash {
+            continue;
+        }
+        if head_hash != base_hash {
+            conflicts.push(name);
+            continue;
+        }
+        match stashed.get(name) {
+            Some(file) => files.push(*file),
+            None => delete.push(name.to_string()),
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(BucketError::from(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Stash {} changes files which were committed since it was saved: {}",
+                stash.name,
+                conflicts.join(", ")
+            ),
+        )));
+    }
+
+    // A stash doesn't record the modes of its files, so they keep the permissions they are restored with
+    restore_files(&full_bucket_path, &files, &HashMap::new(), &delete, &last_commit, true)?;
+
+    drop_stash(conn, &stash.id)?;
+    println!("Restored stash {}", stash.name);
+    Ok(())
+}
+
+fn list(conn: &Connection, bucket: &Bucket) -> Result<(), BucketError> {
+    let stashes = load_stashes(conn, bucket)?;
+    if stashes.is_empty() {
+        println!("No stashes found in bucket {}.", bucket.name);
+        return Ok(());
+    }
+
+    for stash in stashes.iter().rev() {
+        let files = load_stash_files(conn, &stash.id)?;
+        println!("{}  {}  {} file(s)", stash.name, stash.created_at, files.len());
+    }
+    Ok(())
+}
+
+/// Loads every stash of a bucket, oldest first.
+pub(crate) fn load_stashes(conn: &Connection, bucket: &Bucket) -> Result<Vec<Stash>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, created_at, commit_id
+         FROM stashes
+         WHERE bucket_id = ?1
+         ORDER BY created_at, rowid",
+    )?;
+
+    let stashes = stmt
+        .query_map(params![bucket.id.to_string().to_uppercase()], |row| {
+            Ok(Stash {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                created_at: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                commit_id: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(stashes)
+}
+
+/// Loads the files saved in a stash.
+pub(crate) fn load_stash_files(conn: &Connection, stash_id: &str) -> Result<Vec<CommittedFile>, BucketError> {
+    let mut stmt = conn.prepare(
+        "SELECT file_path, hash
+         FROM stash_files
+         WHERE stash_id = ?1
+         ORDER BY file_path",
+    )?;
+
+    let mut files = Vec::new();
+    let mut rows = stmt.query(params![stash_id])?;
+    while let Some(row) = rows.next()? {
+        let hex_string: String = row.get(1)?;
+        files.push(CommittedFile {
+            id: Default::default(),
+            name: row.get(0)?,
+            hash: Hash::from_hex(&hex_string)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+            change: ChangeKind::Unchanged,
+        });
+    }
+
+    Ok(files)
+}
+
+// Finds a stash by name, or the most recent stash when no name is given
+fn find_stash(conn: &Connection, bucket: &Bucket, name: Option<&str>) -> Result<Stash, BucketError> {
+    let stashes = load_stashes(conn, bucket)?;
+    let stash = match name {
+        Some(name) => stashes.into_iter().find(|s| s.name == name),
+        None => stashes.into_iter().last(),
+    };
+
+    stash.ok_or_else(|| {
+        BucketError::from(io::Error::new(
+            io::ErrorKind::NotFound,
+            match name {
+                Some(name) => format!("Stash {} not found.", name),
+                None => "No stashes found.".to_string(),
+            },
+        ))
+    })
+}
+
+// Removes a stash and its files in one transaction, so a stash is never left without its files
+fn drop_stash(conn: &mut Connection, stash_id: &str) -> Result<(), BucketError> {
+    let tx = conn.transaction()?;
+    tx.execute("DELETE FROM stash_files WHERE stash_id = ?1", params![stash_id])?;
+    tx.execute("DELETE FROM stashes WHERE id = ?1", params![stash_id])?;
+    tx.commit()?;
+    Ok(())
+}
+
+// Generates the first free name of the form `stash-N` for a bucket
+fn next_stash_name(conn: &Connection, bucket: &Bucket) -> Result<String, BucketError> {
+    let mut number = 1;
+    loop {
+        let name = format!("stash-{}", number);
+        let exists: Option<String> = conn
+            .query_row(
+                "SELECT id FROM stashes WHERE bucket_id = ?1 AND name = ?2",
+                params![bucket.id.to_string().to_uppercase(), name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if exists.is_none() {
+            return Ok(name);
+        }
+        number += 1;
+    }
+}
+
+fn last_commit_or_empty(bucket: &Bucket) -> Result<Commit, BucketError> {
+    Ok(load_last_commit(bucket)?.unwrap_or_else(|| Commit::empty(&bucket.name)))
+}
@@ -0,0 +1,47 @@
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{get_db_conn, RepositoryConfig};
+use crate::utils::errors::BucketError;
+use crate::utils::storage::{delta_header, full_blob_size, list_stored_blobs, storage_paths};
+use rusqlite::{params, OptionalExtension};
+use std::env;
+
+/// Shows how much space the blobs of the repository take up and what delta compression saves.
+///
+/// The saving of a delta is measured against the size of the same content stored as a full blob, compressed
+/// with the codec of the file it belongs to, so it doesn't count what compression alone saves.
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+    let conn = get_db_conn()?;
+
+    let (mut blobs, mut stored) = (0, 0);
+    let (mut deltas, mut delta_stored, mut full_stored) = (0, 0, 0);
+    for storage_path in storage_paths(&repo_root)? {
+        for (hash, size) in list_stored_blobs(&storage_path)? {
+            blobs += 1;
+            stored += size;
+            if delta_header(&storage_path, &hash)?.is_some() {
+                let path: Option<String> = conn
+                    .query_row("SELECT file_path FROM files WHERE hash = ?1 LIMIT 1", params![hash.to_string()], |row| row.get(0))
+                    .optional()?;
+                let codec = config.codec_for(path.as_deref().unwrap_or_default());
+
+                deltas += 1;
+                delta_stored += size;
+                full_stored += full_blob_size(&storage_path, &hash, codec)?;
+            }
+        }
+    }
+
+    println!("Blobs:        {}", blobs);
+    println!("Stored bytes: {}", stored);
+    println!("Delta blobs:  {}", deltas);
+    println!(
+        "Delta saving: {} bytes, the deltas take {} bytes instead of {} bytes as full blobs",
+        full_stored.saturating_sub(delta_stored),
+        delta_stored,
+        full_stored
+    );
+
+    Ok(())
+}
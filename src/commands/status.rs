@@ -1,5 +1,147 @@
+use crate::commands::commit::{list_files_with_metadata_in_bucket, load_last_commit};
+use crate::data::bucket::Bucket;
+use crate::data::commit::{ChangeKind, Commit, CommittedFile};
 use crate::utils::errors::BucketError;
+use std::env;
+
+/// Files in the working directory grouped by how they differ from the last commit.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct BucketStatus {
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Moved files as `(from, to)` pairs
+    pub renamed: Vec<(String, String)>,
+}
+
+impl BucketStatus {
+    /// Compares the files in the working directory against the files of the last commit.
+    pub(crate) fn from_commits(current: &Commit, last: &Commit) -> Self {
+        match current.compare(last) {
+            Some(changes) => BucketStatus::from_changes(&changes),
+            None => BucketStatus::default(),
+        }
+    }
+
+    /// Groups files by the change kind recorded for them.
+    pub(crate) fn from_changes(changes: &[CommittedFile]) -> Self {
+        let mut status = BucketStatus::default();
+
+        for file in changes.iter() {
+            match &file.change {
+                ChangeKind::Unchanged => {}
+                ChangeKind::Added => status.added.push(file.name.clone()),
+                ChangeKind::Modified => status.modified.push(file.name.clone()),
+                ChangeKind::Deleted => status.deleted.push(file.name.clone()),
+                ChangeKind::Renamed { from } => status.renamed.push((from.clone(), file.name.clone())),
+            }
+        }
+
+        status.added.sort();
+        status.modified.sort();
+        status.deleted.sort();
+        status.renamed.sort();
+        status
+    }
+
+    /// Number of files that changed.
+    pub(crate) fn len(&self) -> usize {
+        self.added.len() + self.modified.len() + self.deleted.len() + self.renamed.len()
+    }
+
+    pub(crate) fn is_clean(&self) -> bool {
+        self.len() == 0
+    }
+}
 
 pub(crate) fn execute() -> Result<(), BucketError> {
-    todo!()
-}
\ No newline at end of file
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+
+    let current = list_files_with_metadata_in_bucket(&bucket)?;
+    let last = load_last_commit(&bucket)?.unwrap_or_else(|| Commit::empty(&bucket.name));
+
+    let status = BucketStatus::from_commits(&current, &last);
+
+    println!("On bucket {}", bucket.name);
+    if status.is_clean() {
+        println!("No changes since last commit.");
+        return Ok(());
+    }
+
+    print_section("New files:", &status.added);
+    print_section("Modified files:", &status.modified);
+    print_section("Deleted files:", &status.deleted);
+    let renamed: Vec<String> = status.renamed.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+    print_section("Renamed files:", &renamed);
+
+    Ok(())
+}
+
+fn print_section(title: &str, files: &[String]) {
+    if files.is_empty() {
+        return;
+    }
+
+    println!("{}", title);
+    for file in files {
+        println!("    {}", file);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::commit::CommittedFile;
+
+    fn commit_with(files: &[(&str, &[u8])]) -> Commit {
+        Commit {
+            bucket: "test_bucket".to_string(),
+            files: files
+                .iter()
+                .map(|(name, content)| CommittedFile {
+                    id: Default::default(),
+                    name: name.to_string(),
+                    hash: blake3::hash(content),
+                    change: ChangeKind::Unchanged,
+                })
+                .collect(),
+            timestamp: "".to_string(),
+            id: None,
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn test_status_groups_changes() {
+        let last = commit_with(&[("same.txt", b"same"), ("changed.txt", b"old"), ("removed.txt", b"gone")]);
+        let current = commit_with(&[("same.txt", b"same"), ("changed.txt", b"new"), ("added.txt", b"added")]);
+
+        let status = BucketStatus::from_commits(&current, &last);
+
+        assert_eq!(status.added, vec!["added.txt".to_string()]);
+        assert_eq!(status.modified, vec!["changed.txt".to_string()]);
+        assert_eq!(status.deleted, vec!["removed.txt".to_string()]);
+        assert!(!status.is_clean());
+    }
+
+    #[test]
+    fn test_status_renamed() {
+        let last = commit_with(&[("old/wall.png", b"wall")]);
+        let current = commit_with(&[("new/wall.png", b"wall")]);
+
+        let status = BucketStatus::from_commits(&current, &last);
+
+        assert_eq!(status.renamed, vec![("old/wall.png".to_string(), "new/wall.png".to_string())]);
+        assert!(status.added.is_empty());
+        assert!(status.deleted.is_empty());
+        assert_eq!(status.len(), 1);
+    }
+
+    #[test]
+    fn test_status_clean() {
+        let last = commit_with(&[("same.txt", b"same")]);
+        let current = commit_with(&[("same.txt", b"same")]);
+
+        assert!(BucketStatus::from_commits(&current, &last).is_clean());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::commands::list::load_buckets;
+use crate::utils::checks::find_repo_root;
+use crate::utils::config::{get_db_conn, RepositoryConfig, StorageMode};
+use crate::utils::errors::BucketError;
+use crate::utils::packs::{list_loose_blobs, list_packs, packs_path, read_index};
+use crate::utils::storage::{bucket_storage_path, copy_atomically, import_blob, objects_path, storage_paths};
+use std::{env, fs};
+
+/// Shows the storage mode of the repository and how many blobs it stores.
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let config = RepositoryConfig::from_file(repo_root.clone())?;
+    let conn = get_db_conn()?;
+
+    match config.storage {
+        StorageMode::Bucket => println!("Storage mode: bucket"),
+        StorageMode::Repository => println!("Storage mode: repository"),
+    }
+
+    let (mut loose, mut packed, mut packs, mut size) = (0, 0, 0, 0);
+    for storage_path in storage_paths(&repo_root)? {
+        for (_, path) in list_loose_blobs(&storage_path)? {
+            loose += 1;
+            size += fs::metadata(path)?.len();
+        }
+        for pack_path in list_packs(&storage_path)? {
+            packs += 1;
+            packed += read_index(&pack_path)?.len();
+            size += fs::metadata(pack_path)?.len();
+        }
+    }
+
+    let shared: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM (SELECT hash FROM object_refs GROUP BY hash HAVING COUNT(*) > 1)",
+        [],
+        |row| row.get(0),
+    )?;
+
+    println!("Blobs:        {} ({} bytes)", loose + packed, size);
+    println!("Packed blobs: {} in {} pack(s)", packed, packs);
+    println!("Shared blobs: {} referenced by more than one bucket", shared);
+
+    Ok(())
+}
+
+/// Moves the blobs of every bucket into the object store of the repository and switches the repository
+/// to the repository storage mode.
+///
+/// Blobs are added to the object store first, the repository only switches once every blob is in place and
+/// the blobs of the buckets are removed last. An interrupted migration leaves a working repository behind
+/// and can be run again to finish it.
+pub(crate) fn migrate() -> Result<(), BucketError> {
+    let repo_root = find_repo_root(&env::current_dir()?).ok_or(BucketError::NotInBucketRepo)?;
+    let mut config = RepositoryConfig::from_file(repo_root.clone())?;
+    let conn = get_db_conn()?;
+    let objects = objects_path(&repo_root);
+
+    let mut bucket_blobs = Vec::new();
+    let mut bucket_packs = Vec::new();
+    for record in load_buckets(&conn)? {
+        let storage_path = bucket_storage_path(&repo_root.join(&record.path));
+        bucket_blobs.extend(list_loose_blobs(&storage_path)?);
+        bucket_packs.extend(list_packs(&storage_path)?);
+    }
+
+    if config.storage == StorageMode::Repository && bucket_blobs.is_empty() && bucket_packs.is_empty() {
+        println!("Repository already stores its blobs in {}.", objects.display());
+        return Ok(());
+    }
+
+    let mut moved = 0;
+    let mut saved = 0;
+    for (hash, path) in bucket_blobs.iter() {
+        if import_blob(path, &objects, hash)? {
+            moved += 1;
+        } else {
+            saved += fs::metadata(path)?.len();
+        }
+    }
+    fs::create_dir_all(&objects)?;
+
+    // Packfiles are named after their content, so they are copied as they are. The index is copied last, a
+    // packfile without its index is not used, and a copy of the same size was completed by an earlier run.
+    let objects_packs = packs_path(&objects);
+    for pack_path in bucket_packs.iter() {
+        fs::create_dir_all(&objects_packs)?;
+        for path in [pack_path.clone(), pack_path.with_extension("idx")] {
+            let target = objects_packs.join(path.file_name().unwrap());
+            let copied = target.is_file() && fs::metadata(&target)?.len() == fs::metadata(&path)?.len();
+            if !copied {
+                copy_atomically(&path, &target)?;
+            }
+        }
+    }
+
+    if config.storage != StorageMode::Repository {
+        config.storage = StorageMode::Repository;
+        config.write(&repo_root.join(".buckets"))?;
+    }
+
+    for (_, path) in bucket_blobs.iter() {
+        fs::remove_file(path)?;
+    }
+    for pack_path in bucket_packs.iter() {
+        fs::remove_file(pack_path.with_extension("idx"))?;
+        fs::remove_file(pack_path)?;
+    }
+
+    println!(
+        "Moved {} blob(s) and {} pack(s) into {}, {} duplicate blob(s) removed saving {} bytes.",
+        moved,
+        bucket_packs.len(),
+        objects.display(),
+        bucket_blobs.len() - moved,
+        saved
+    );
+
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use crate::commands::finalize::load_finalized_versions;
+use crate::data::bucket::Bucket;
+use crate::data::commit::short_id;
+use crate::utils::config::get_db_conn;
+use crate::utils::errors::BucketError;
+use std::env;
+
+pub(crate) fn execute() -> Result<(), BucketError> {
+    let bucket = Bucket::from_meta_data(env::current_dir()?)?;
+    let conn = get_db_conn()?;
+
+    let versions = load_finalized_versions(&conn, &bucket)?;
+    if versions.is_empty() {
+        println!("No finalized versions of bucket {}.", bucket.name);
+        return Ok(());
+    }
+
+    let width = versions.iter().map(|v| v.version.len()).max().unwrap_or(0).max(7);
+    println!("{:<width$}  {:<12}  FINALIZED", "VERSION", "COMMIT");
+    for version in versions.iter().rev() {
+        println!("{:<width$}  {:<12}  {}", version.version, short_id(&version.commit_id), version.created_at);
+    }
+
+    Ok(())
+}
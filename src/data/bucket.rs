@@ -17,7 +17,7 @@ pub struct Bucket {
 }
 
 impl Bucket {
-    pub(crate) fn default(uuid: Uuid, name: &String, path: &PathBuf) -> Bucket {
+    pub(crate) fn default(uuid: Uuid, name: &String, path: &Path) -> Bucket {
         Bucket {
             id: uuid,
             name: name.to_string(),
@@ -48,8 +48,8 @@ impl Bucket {
         Ok(bucket)
     }
 
-    pub fn write_bucket_info(&self) {
-        let mut file = File::create(self.relative_bucket_path.join(".b").join("info")).unwrap();
+    pub fn write_bucket_info(&self, bucket_path: &Path) {
+        let mut file = File::create(bucket_path.join(".b").join("info")).unwrap();
         file.write_fmt(format_args!("{}", to_string(self).unwrap()))
             .unwrap();
     }
@@ -63,7 +63,7 @@ impl Bucket {
     }
 }
 
-fn read_bucket_info(path: &PathBuf) -> Result<Bucket, std::io::Error> {
+pub(crate) fn read_bucket_info(path: &Path) -> Result<Bucket, std::io::Error> {
     let info_path = path.join(".b").join("info");
     let mut file = File::open(&info_path).map_err(|e| {
         io::Error::new(
@@ -111,7 +111,7 @@ mod tests {
         create_dir_all(&bucket_meta_path)?;
 
         let bucket_default = Bucket::default(Uuid::new_v4(), &bucket_name, &bucket_path);
-        bucket_default.write_bucket_info();
+        bucket_default.write_bucket_info(&bucket_path);
 
         let bucket = match Bucket::from_meta_data(bucket_path) {
             Ok(bucket) => bucket,
@@ -1,24 +1,71 @@
 use blake3::Hash;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet, VecDeque};
 use uuid::Uuid;
 
+/// How a file changed compared to the parent commit.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    Unchanged,
+    Added,
+    Modified,
+    Deleted,
+    /// The file was moved, its content is the same as the file at `from` in the parent commit
+    Renamed { from: String },
+}
+
+impl ChangeKind {
+    /// The value stored in the `change_kind` column of the `files` table.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Unchanged => "unchanged",
+            ChangeKind::Added => "added",
+            ChangeKind::Modified => "modified",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Renamed { .. } => "renamed",
+        }
+    }
+
+    /// The original path of a renamed file, stored in the `renamed_from` column of the `files` table.
+    pub fn renamed_from(&self) -> Option<&str> {
+        match self {
+            ChangeKind::Renamed { from } => Some(from),
+            _ => None,
+        }
+    }
+
+    /// Reads a change kind back from the `change_kind` and `renamed_from` columns.
+    pub fn from_columns(kind: &str, renamed_from: Option<String>) -> Result<ChangeKind, String> {
+        match (kind, renamed_from) {
+            ("unchanged", _) => Ok(ChangeKind::Unchanged),
+            ("added", _) => Ok(ChangeKind::Added),
+            ("modified", _) => Ok(ChangeKind::Modified),
+            ("deleted", _) => Ok(ChangeKind::Deleted),
+            ("renamed", Some(from)) => Ok(ChangeKind::Renamed { from }),
+            ("renamed", None) => Err("Renamed file without original path".to_string()),
+            (other, _) => Err(format!("Unknown change kind {}", other)),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct CommittedFile {
     pub id: Uuid,
     pub name: String,
     #[serde(serialize_with = "hash_to_hex", deserialize_with = "hex_to_hash")]
     pub hash: Hash,
-    pub new: bool,
-    pub changed: bool,
+    pub change: ChangeKind,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Commit {
+    /// Id of the commit, `None` for the state of the working directory which is not committed yet
+    pub id: Option<String>,
+    /// Id of the commit this commit is based on, `None` for the first commit of a bucket
+    pub parent: Option<String>,
     pub bucket: String,
     pub files: Vec<CommittedFile>,
     pub timestamp: String,
-    pub(crate) previous: Option<Box<Commit>>,
-    pub(crate) next: Option<Box<Commit>>,
 }
 
 // Custom function to serialize a `blake3::Hash` to a hex string
@@ -38,75 +85,202 @@ fn hex_to_hash<'de, D>(deserializer: D) -> Result<Hash, D::Error>
     Hash::from_hex(&s).map_err(serde::de::Error::custom)
 }
 
+/// Computes the content addressed id of a commit.
+///
+/// The id is the blake3 hash over the manifest, the parent, the author, the timestamp and the message of
+/// the commit. Two repositories with the same commit id hold the same snapshot and history, and changing
+/// any of these values in a stored commit no longer matches its id.
+///
+/// Every value but the message is written on a line of its own and the message follows an empty line, so
+/// only values without a line break, see `is_single_line`, encode a commit unambiguously.
+pub fn commit_id(manifest_id: &str, parent: Option<&str>, author: &str, timestamp: &str, message: &str) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(format!("manifest {}\n", manifest_id).as_bytes());
+    if let Some(parent) = parent {
+        hasher.update(format!("parent {}\n", parent).as_bytes());
+    }
+    hasher.update(format!("author {}\n", author).as_bytes());
+    hasher.update(format!("timestamp {}\n", timestamp).as_bytes());
+    hasher.update(b"\n");
+    hasher.update(message.as_bytes());
+    hasher.finalize()
+}
+
+/// Checks that a value of a commit holds no line break, the message is the only value of a commit id that can.
+pub fn is_single_line(value: &str) -> bool {
+    !value.contains('\n')
+}
+
+/// The abbreviated form of a commit id used in tables, any unambiguous prefix identifies a commit.
+pub fn short_id(id: &str) -> &str {
+    &id[..id.len().min(12)]
+}
+
 impl Commit {
+    /// A commit without any files, used as the base of the first commit of a bucket.
+    pub fn empty(bucket: &str) -> Commit {
+        Commit {
+            id: None,
+            parent: None,
+            bucket: bucket.to_string(),
+            files: Vec::new(),
+            timestamp: "".to_string(),
+        }
+    }
+
+    /// Compares the files of this commit against `other_commit`, its parent.
+    ///
+    /// Returns every file of this commit with its change kind, followed by the files that were deleted,
+    /// or `None` when nothing changed. A new file with the same content as a deleted file is recorded as
+    /// a rename of that file.
     pub fn compare(&self, other_commit: &Commit) -> Option<Vec<CommittedFile>> {
-        match other_commit {
-            Commit {
-                bucket: _,
-                files: _,
-                timestamp: _,
-                previous: _,
-                next: _,
-            } => {
-                let mut changes = Vec::new();
-
-                // First check if existing files are the same
-                for file in self.files.iter() {
-                    for other_file in other_commit.files.iter() {
-                        if file.name == other_file.name && file.hash != other_file.hash {
-                            changes.push(CommittedFile {
-                                id: file.id,
-                                name: file.name.clone(),
-                                hash: file.hash.clone(),
-                                new: false,
-                                changed: true,
-                            });
-                        } else if file.name == other_file.name && file.hash == other_file.hash {
-                            changes.push(CommittedFile {
-                                id: file.id,
-                                name: file.name.clone(),
-                                hash: other_file.hash.clone(),
-                                new: false,
-                                changed: false,
-                            });
-                        }
-                    }
-                }
-
-                // Add files which haven't changed
-                for file in self.files.iter() {
-                    let mut found = false;
-                    for other_file in other_commit.files.iter() {
-                        if file.name == other_file.name {
-                            found = true;
-                        }
-                    }
-                    if !found {
-                        changes.push(CommittedFile {
-                            id: file.id,
-                            name: file.name.clone(),
-                            hash: file.hash.clone(),
-                            new: true,
-                            changed: false,
-                        });
-                    }
-                }
-
-                // Check if any changes were found
-                if changes.iter().filter(|cf| cf.new).collect::<Vec<_>>().len() > 0 {
-                    println!("Changes found.");
-                    return Some(changes);
-                }
-
-                // Check if any files were removed
-                if changes.len() < other_commit.files.len() {
-                    println!("Changes found.");
-                    return Some(changes);
-                }
-
-                None
-            }
+        let names: HashSet<&str> = self.files.iter().map(|file| file.name.as_str()).collect();
+        let others: HashMap<&str, &CommittedFile> =
+            other_commit.files.iter().map(|other_file| (other_file.name.as_str(), other_file)).collect();
+
+        // Deleted files in the order of the parent, a rename takes the first deleted file with its content
+        let mut deleted: Vec<Option<&CommittedFile>> = other_commit
+            .files
+            .iter()
+            .filter(|other_file| !names.contains(other_file.name.as_str()))
+            .map(Some)
+            .collect();
+        let mut deleted_by_hash: HashMap<Hash, VecDeque<usize>> = HashMap::new();
+        for (index, other_file) in deleted.iter().flatten().enumerate() {
+            deleted_by_hash.entry(other_file.hash).or_default().push_back(index);
+        }
+
+        let mut changes = Vec::new();
+        for file in self.files.iter() {
+            let change = match others.get(file.name.as_str()) {
+                Some(other_file) if other_file.hash == file.hash => ChangeKind::Unchanged,
+                Some(_) => ChangeKind::Modified,
+                None => match deleted_by_hash.get_mut(&file.hash).and_then(|indexes| indexes.pop_front()) {
+                    Some(index) => ChangeKind::Renamed { from: deleted[index].take().unwrap().name.clone() },
+                    None => ChangeKind::Added,
+                },
+            };
+
+            changes.push(CommittedFile {
+                id: file.id,
+                name: file.name.clone(),
+                hash: file.hash,
+                change,
+            });
         }
+
+        for other_file in deleted.into_iter().flatten() {
+            changes.push(CommittedFile {
+                id: other_file.id,
+                name: other_file.name.clone(),
+                hash: other_file.hash,
+                change: ChangeKind::Deleted,
+            });
+        }
+
+        if changes.iter().all(|file| file.change == ChangeKind::Unchanged) {
+            return None;
+        }
+
+        Some(changes)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_with(files: &[(&str, &[u8])]) -> Commit {
+        Commit {
+            id: None,
+            parent: None,
+            bucket: "test_bucket".to_string(),
+            files: files
+                .iter()
+                .map(|(name, content)| CommittedFile {
+                    id: Default::default(),
+                    name: name.to_string(),
+                    hash: blake3::hash(content),
+                    change: ChangeKind::Unchanged,
+                })
+                .collect(),
+            timestamp: "".to_string(),
+        }
+    }
+
+    fn change_of<'a>(changes: &'a [CommittedFile], name: &str) -> &'a ChangeKind {
+        &changes.iter().find(|file| file.name == name).unwrap().change
+    }
+
+    #[test]
+    fn test_compare_change_kinds() {
+        let parent = commit_with(&[("same", b"same"), ("edited", b"old"), ("removed", b"gone"), ("old/moved", b"moved")]);
+        let current = commit_with(&[("same", b"same"), ("edited", b"new"), ("added", b"added"), ("new/moved", b"moved")]);
+
+        let changes = current.compare(&parent).unwrap();
+
+        assert_eq!(changes.len(), 5);
+        assert_eq!(change_of(&changes, "same"), &ChangeKind::Unchanged);
+        assert_eq!(change_of(&changes, "edited"), &ChangeKind::Modified);
+        assert_eq!(change_of(&changes, "added"), &ChangeKind::Added);
+        assert_eq!(change_of(&changes, "removed"), &ChangeKind::Deleted);
+        assert_eq!(change_of(&changes, "new/moved"), &ChangeKind::Renamed { from: "old/moved".to_string() });
+    }
+
+    #[test]
+    fn test_compare_only_modified() {
+        let parent = commit_with(&[("edited", b"old")]);
+        let current = commit_with(&[("edited", b"new")]);
+
+        let changes = current.compare(&parent).unwrap();
+        assert_eq!(change_of(&changes, "edited"), &ChangeKind::Modified);
+    }
+
+    #[test]
+    fn test_compare_renames_with_same_content() {
+        let parent = commit_with(&[("a", b"copy"), ("b", b"copy"), ("c", b"copy")]);
+        let current = commit_with(&[("x", b"copy"), ("y", b"copy")]);
+
+        let changes = current.compare(&parent).unwrap();
+        assert_eq!(change_of(&changes, "x"), &ChangeKind::Renamed { from: "a".to_string() });
+        assert_eq!(change_of(&changes, "y"), &ChangeKind::Renamed { from: "b".to_string() });
+        assert_eq!(change_of(&changes, "c"), &ChangeKind::Deleted);
+        assert_eq!(changes.len(), 3);
+    }
+
+    #[test]
+    fn test_compare_no_changes() {
+        let parent = commit_with(&[("same", b"same")]);
+        let current = commit_with(&[("same", b"same")]);
+
+        assert!(current.compare(&parent).is_none());
+    }
+
+    #[test]
+    fn test_commit_id_covers_every_field() {
+        let id = commit_id("manifest", Some("parent"), "artist", "2024-01-01 10:00:00.000", "message");
+
+        assert_eq!(id, commit_id("manifest", Some("parent"), "artist", "2024-01-01 10:00:00.000", "message"));
+        assert_ne!(id, commit_id("other", Some("parent"), "artist", "2024-01-01 10:00:00.000", "message"));
+        assert_ne!(id, commit_id("manifest", None, "artist", "2024-01-01 10:00:00.000", "message"));
+        assert_ne!(id, commit_id("manifest", Some("parent"), "other", "2024-01-01 10:00:00.000", "message"));
+        assert_ne!(id, commit_id("manifest", Some("parent"), "artist", "2024-01-01 10:00:00.001", "message"));
+        assert_ne!(id, commit_id("manifest", Some("parent"), "artist", "2024-01-01 10:00:00.000", "other"));
+
+        // an author with a line break could pose as a different timestamp and message
+        let forged = commit_id("manifest", Some("parent"), "artist\ntimestamp 2024-01-01 10:00:00.000\n\nmessage", "", "");
+        assert!(!is_single_line("artist\ntimestamp 2024-01-01 10:00:00.000\n\nmessage"));
+        assert!(is_single_line("artist"));
+        assert_ne!(id, forged);
+    }
+
+    #[test]
+    fn test_change_kind_columns() {
+        let renamed = ChangeKind::Renamed { from: "old".to_string() };
+        let restored = ChangeKind::from_columns(renamed.as_str(), renamed.renamed_from().map(str::to_string)).unwrap();
+
+        assert_eq!(restored, renamed);
+        assert!(ChangeKind::from_columns("renamed", None).is_err());
+        assert!(ChangeKind::from_columns("copied", None).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// A rule that has to be satisfied before a bucket is ready to be finalized.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expectation {
+    /// A bucket with this name exists in the repository.
+    Bucket { name: String },
+    /// At least one file of this type exists in the bucket, optionally inside a directory of the bucket.
+    File {
+        file_type: String,
+        directory: Option<String>,
+    },
+}
+
+/// The state of the repository an expectation is evaluated against.
+pub struct ExpectationContext<'a> {
+    /// Names of all buckets in the repository.
+    pub bucket_names: &'a [String],
+    /// Paths of the files in the bucket, relative to the top of the bucket.
+    pub files: &'a [String],
+}
+
+impl Expectation {
+    /// The kind of the expectation as stored in the database.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Expectation::Bucket { .. } => "bucket",
+            Expectation::File { .. } => "file",
+        }
+    }
+
+    /// Recreates an expectation from its database columns.
+    pub fn from_columns(kind: &str, subject: String, directory: Option<String>) -> Option<Expectation> {
+        match kind {
+            "bucket" => Some(Expectation::Bucket { name: subject }),
+            "file" => Some(Expectation::File {
+                file_type: subject,
+                directory,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The name of the bucket or the file type the expectation is about.
+    pub fn subject(&self) -> &str {
+        match self {
+            Expectation::Bucket { name } => name,
+            Expectation::File { file_type, .. } => file_type,
+        }
+    }
+
+    pub fn directory(&self) -> Option<&str> {
+        match self {
+            Expectation::Bucket { .. } => None,
+            Expectation::File { directory, .. } => directory.as_deref(),
+        }
+    }
+
+    /// Files of the bucket which satisfy the expectation.
+    pub fn matching_files<'a>(&self, files: &'a [String]) -> Vec<&'a String> {
+        match self {
+            Expectation::Bucket { .. } => Vec::new(),
+            Expectation::File { file_type, directory } => files
+                .iter()
+                .filter(|file| has_file_type(file, file_type) && is_in_directory(file, directory.as_deref()))
+                .collect(),
+        }
+    }
+
+    pub fn is_satisfied(&self, context: &ExpectationContext) -> bool {
+        match self {
+            Expectation::Bucket { name } => context.bucket_names.iter().any(|n| n == name),
+            Expectation::File { .. } => !self.matching_files(context.files).is_empty(),
+        }
+    }
+}
+
+impl Display for Expectation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expectation::Bucket { name } => write!(f, "bucket {} exists", name),
+            Expectation::File { file_type, directory: None } => write!(f, "file of type {} in bucket", file_type),
+            Expectation::File { file_type, directory: Some(directory) } => {
+                write!(f, "file of type {} in {}", file_type, directory)
+            }
+        }
+    }
+}
+
+// The file type is the extension of the file, with or without the leading dot
+fn has_file_type(file: &str, file_type: &str) -> bool {
+    let file_type = file_type.trim_start_matches('.');
+    match Path::new(file).extension() {
+        Some(extension) => extension.to_string_lossy().eq_ignore_ascii_case(file_type),
+        None => false,
+    }
+}
+
+fn is_in_directory(file: &str, directory: Option<&str>) -> bool {
+    match directory {
+        None => true,
+        Some(directory) => {
+            let directory = directory.trim_matches('/');
+            directory.is_empty() || directory == "." || Path::new(file).starts_with(directory)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_expectation() {
+        let files = vec!["concept/hero.PNG".to_string(), "notes.txt".to_string()];
+        let context = ExpectationContext {
+            bucket_names: &[],
+            files: &files,
+        };
+
+        let anywhere = Expectation::File { file_type: "png".to_string(), directory: None };
+        let in_concept = Expectation::File { file_type: ".png".to_string(), directory: Some("concept/".to_string()) };
+        let in_other = Expectation::File { file_type: "png".to_string(), directory: Some("conc".to_string()) };
+        let blend = Expectation::File { file_type: "blend".to_string(), directory: None };
+
+        assert!(anywhere.is_satisfied(&context));
+        assert!(in_concept.is_satisfied(&context));
+        assert!(!in_other.is_satisfied(&context));
+        assert!(!blend.is_satisfied(&context));
+    }
+
+    #[test]
+    fn test_bucket_expectation() {
+        let names = vec!["concept_art".to_string()];
+        let context = ExpectationContext {
+            bucket_names: &names,
+            files: &[],
+        };
+
+        assert!(Expectation::Bucket { name: "concept_art".to_string() }.is_satisfied(&context));
+        assert!(!Expectation::Bucket { name: "textures".to_string() }.is_satisfied(&context));
+    }
+
+    #[test]
+    fn test_from_columns() {
+        let expectation = Expectation::File { file_type: "png".to_string(), directory: Some("concept".to_string()) };
+        let restored = Expectation::from_columns(
+            expectation.kind(),
+            expectation.subject().to_string(),
+            expectation.directory().map(|d| d.to_string()),
+        );
+        assert_eq!(restored, Some(expectation));
+        assert_eq!(Expectation::from_columns("unknown", "x".to_string(), None), None);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+/// A one way link between two buckets, the output of `from` is the input of `to`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Link {
+    pub from: String,
+    pub to: String,
+}
+
+/// Checks if adding a link from `from` to `to` would create a cycle in the workflow graph.
+///
+/// A cycle is created when `from` can already be reached by following the links downstream of `to`,
+/// including the case where `from` and `to` are the same bucket.
+pub fn creates_cycle(links: &[Link], from: &str, to: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut stack = vec![to];
+
+    while let Some(bucket) = stack.pop() {
+        if bucket == from {
+            return true;
+        }
+        if !visited.insert(bucket) {
+            continue;
+        }
+        stack.extend(links.iter().filter(|l| l.from == bucket).map(|l| l.to.as_str()));
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(from: &str, to: &str) -> Link {
+        Link {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_creates_cycle() {
+        let links = vec![link("concept", "model"), link("model", "texture")];
+
+        assert!(creates_cycle(&links, "texture", "concept"));
+        assert!(creates_cycle(&links, "model", "concept"));
+        assert!(creates_cycle(&links, "concept", "concept"));
+        assert!(!creates_cycle(&links, "concept", "texture"));
+        assert!(!creates_cycle(&links, "texture", "audio"));
+    }
+}
@@ -0,0 +1,136 @@
+use blake3::{Hash, Hasher};
+use std::collections::BTreeMap;
+
+/// A file in the snapshot of a bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// Path of the file relative to the bucket root
+    pub path: String,
+    pub hash: Hash,
+    pub size: u64,
+    pub mode: u32,
+}
+
+/// The files of one directory in a manifest, identified by the hash of its entries.
+///
+/// Trees are stored once and shared between manifests, so a directory that didn't change between two
+/// commits doesn't take up any extra space.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    /// Directory relative to the bucket root, empty for the bucket root itself
+    pub directory: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// The complete snapshot of a bucket at the time of a commit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Tree {
+    pub fn id(&self) -> Hash {
+        let mut hasher = Hasher::new();
+        for entry in self.entries.iter() {
+            hasher.update(format!("{} {} {:o} {}\n", entry.hash, entry.size, entry.mode, file_name(&entry.path)).as_bytes());
+        }
+        hasher.finalize()
+    }
+}
+
+impl Manifest {
+    pub fn new(mut entries: Vec<ManifestEntry>) -> Manifest {
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Manifest { entries }
+    }
+
+    /// Splits the manifest into one tree per directory, sorted by directory.
+    pub fn trees(&self) -> Vec<Tree> {
+        let mut trees: BTreeMap<&str, Vec<ManifestEntry>> = BTreeMap::new();
+        for entry in self.entries.iter() {
+            trees.entry(directory(&entry.path)).or_default().push(entry.clone());
+        }
+
+        trees
+            .into_iter()
+            .map(|(directory, entries)| Tree {
+                directory: directory.to_string(),
+                entries,
+            })
+            .collect()
+    }
+
+    /// The id of a manifest is the hash of its trees, equal snapshots share the same id.
+    pub fn id(&self) -> Hash {
+        let mut hasher = Hasher::new();
+        for tree in self.trees() {
+            hasher.update(format!("{} {}\n", tree.id(), tree.directory).as_bytes());
+        }
+        hasher.finalize()
+    }
+}
+
+/// The directory part of a path relative to the bucket root.
+pub fn directory(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(index) => &path[..index],
+        None => "",
+    }
+}
+
+/// The file name part of a path relative to the bucket root.
+pub fn file_name(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(index) => &path[index + 1..],
+        None => path,
+    }
+}
+
+/// Joins a directory and a file name back into a path relative to the bucket root.
+pub fn join(directory: &str, name: &str) -> String {
+    if directory.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", directory, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, content: &[u8]) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            hash: blake3::hash(content),
+            size: content.len() as u64,
+            mode: 0o644,
+        }
+    }
+
+    #[test]
+    fn test_trees_per_directory() {
+        let manifest = Manifest::new(vec![
+            entry("textures/wall.png", b"wall"),
+            entry("scene.blend", b"scene"),
+            entry("textures/floor.png", b"floor"),
+        ]);
+
+        let trees = manifest.trees();
+        assert_eq!(trees.len(), 2);
+        assert_eq!(trees[0].directory, "");
+        assert_eq!(trees[1].directory, "textures");
+        assert_eq!(trees[1].entries[0].path, "textures/floor.png");
+        assert_eq!(join(&trees[1].directory, file_name(&trees[1].entries[0].path)), "textures/floor.png");
+    }
+
+    #[test]
+    fn test_unchanged_trees_are_shared() {
+        let first = Manifest::new(vec![entry("textures/wall.png", b"wall"), entry("scene.blend", b"scene")]);
+        let second = Manifest::new(vec![entry("textures/wall.png", b"wall"), entry("scene.blend", b"scene2")]);
+
+        assert_ne!(first.id(), second.id());
+        assert_eq!(first.trees()[1].id(), second.trees()[1].id());
+        assert_eq!(first.id(), Manifest::new(first.entries.clone()).id());
+    }
+}
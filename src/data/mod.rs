@@ -1,2 +1,6 @@
 pub mod commit;
 pub mod bucket;
+pub mod expectation;
+pub mod link;
+pub mod manifest;
+pub mod version;
@@ -0,0 +1,98 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Version number of a finalized bucket, either a plain integer or a semantic version.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Version {
+    Integer(u64),
+    SemVer {
+        major: u64,
+        minor: u64,
+        patch: u64,
+        pre_release: Option<String>,
+    },
+}
+
+impl Version {
+    /// The version that follows this one when no version is given on the command line.
+    pub fn next(&self) -> Version {
+        match self {
+            Version::Integer(n) => Version::Integer(n + 1),
+            Version::SemVer { major, minor, patch, pre_release: _ } => Version::SemVer {
+                major: *major,
+                minor: *minor,
+                patch: patch + 1,
+                pre_release: None,
+            },
+        }
+    }
+}
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid version {}, expected an integer like 3 or a semantic version like 1.2.0", s);
+        let s = s.strip_prefix('v').unwrap_or(s);
+
+        if let Ok(n) = s.parse::<u64>() {
+            return Ok(Version::Integer(n));
+        }
+
+        let (numbers, pre_release) = match s.split_once('-') {
+            Some((numbers, pre)) if !pre.is_empty() => (numbers, Some(pre.to_string())),
+            Some(_) => return Err(invalid()),
+            None => (s, None),
+        };
+
+        let parts: Vec<&str> = numbers.split('.').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+        let parse = |part: &str| part.parse::<u64>().map_err(|_| invalid());
+
+        Ok(Version::SemVer {
+            major: parse(parts[0])?,
+            minor: parse(parts[1])?,
+            patch: parse(parts[2])?,
+            pre_release,
+        })
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Version::Integer(n) => write!(f, "{}", n),
+            Version::SemVer { major, minor, patch, pre_release: None } => write!(f, "{}.{}.{}", major, minor, patch),
+            Version::SemVer { major, minor, patch, pre_release: Some(pre) } => {
+                write!(f, "{}.{}.{}-{}", major, minor, patch, pre)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!("3".parse::<Version>().unwrap(), Version::Integer(3));
+        assert_eq!("v3".parse::<Version>().unwrap(), Version::Integer(3));
+        assert_eq!(
+            "1.2.0-rc1".parse::<Version>().unwrap(),
+            Version::SemVer { major: 1, minor: 2, patch: 0, pre_release: Some("rc1".to_string()) }
+        );
+        assert_eq!("1.2.0".parse::<Version>().unwrap().to_string(), "1.2.0");
+        assert!("1.2".parse::<Version>().is_err());
+        assert!("final".parse::<Version>().is_err());
+        assert!("1.2.0-".parse::<Version>().is_err());
+    }
+
+    #[test]
+    fn test_next_version() {
+        assert_eq!(Version::Integer(3).next(), Version::Integer(4));
+        assert_eq!("1.2.0-rc1".parse::<Version>().unwrap().next().to_string(), "1.2.1");
+    }
+}
@@ -3,7 +3,7 @@ mod data;
 mod utils;
 
 use clap::{arg, Command, crate_version};
-use std::io;
+use std::{env, io};
 use std::process::exit;
 use log::{debug, error, info};
 
@@ -17,6 +17,12 @@ fn cli() -> Command {
             Command::new("init")
                 .about("Initialises bucket repository")
                 .arg(arg!(<NAME> "Name of the repository"))
+                .arg(
+                    arg!(--storage <MODE> "Where blobs are stored: bucket keeps them per bucket, repository shares one object store between all buckets")
+                        .required(false)
+                        .default_value("bucket")
+                        .value_parser(["bucket", "repository"]),
+                )
                 .arg_required_else_help(true),
         )
         .subcommand(
@@ -34,10 +40,171 @@ fn cli() -> Command {
                         .value_parser(clap::builder::NonEmptyStringValueParser::new()),
                 )
         )
+        .subcommand(
+            Command::new("finalize")
+                .about("Finalizes the last commit of the bucket with a version number")
+                .arg(arg!([VERSION] "Version number, an integer or a semantic version. Defaults to the next version")),
+        )
+        .subcommand(Command::new("versions").about("Lists the finalized versions of the bucket"))
+        .subcommand(
+            Command::new("expect")
+                .about("Sets the expectations of the bucket")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("bucket")
+                        .about("Expects the existence of a bucket with the given name")
+                        .arg(arg!(<NAME> "Name of the bucket"))
+                        .arg_required_else_help(true),
+                )
+                .subcommand(
+                    Command::new("set")
+                        .about("Sets what to expect in the bucket")
+                        .subcommand_required(true)
+                        .subcommand(
+                            Command::new("file")
+                                .about("Expects a file of the given type in the bucket")
+                                .arg(arg!(<TYPE> "File type, the extension of the file e.g. png"))
+                                .arg(arg!([DIRECTORY] "Directory in the bucket the file is expected in"))
+                                .arg_required_else_help(true),
+                        ),
+                )
+                .subcommand(Command::new("list").about("Lists the expectations of the bucket"))
+                .subcommand(
+                    Command::new("remove")
+                        .about("Removes an expectation by its number in `bucket expect list`")
+                        .arg(arg!(<NUMBER> "Number of the expectation").value_parser(clap::value_parser!(usize)))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("link")
+                .about("Creates a one way link between two buckets")
+                .arg(arg!(<FROM> "Directory or name of the bucket providing the output"))
+                .arg(arg!(<TO> "Directory or name of the bucket receiving the output as input"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("unlink")
+                .about("Removes the link between two buckets")
+                .arg(arg!(<FROM> "Directory or name of the upstream bucket"))
+                .arg(arg!(<TO> "Directory or name of the downstream bucket"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(Command::new("links").about("Lists the upstream and downstream buckets of the bucket"))
+        .subcommand(Command::new("check").about("Checks if all expectations of the bucket are met"))
         .subcommand(
             Command::new("status")
                 .about("Displays the status of the bucket")
         )
+        .subcommand(
+            Command::new("revert")
+                .about("Discards local changes and restores files from the last commit")
+                .arg(arg!(<FILE> "File to restore, or 'all' to restore every file in the bucket"))
+                .arg(arg!(-y --yes "Do not ask for confirmation before discarding local modifications"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("rollback")
+                .about("Restores files from the commit with the given id")
+                .arg(arg!(<FILE> "File to restore, or 'all' to restore every file in the commit"))
+                .arg(arg!(<COMMIT> "Id, or the start of the id, of the commit to restore from"))
+                .arg(arg!(-d --delete "Delete files which did not exist in the commit when restoring all files"))
+                .arg(arg!(-y --yes "Do not ask for confirmation before discarding local modifications"))
+                .arg_required_else_help(true),
+        )
+        .subcommand(
+            Command::new("stash")
+                .about("Stashes local changes and resets the bucket to the last commit")
+                .args_conflicts_with_subcommands(true)
+                .arg(arg!(-n --name <NAME> "Name of the stash").required(false))
+                .subcommand(
+                    Command::new("restore")
+                        .about("Reapplies a stash and removes it, the most recent stash if no name is given")
+                        .arg(arg!([NAME] "Name of the stash")),
+                )
+                .subcommand(Command::new("list").about("Lists the stashes of the bucket"))
+                .subcommand(
+                    Command::new("drop")
+                        .about("Removes a stash without applying it")
+                        .arg(arg!(<NAME> "Name of the stash"))
+                        .arg_required_else_help(true),
+                ),
+        )
+        .subcommand(
+            Command::new("list")
+                .about("Lists all buckets in the repository")
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Lists all commits in the bucket, newest first")
+                .arg(
+                    arg!(-n --limit <COUNT> "Maximum number of commits to show")
+                        .required(false)
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(arg!(-f --files "Show the changed files of every commit")),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Updates the repository database to the schema of this version of buckets")
+        )
+        .subcommand(
+            Command::new("storage")
+                .about("Shows how the blobs of the repository are stored")
+                .subcommand(
+                    Command::new("migrate")
+                        .about("Moves the blobs of every bucket into the object store shared by all buckets"),
+                ),
+        )
+        .subcommand(
+            Command::new("pack")
+                .about("Gathers the loose blobs of the repository into packfiles")
+        )
+        .subcommand(
+            Command::new("stats")
+                .about("Shows how much space the blobs of the repository take up and what delta compression saves")
+        )
+        .subcommand(
+            Command::new("prune")
+                .about("Prunes the files of the commits that the retention rules of the config no longer keep")
+                .arg(arg!(--"dry-run" "List the commits that would be pruned without pruning them")),
+        )
+        .subcommand(
+            Command::new("fsck")
+                .about("Checks the repository database against the blobs and buckets on disk")
+                .arg(arg!(--repair "Repair the problems that can be repaired")),
+        )
+        .subcommand(
+            Command::new("gc")
+                .about("Removes the blobs that no commit, stash or finalized version references")
+                .arg(arg!(--"dry-run" "Show how many bytes can be reclaimed without removing anything"))
+                .arg(arg!(--quarantine "Move unreachable blobs to .buckets/quarantine instead of deleting them"))
+                .arg(
+                    arg!(--"grace-period" <DAYS> "Keep unreachable blobs younger than this many days, defaults to the config")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u64)),
+                ),
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Shows a commit with its changes and the files of its snapshot")
+                .arg(arg!(<COMMIT> "Id of the commit, or an unambiguous prefix of the id"))
+                .arg_required_else_help(true),
+        )
+}
+
+// Checks if a command writes to the repository, only those take the repository lock
+fn modifies_repository(matches: &clap::ArgMatches) -> bool {
+    match matches.subcommand() {
+        Some(("system", _)) | Some(("versions", _)) | Some(("links", _)) | Some(("check", _)) | Some(("status", _))
+        | Some(("list", _)) | Some(("history", _)) | Some(("stats", _)) | Some(("show", _)) => false,
+        Some(("expect", args)) => args.subcommand_name() != Some("list"),
+        Some(("stash", args)) => args.subcommand_name() != Some("list"),
+        Some(("storage", args)) => args.subcommand_name() == Some("migrate"),
+        Some(("prune", args)) | Some(("gc", args)) => !args.get_flag("dry-run"),
+        Some(("fsck", args)) => args.get_flag("repair"),
+        _ => true,
+    }
 }
 
 fn main() {
@@ -45,13 +212,62 @@ fn main() {
 
     let matches = cli().get_matches();
 
+    // Held until the process exits, so commands that change the repository don't run at the same time
+    let mut _lock = None;
+    if let Some(repo_root) = env::current_dir().ok().and_then(|dir| utils::checks::find_repo_root(&dir)) {
+        let pending = match utils::migrations::repository_schema_version(&repo_root) {
+            Ok(version) if version < utils::migrations::SCHEMA_VERSION => Some(version),
+            Ok(_) => None,
+            Err(e) => {
+                eprintln!("Can not open repository: {}", e);
+                exit(1)
+            }
+        };
+
+        if modifies_repository(&matches) {
+            match utils::lock::lock_repository(&repo_root) {
+                Ok(lock) => _lock = Some(lock),
+                Err(e) => {
+                    eprintln!("Can not lock repository: {}", e);
+                    exit(1)
+                }
+            }
+        } else if let Some(version) = pending {
+            // Commands that only read the repository never write to it, not even to migrate the database
+            eprintln!(
+                "Can not open repository: {}",
+                utils::errors::BucketError::SchemaTooOld {
+                    found: version,
+                    supported: utils::migrations::SCHEMA_VERSION,
+                }
+            );
+            exit(1)
+        }
+
+        // Bring the database of an older repository up to date under the lock, unless that is the command itself
+        if pending.is_some() && matches.subcommand_name() != Some("migrate") {
+            match utils::migrations::migrate_repository(&repo_root) {
+                Ok(applied) if applied.is_empty() => {}
+                Ok(_) => eprintln!(
+                    "Migrated repository database to schema version {}",
+                    utils::migrations::SCHEMA_VERSION
+                ),
+                Err(e) => {
+                    eprintln!("Can not open repository: {}", e);
+                    exit(1)
+                }
+            }
+        }
+    }
+
     match matches.subcommand() {
         None => {}
         Some(("system", _)) => commands::version::execute(&mut io::stdout()).unwrap(),
         Some(("init", sub_matches)) => {
             let arg = sub_matches.get_one::<String>("NAME").unwrap();
+            let storage = sub_matches.get_one::<String>("storage").unwrap().parse().unwrap();
 
-            if let Err(e) = commands::init::execute(&arg.to_string()) {
+            if let Err(e) = commands::init::execute(&arg.to_string(), storage) {
                 eprintln!("Can not create repository: {}", e);
                 exit(1)
             } else {
@@ -86,17 +302,250 @@ fn main() {
                 exit(0)
             }
         }
+        Some(("finalize", sub_matches)) => {
+            let version = sub_matches.get_one::<String>("VERSION").map(|v| v.as_str());
+
+            if let Err(e) = commands::finalize::execute(version) {
+                eprintln!("Can not finalize bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("versions", _)) => {
+            if let Err(e) = commands::versions::execute() {
+                eprintln!("Can not list versions of the bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("expect", sub_matches)) => {
+            use commands::expect::ExpectAction;
+            use data::expectation::Expectation;
+
+            let action = match sub_matches.subcommand() {
+                Some(("bucket", args)) => ExpectAction::Add(Expectation::Bucket {
+                    name: args.get_one::<String>("NAME").unwrap().to_string(),
+                }),
+                Some(("set", set_matches)) => match set_matches.subcommand() {
+                    Some(("file", args)) => ExpectAction::Add(Expectation::File {
+                        file_type: args.get_one::<String>("TYPE").unwrap().to_string(),
+                        directory: args.get_one::<String>("DIRECTORY").cloned(),
+                    }),
+                    _ => unreachable!("subcommand is required"),
+                },
+                Some(("remove", args)) => ExpectAction::Remove(*args.get_one::<usize>("NUMBER").unwrap()),
+                _ => ExpectAction::List,
+            };
+
+            if let Err(e) = commands::expect::execute(&action) {
+                eprintln!("Can not set expectation: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("link", sub_matches)) | Some(("unlink", sub_matches)) => {
+            let from = sub_matches.get_one::<String>("FROM").unwrap().to_string();
+            let to = sub_matches.get_one::<String>("TO").unwrap().to_string();
+            let action = match matches.subcommand_name() {
+                Some("link") => commands::link::LinkAction::Link { from, to },
+                _ => commands::link::LinkAction::Unlink { from, to },
+            };
+
+            if let Err(e) = commands::link::execute(&action) {
+                eprintln!("Can not change link: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("links", _)) => {
+            if let Err(e) = commands::link::execute(&commands::link::LinkAction::Show) {
+                eprintln!("Can not list links: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("check", _)) => {
+            match commands::check::execute() {
+                Ok(true) => exit(0),
+                Ok(false) => exit(1),
+                Err(e) => {
+                    eprintln!("Can not check expectations: {}", e);
+                    exit(2)
+                }
+            }
+        }
         Some(("status", _)) => {
             match commands::status::execute() {
                 Ok(_) => {
                     exit(0)
                 }
                 Err(e) => {
-                    error!("Can not get status of the bucket: {}", e);
+                    eprintln!("Can not get status of the bucket: {}", e);
                     exit(1)
                 }
             }
         }
+        Some(("revert", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").unwrap();
+            let target = match file.as_str() {
+                "all" => commands::revert::RevertTarget::All,
+                _ => commands::revert::RevertTarget::File(file.to_string()),
+            };
+
+            if let Err(e) = commands::revert::execute(&target, sub_matches.get_flag("yes")) {
+                eprintln!("Can not revert bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("rollback", sub_matches)) => {
+            let file = sub_matches.get_one::<String>("FILE").unwrap();
+            let target = match file.as_str() {
+                "all" => commands::rollback::RollbackTarget::All,
+                _ => commands::rollback::RollbackTarget::File(file.to_string()),
+            };
+            let options = commands::rollback::RollbackOptions {
+                commit_id: sub_matches.get_one::<String>("COMMIT").unwrap().to_string(),
+                delete: sub_matches.get_flag("delete"),
+                force: sub_matches.get_flag("yes"),
+            };
+
+            if let Err(e) = commands::rollback::execute(&target, &options) {
+                eprintln!("Can not rollback bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("stash", sub_matches)) => {
+            let action = match sub_matches.subcommand() {
+                Some(("restore", args)) => commands::stash::StashAction::Restore(args.get_one::<String>("NAME").cloned()),
+                Some(("list", _)) => commands::stash::StashAction::List,
+                Some(("drop", args)) => commands::stash::StashAction::Drop(args.get_one::<String>("NAME").unwrap().to_string()),
+                _ => commands::stash::StashAction::Save(sub_matches.get_one::<String>("name").cloned()),
+            };
+
+            if let Err(e) = commands::stash::execute(&action) {
+                eprintln!("Can not stash bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("list", _)) => {
+            if let Err(e) = commands::list::execute() {
+                eprintln!("Can not list buckets: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+        Some(("history", sub_matches)) => {
+            let options = commands::history::HistoryOptions {
+                limit: sub_matches.get_one::<usize>("limit").copied(),
+                show_files: sub_matches.get_flag("files"),
+            };
+
+            if let Err(e) = commands::history::execute(&options) {
+                eprintln!("Can not show history of the bucket: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("migrate", _)) => {
+            if let Err(e) = commands::migrate::execute() {
+                eprintln!("Can not migrate repository: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("pack", _)) => {
+            if let Err(e) = commands::pack::execute() {
+                eprintln!("Can not pack repository: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("stats", _)) => {
+            if let Err(e) = commands::stats::execute() {
+                eprintln!("Can not show statistics: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("prune", sub_matches)) => {
+            if let Err(e) = commands::prune::execute(sub_matches.get_flag("dry-run")) {
+                eprintln!("Can not prune repository: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("fsck", sub_matches)) => {
+            match commands::fsck::execute(sub_matches.get_flag("repair")) {
+                Ok(true) => exit(0),
+                Ok(false) => exit(1),
+                Err(e) => {
+                    eprintln!("Can not check repository: {}", e);
+                    exit(2)
+                }
+            }
+        }
+
+        Some(("gc", sub_matches)) => {
+            let options = commands::gc::GcOptions {
+                dry_run: sub_matches.get_flag("dry-run"),
+                quarantine: sub_matches.get_flag("quarantine"),
+                grace_period_days: sub_matches.get_one::<u64>("grace-period").copied(),
+            };
+
+            if let Err(e) = commands::gc::execute(&options) {
+                eprintln!("Can not collect garbage: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("storage", sub_matches)) => {
+            let result = match sub_matches.subcommand() {
+                Some(("migrate", _)) => commands::storage::migrate(),
+                _ => commands::storage::execute(),
+            };
+
+            if let Err(e) = result {
+                eprintln!("Can not update storage: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
+
+        Some(("show", sub_matches)) => {
+            let commit_id = sub_matches.get_one::<String>("COMMIT").unwrap();
+
+            if let Err(e) = commands::show::execute(commit_id) {
+                eprintln!("Can not show commit: {}", e);
+                exit(1)
+            } else {
+                exit(0)
+            }
+        }
 
         _ => commands::version::execute(&mut io::stdout()).unwrap(),
     }
@@ -72,10 +72,21 @@ pub fn is_valid_bucket_info(dir_path: &Path) -> bool {
 /// Returns `Some(PathBuf)` containing the path to the found bucket repository directory or `None` if not found.
 ///
 pub fn find_bucket_repo(dir_path: &Path) -> Option<PathBuf> {
-    match find_directory_in_parents(dir_path, ".buckets") {
-        Some(path) => Some(path),
-        None => None,
-    }
+    find_directory_in_parents(dir_path, ".buckets")
+}
+
+/// Searches for the top level directory of a bucket repository, the directory containing `.buckets`.
+///
+/// # Arguments
+///
+/// * `dir_path` - The path to start the search from.
+///
+/// # Returns
+///
+/// Returns `Some(PathBuf)` containing the top level directory of the repository or `None` if not found.
+///
+pub fn find_repo_root(dir_path: &Path) -> Option<PathBuf> {
+    find_bucket_repo(dir_path).and_then(|path| path.parent().map(Path::to_path_buf))
 }
 
 /// Searches for a bucket directory in the parent directories.
@@ -89,10 +100,7 @@ pub fn find_bucket_repo(dir_path: &Path) -> Option<PathBuf> {
 /// Returns `Some(PathBuf)` containing the path to the found bucket directory or `None` if not found.
 ///
 pub fn find_bucket(dir_path: &Path) -> Option<PathBuf> {
-    match find_directory_in_parents(dir_path, ".b") {
-        Some(path) => Some(path),
-        None => None,
-    }
+    find_directory_in_parents(dir_path, ".b")
 }
 
 #[cfg(test)]
@@ -0,0 +1,185 @@
+use std::io;
+use std::io::Read;
+
+/// Splits a stream into chunks at positions determined by the content, using FastCDC.
+///
+/// A rolling gear hash is calculated over the data and a chunk ends where the hash matches a mask. Because
+/// the boundaries depend only on the bytes right before them, inserting or removing data only changes the
+/// chunks around the edit, the chunks after it are found again and can be reused from earlier versions.
+///
+/// Chunks are at least `min_size` and at most `max_size` bytes, except for the last chunk of the stream
+/// which can be smaller. Normalized chunking makes a cut less likely before `average_size` and more likely
+/// after it, which keeps most chunks close to the average size.
+pub(crate) struct Chunker<R: Read> {
+    reader: R,
+    buffer: Vec<u8>,
+    min_size: usize,
+    average_size: usize,
+    max_size: usize,
+    mask_small: u64,
+    mask_large: u64,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    /// Creates a chunker with chunks of around `average_size` bytes, which is rounded up to a power of two.
+    pub(crate) fn new(reader: R, average_size: usize) -> Chunker<R> {
+        let average_size = rounded_average_size(average_size);
+        let bits = average_size.trailing_zeros();
+
+        Chunker {
+            reader,
+            buffer: Vec::new(),
+            min_size: average_size / 4,
+            average_size,
+            max_size: max_chunk_size(average_size),
+            mask_small: mask(bits + 2),
+            mask_large: mask(bits - 2),
+            eof: false,
+        }
+    }
+
+    // Finds the end of the first chunk in data, which holds at least max_size bytes unless the stream ended
+    fn cut_point(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+
+        let end = data.len().min(self.max_size);
+        let normal = end.min(self.average_size);
+        let mut hash: u64 = 0;
+
+        for (i, byte) in data.iter().enumerate().take(normal).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask_small == 0 {
+                return i + 1;
+            }
+        }
+        for (i, byte) in data.iter().enumerate().take(end).skip(normal) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask_large == 0 {
+                return i + 1;
+            }
+        }
+
+        end
+    }
+
+    // Reads from the stream until the buffer holds max_size bytes or the stream ends
+    fn fill_buffer(&mut self) -> io::Result<()> {
+        let mut read_buffer = [0u8; 64 * 1024];
+        while !self.eof && self.buffer.len() < self.max_size {
+            let wanted = read_buffer.len().min(self.max_size - self.buffer.len());
+            match self.reader.read(&mut read_buffer[..wanted]) {
+                Ok(0) => self.eof = true,
+                Ok(read) => self.buffer.extend_from_slice(&read_buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for Chunker<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.fill_buffer() {
+            return Some(Err(e));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let cut = self.cut_point(&self.buffer);
+        let rest = self.buffer.split_off(cut);
+        Some(Ok(std::mem::replace(&mut self.buffer, rest)))
+    }
+}
+
+/// The largest chunk a chunker with chunks of around `average_size` bytes creates.
+pub(crate) fn max_chunk_size(average_size: usize) -> usize {
+    rounded_average_size(average_size) * 4
+}
+
+fn rounded_average_size(average_size: usize) -> usize {
+    average_size.max(MIN_AVERAGE_SIZE).next_power_of_two()
+}
+
+/// Smallest average chunk size, smaller chunks cost more in bookkeeping than they save.
+const MIN_AVERAGE_SIZE: usize = 256;
+
+// A mask with the highest bits of the hash set, these depend on the most bytes of the rolling window
+const fn mask(bits: u32) -> u64 {
+    u64::MAX << (64 - bits)
+}
+
+/// Random values for every byte value, added to the rolling hash.
+///
+/// The values are generated with splitmix64 from a fixed seed. They must never change, different values
+/// would move the chunk boundaries and chunks stored by earlier versions would no longer be reused.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x6275_636b_6574_7321;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pseudo random test data, chunk boundaries are only found in data that isn't repetitive
+    fn random_data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn chunks(data: &[u8]) -> Vec<Vec<u8>> {
+        Chunker::new(data, 1024).collect::<io::Result<Vec<_>>>().unwrap()
+    }
+
+    #[test]
+    fn test_chunks_cover_the_data() {
+        let data = random_data(100_000, 1);
+        let chunks = chunks(&data);
+
+        assert!(chunks.len() > 10);
+        assert_eq!(chunks.concat(), data);
+        for chunk in chunks.iter().take(chunks.len() - 1) {
+            assert!(chunk.len() >= 256 && chunk.len() <= 4096);
+        }
+        assert!(Chunker::new(&[][..], 1024).next().is_none());
+    }
+
+    #[test]
+    fn test_insert_keeps_later_chunks() {
+        let data = random_data(100_000, 2);
+        let mut edited = data.clone();
+        edited.insert(50_000, 42);
+
+        let original = chunks(&data);
+        let changed = chunks(&edited);
+        let reused = changed.iter().filter(|chunk| original.contains(chunk)).count();
+
+        assert!(reused >= changed.len() - 3, "only {} of {} chunks reused", reused, changed.len());
+    }
+}
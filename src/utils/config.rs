@@ -1,11 +1,15 @@
 use crate::utils::checks;
 use crate::utils::checks::find_directory_in_parents;
+use crate::utils::glob;
+use crate::utils::storage::write_atomically;
 use rusqlite::Connection;
 use serde::{Deserialize, Serialize};
 use std::env;
+use std::fmt::{Display, Formatter};
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use toml::to_string;
 
 #[derive(Serialize, Deserialize)]
@@ -13,6 +17,240 @@ pub(crate) struct RepositoryConfig {
     pub ntp_server: String,
     pub ip_check: String,
     pub url_check: String,
+    /// Name recorded as the author of commits, defaults to the name of the logged in user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Where the blobs of the buckets are stored
+    #[serde(default)]
+    pub storage: StorageMode,
+    /// How large files are split into chunks
+    #[serde(default)]
+    pub chunking: ChunkingConfig,
+    /// When small blobs are gathered into packfiles
+    #[serde(default)]
+    pub packing: PackingConfig,
+    /// Which files are stored as a delta against their previous version
+    #[serde(default)]
+    pub delta: DeltaConfig,
+    /// Removal of blobs that nothing references anymore
+    #[serde(default)]
+    pub gc: GcConfig,
+    /// How files are compressed, the first rule with a pattern matching the path of a file is used
+    #[serde(default = "default_compression_rules")]
+    pub compression: Vec<CompressionRule>,
+    /// Which commits of a bucket `bucket prune` keeps, the first rule with a pattern matching the bucket name is used
+    #[serde(default)]
+    pub retention: Vec<RetentionRule>,
+}
+
+/// Which commits of the buckets matching a glob pattern keep their files, the other commits are pruned.
+///
+/// A commit is kept when it is one of the last `keep_last` commits or younger than `keep_days` days. The head
+/// commit of the bucket and finalized versions are always kept. A rule without either limit prunes nothing.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RetentionRule {
+    pub bucket: String,
+    pub keep_last: Option<usize>,
+    pub keep_days: Option<u64>,
+}
+
+/// Compresses the files matching a glob pattern with a codec.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CompressionRule {
+    pub pattern: String,
+    pub codec: Codec,
+}
+
+/// How the content of a blob is compressed, recorded in every blob so any of them can be decoded.
+///
+/// Written as `none`, `zstd <level>` or `zstd-long <level>` in the config and in the blobs.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Codec {
+    /// Stored as it is, for formats that are compressed already
+    None,
+    /// Compressed with zstd at a level from 1 to 22
+    Zstd(i32),
+    /// Compressed with zstd with a large window, finds repetitions far apart in large files
+    ZstdLong(i32),
+}
+
+/// Codec of files that match no compression rule.
+pub const DEFAULT_CODEC: Codec = Codec::Zstd(3);
+
+impl Display for Codec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Codec::None => write!(f, "none"),
+            Codec::Zstd(level) => write!(f, "zstd {}", level),
+            Codec::ZstdLong(level) => write!(f, "zstd-long {}", level),
+        }
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let name = parts.next().unwrap_or_default();
+        let level = parts.next();
+        if name == "none" && level.is_some() {
+            return Err(format!("Invalid codec {}, none has no level", s));
+        }
+        let level = match level {
+            Some(level) => level.parse::<i32>().map_err(|_| format!("Invalid compression level in codec {}", s))?,
+            None => DEFAULT_LEVEL,
+        };
+        if parts.next().is_some() || !(1..=22).contains(&level) {
+            return Err(format!("Invalid codec {}, the level goes from 1 to 22", s));
+        }
+
+        match name {
+            "none" => Ok(Codec::None),
+            "zstd" => Ok(Codec::Zstd(level)),
+            "zstd-long" => Ok(Codec::ZstdLong(level)),
+            _ => Err(format!("Invalid codec {}, expected none, zstd <level> or zstd-long <level>", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Codec {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Codec> for String {
+    fn from(codec: Codec) -> Self {
+        codec.to_string()
+    }
+}
+
+/// Level of a zstd codec without a level.
+const DEFAULT_LEVEL: i32 = 3;
+
+/// Default compression for common game asset formats.
+///
+/// Images, audio, video and archives are compressed already and are stored as they are. Text formats
+/// compress very well and are small, so they get a high level. Large binary scene and model files have
+/// repetitions far apart and use the long window.
+fn default_compression_rules() -> Vec<CompressionRule> {
+    let compressed = ["*.png", "*.jpg", "*.jpeg", "*.ogg", "*.mp3", "*.mp4", "*.webm", "*.zip", "*.gz", "*.ktx2"];
+    let text = ["*.json", "*.lua", "*.txt", "*.xml", "*.yaml", "*.yml", "*.csv", "*.ini", "*.mat", "*.hlsl", "*.glsl"];
+    let large = ["*.fbx", "*.blend", "*.uasset", "*.umap", "*.psd", "*.wav", "*.tif", "*.tiff", "*.exr"];
+
+    let rules = |patterns: &[&str], codec: Codec| {
+        patterns
+            .iter()
+            .map(|pattern| CompressionRule {
+                pattern: pattern.to_string(),
+                codec,
+            })
+            .collect::<Vec<_>>()
+    };
+    [rules(&compressed, Codec::None), rules(&text, Codec::Zstd(19)), rules(&large, Codec::ZstdLong(3))].concat()
+}
+
+/// Content defined chunking of large files, so a small edit to a large file only stores the changed chunks.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChunkingConfig {
+    /// Files of at least this many bytes are split into chunks, smaller files are stored as one blob
+    pub threshold: u64,
+    /// Average size of a chunk in bytes
+    pub average_size: u64,
+}
+
+/// Packing of loose blobs into packfiles, so the storage doesn't hold a file for every small asset.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PackingConfig {
+    /// A commit packs the loose blobs once the storage holds this many of them, 0 turns automatic packing off
+    pub auto_pack: usize,
+    /// Blobs larger than this many bytes stay loose
+    pub max_blob_size: u64,
+}
+
+/// Delta compression for formats that don't chunk well, like compressed Photoshop files or FBX models.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DeltaConfig {
+    /// A modified file matching one of these glob patterns is stored as a delta against its previous version
+    pub patterns: Vec<String>,
+    /// Most deltas applied after each other to restore a file, the next version is stored in full again
+    pub max_chain_depth: usize,
+    /// Files, or previous versions, larger than this many bytes are stored in full or in chunks instead, both
+    /// are held in memory to make or apply a delta. Sizes above 512 MiB are treated as 512 MiB.
+    #[serde(default = "default_max_delta_file_size")]
+    pub max_file_size: u64,
+}
+
+fn default_max_delta_file_size() -> u64 {
+    256 * 1024 * 1024
+}
+
+/// Garbage collection of blobs that no commit, stash or finalized version references.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct GcConfig {
+    /// Unreferenced blobs younger than this many days are kept, they may belong to a commit that is running
+    pub grace_period_days: u64,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        GcConfig { grace_period_days: 14 }
+    }
+}
+
+impl Default for DeltaConfig {
+    fn default() -> Self {
+        DeltaConfig {
+            patterns: Vec::new(),
+            max_chain_depth: 10,
+            max_file_size: default_max_delta_file_size(),
+        }
+    }
+}
+
+impl Default for PackingConfig {
+    fn default() -> Self {
+        PackingConfig {
+            auto_pack: 1000,
+            max_blob_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        ChunkingConfig {
+            threshold: 8 * 1024 * 1024,
+            average_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Where the blobs of the buckets in a repository are stored.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageMode {
+    /// Every bucket stores its own blobs in `.b/storage`
+    #[default]
+    Bucket,
+    /// All buckets share one object store in `.buckets/objects`, a file is stored once for the whole repository
+    Repository,
+}
+
+impl FromStr for StorageMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bucket" => Ok(StorageMode::Bucket),
+            "repository" => Ok(StorageMode::Repository),
+            _ => Err(format!("Invalid storage mode {}, expected bucket or repository", s)),
+        }
+    }
 }
 
 impl RepositoryConfig {
@@ -31,7 +269,41 @@ impl RepositoryConfig {
         file.read_to_string(&mut toml_string)
             .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
 
-        Ok(toml::from_str(&toml_string).unwrap())
+        toml::from_str(&toml_string).map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("Invalid repository config {}: {}", buckets_repo_path.join("config").display(), e),
+            )
+        })
+    }
+
+    /// Writes the config to the `config` file in the `.buckets` directory `buckets_dir`, replacing the file
+    /// at once so an interrupted write leaves the old config.
+    pub(crate) fn write(&self, buckets_dir: &Path) -> std::io::Result<()> {
+        let toml_string = to_string(self).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        write_atomically(&buckets_dir.join("config"), toml_string.as_bytes())
+    }
+
+    /// The codec of the first compression rule matching `path`, a path relative to the bucket root.
+    pub(crate) fn codec_for(&self, path: &str) -> Codec {
+        self.compression
+            .iter()
+            .find(|rule| glob::matches(&rule.pattern, path))
+            .map_or(DEFAULT_CODEC, |rule| rule.codec)
+    }
+
+    /// The first retention rule whose pattern matches the name of a bucket.
+    pub(crate) fn retention_for(&self, bucket_name: &str) -> Option<&RetentionRule> {
+        self.retention.iter().find(|rule| glob::matches(&rule.bucket, bucket_name))
+    }
+
+    /// The author of new commits, taken from the config or else from the environment.
+    pub(crate) fn author(&self) -> String {
+        self.author
+            .clone()
+            .or_else(|| env::var("USER").ok())
+            .or_else(|| env::var("USERNAME").ok())
+            .unwrap_or_else(|| "unknown".to_string())
     }
 }
 
@@ -41,6 +313,14 @@ impl Default for RepositoryConfig {
             ntp_server: "pool.ntp.org".to_string(),
             ip_check: "8.8.8.8".to_string(),
             url_check: "api.ipify.org".to_string(),
+            author: None,
+            storage: StorageMode::default(),
+            chunking: ChunkingConfig::default(),
+            packing: PackingConfig::default(),
+            delta: DeltaConfig::default(),
+            gc: GcConfig::default(),
+            compression: default_compression_rules(),
+            retention: Vec::new(),
         }
     }
 }
@@ -48,16 +328,20 @@ impl Default for RepositoryConfig {
 pub fn get_db_conn() -> rusqlite::Result<Connection> {
     let current_path = env::current_dir().unwrap();
     let db_location = checks::db_location(current_path.as_path());
-    Connection::open(db_location)
+    open_db(&db_location)
+}
+
+/// Opens the repository database with foreign keys enforced.
+pub fn open_db(db_location: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(db_location)?;
+    conn.pragma_update(None, "foreign_keys", true)?;
+    Ok(conn)
 }
 
-pub fn create_default_config(file_path: &Path) {
+pub fn create_config(file_path: &Path, config: &RepositoryConfig) {
     println!("Creating config files in {:?}", file_path.as_os_str());
 
-    let config = RepositoryConfig::default();
-    let toml_string = to_string(&config).unwrap();
-    let mut file = File::create(file_path.join("config")).unwrap();
-    file.write_all(toml_string.as_bytes()).unwrap();
+    config.write(file_path).unwrap();
 }
 
 
@@ -74,7 +358,7 @@ mod tests {
         fs::create_dir(&buckets_dir).unwrap();
 
         // Create and write to the file
-        create_default_config(&buckets_dir.as_path());
+        create_config(buckets_dir.as_path(), &RepositoryConfig::default());
 
         // Read the file
         let config = RepositoryConfig::from_file(temp_dir.path().to_path_buf()).unwrap();
@@ -83,5 +367,64 @@ mod tests {
         assert_eq!(config.ip_check, "8.8.8.8");
         assert_eq!(config.ntp_server, "pool.ntp.org");
         assert_eq!(config.url_check, "api.ipify.org");
+        assert_eq!(config.storage, StorageMode::Bucket);
+    }
+
+    #[test]
+    fn test_storage_mode() {
+        let temp_dir = tempdir().unwrap();
+        let buckets_dir = temp_dir.path().join(".buckets");
+        fs::create_dir(&buckets_dir).unwrap();
+
+        let config = RepositoryConfig {
+            storage: "repository".parse().unwrap(),
+            ..Default::default()
+        };
+        create_config(buckets_dir.as_path(), &config);
+
+        let config = RepositoryConfig::from_file(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(config.storage, StorageMode::Repository);
+        assert!("shared".parse::<StorageMode>().is_err());
+    }
+
+    #[test]
+    fn test_compression_rules() {
+        let temp_dir = tempdir().unwrap();
+        let buckets_dir = temp_dir.path().join(".buckets");
+        fs::create_dir(&buckets_dir).unwrap();
+        create_config(buckets_dir.as_path(), &RepositoryConfig::default());
+
+        let mut config = RepositoryConfig::from_file(temp_dir.path().to_path_buf()).unwrap();
+        assert_eq!(config.codec_for("textures/wall.PNG"), Codec::None);
+        assert_eq!(config.codec_for("scripts/ai.lua"), Codec::Zstd(19));
+        assert_eq!(config.codec_for("levels/one.umap"), Codec::ZstdLong(3));
+        assert_eq!(config.codec_for("notes.unknown"), DEFAULT_CODEC);
+
+        config.compression.insert(
+            0,
+            CompressionRule {
+                pattern: "textures/**".to_string(),
+                codec: "zstd 9".parse().unwrap(),
+            },
+        );
+        assert_eq!(config.codec_for("textures/wall.png"), Codec::Zstd(9));
+        assert!("zstd 23".parse::<Codec>().is_err());
+        assert!("brotli".parse::<Codec>().is_err());
+        assert!("none 5".parse::<Codec>().is_err());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let temp_dir = tempdir().unwrap();
+        let buckets_dir = temp_dir.path().join(".buckets");
+        fs::create_dir(&buckets_dir).unwrap();
+        create_config(buckets_dir.as_path(), &RepositoryConfig::default());
+
+        let config = fs::read_to_string(buckets_dir.join("config")).unwrap();
+        fs::write(buckets_dir.join("config"), config.replacen("\"zstd 19\"", "\"zstd 23\"", 1)).unwrap();
+
+        let error = RepositoryConfig::from_file(temp_dir.path().to_path_buf()).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("the level goes from 1 to 22"), "{}", error);
     }
 }
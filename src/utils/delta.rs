@@ -0,0 +1,107 @@
+use blake3::Hash;
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+/// First line of a blob that holds a file as a delta against an earlier version of the file.
+const DELTA_HEADER: &[u8] = b"buckets delta 1\n";
+
+/// The second line of a delta blob, which describes the delta.
+#[derive(Debug, PartialEq)]
+pub(crate) struct DeltaHeader {
+    /// Hash of the blob the delta was made against
+    pub base: Hash,
+    /// Number of deltas that have to be applied to get to this version, 1 when the base is stored in full
+    pub depth: usize,
+    /// Size of the content of the file
+    pub size: u64,
+}
+
+/// Writes a file as a delta against the content of an earlier version of the file.
+///
+/// The content is compressed with zstd using the earlier version as dictionary, so every part of the file that
+/// didn't change is stored as a reference into the base instead of as data. The window is large enough to
+/// reach back over the whole base, which is what `zstd --patch-from` does.
+pub(crate) fn write_delta<R: Read, W: Write>(writer: &mut W, input: &mut R, base: &[u8], header: &DeltaHeader) -> io::Result<()> {
+    writer.write_all(DELTA_HEADER)?;
+    writer.write_all(format!("{} {} {}\n", header.base, header.depth, header.size).as_bytes())?;
+
+    let mut encoder = zstd::stream::write::Encoder::with_dictionary(writer, 0, base)?;
+    encoder.window_log(window_log(base.len() as u64 + header.size))?;
+    encoder.long_distance_matching(true)?;
+    io::copy(input, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
+
+/// Reads the header of a delta blob, returns `None` without reading anything when the blob is no delta.
+pub(crate) fn read_delta_header<R: BufRead>(reader: &mut R) -> io::Result<Option<DeltaHeader>> {
+    if !reader.fill_buf()?.starts_with(DELTA_HEADER) {
+        return Ok(None);
+    }
+
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    line.clear();
+    reader.read_line(&mut line)?;
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("Invalid delta header: {}", line.trim_end()));
+    let mut fields = line.split_whitespace();
+    let header = DeltaHeader {
+        base: fields.next().and_then(|hash| Hash::from_hex(hash).ok()).ok_or_else(invalid)?,
+        depth: fields.next().and_then(|depth| depth.parse().ok()).ok_or_else(invalid)?,
+        size: fields.next().and_then(|size| size.parse().ok()).ok_or_else(invalid)?,
+    };
+
+    Ok(Some(header))
+}
+
+/// Applies a delta to the content of its base, `reader` is positioned after the header of the delta blob.
+pub(crate) fn apply_delta<R: BufRead, W: Write>(reader: R, base: &[u8], writer: &mut W) -> io::Result<()> {
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(reader, base)?;
+    decoder.window_log_max(MAX_WINDOW_LOG)?;
+    io::copy(&mut decoder, writer)?;
+    Ok(())
+}
+
+/// Largest window, in bits, that a delta is written with.
+const MAX_WINDOW_LOG: u32 = 30;
+
+/// Largest file, and base, stored as a delta. The window has to reach back over the base and the file together.
+pub(crate) const MAX_DELTA_SIZE: u64 = 1 << (MAX_WINDOW_LOG - 1);
+
+// The smallest window that covers size bytes
+fn window_log(size: u64) -> u32 {
+    (64 - size.leading_zeros()).clamp(10, MAX_WINDOW_LOG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delta_round_trip() -> io::Result<()> {
+        let base: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let mut content = base.clone();
+        content[100_000] ^= 0xff;
+        content.extend_from_slice(b"new layer");
+
+        let header = DeltaHeader {
+            base: blake3::hash(&base),
+            depth: 1,
+            size: content.len() as u64,
+        };
+        let mut delta = Vec::new();
+        write_delta(&mut delta, &mut content.as_slice(), &base, &header)?;
+        assert!(delta.len() < 5000, "delta of {} bytes", delta.len());
+
+        let mut reader = delta.as_slice();
+        assert_eq!(read_delta_header(&mut reader)?, Some(header));
+        let mut restored = Vec::new();
+        apply_delta(reader, &base, &mut restored)?;
+        assert_eq!(restored, content);
+
+        assert_eq!(read_delta_header(&mut &b"not a delta"[..])?, None);
+        Ok(())
+    }
+}
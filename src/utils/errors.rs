@@ -1,15 +1,18 @@
 use std::fmt::{Display, Formatter};
 use std::io;
 
+#[derive(Debug)]
 pub enum BucketError {
     IoError(io::Error),
     Sqlite(rusqlite::Error),
     #[allow(dead_code)]
     BucketAlreadyExists,
-    #[allow(dead_code)]
     NotInBucketRepo,
     InBucketRepo,
     NotAValidBucket,
+    UncommittedChanges,
+    SchemaTooNew { found: i64, supported: i64 },
+    SchemaTooOld { found: i64, supported: i64 },
 }
 
 impl Display for BucketError {
@@ -21,6 +24,17 @@ impl Display for BucketError {
             BucketError::NotInBucketRepo => write!(f, "Not in a bucket repository"),
             BucketError::InBucketRepo => write!(f, "Already in a bucket repository"),
             BucketError::NotAValidBucket => write!(f, "Not a valid bucket"),
+            BucketError::UncommittedChanges => write!(f, "Bucket has uncommitted changes, commit or stash them first"),
+            BucketError::SchemaTooNew { found, supported } => write!(
+                f,
+                "Repository database has schema version {}, this version of buckets supports up to version {}. Upgrade buckets to open it",
+                found, supported
+            ),
+            BucketError::SchemaTooOld { found, supported } => write!(
+                f,
+                "Repository database has schema version {}, this version of buckets needs version {}. Run `bucket migrate` to update it",
+                found, supported
+            ),
         }
     }
 }
@@ -0,0 +1,55 @@
+/// Checks if a path relative to the bucket root matches a glob pattern.
+///
+/// `*` matches any characters except `/`, `**` matches any characters including `/` and `?` matches a single
+/// character. A pattern without a `/` is matched against the file name only, so `*.psd` matches Photoshop
+/// files in every directory. Matching ignores the case of letters, asset extensions are often upper case.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let path = path.to_lowercase();
+
+    let subject = if pattern.contains('/') {
+        path.as_str()
+    } else {
+        path.rsplit('/').next().unwrap_or(&path)
+    };
+
+    matches_from(pattern.as_bytes(), subject.as_bytes())
+}
+
+fn matches_from(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = pattern[2..].strip_prefix(b"/").unwrap_or(&pattern[2..]);
+            (0..=path.len()).any(|skip| matches_from(rest, &path[skip..]))
+        }
+        Some(b'*') => {
+            let max = path.iter().position(|c| *c == b'/').unwrap_or(path.len());
+            (0..=max).any(|skip| matches_from(&pattern[1..], &path[skip..]))
+        }
+        Some(b'?') => !path.is_empty() && path[0] != b'/' && matches_from(&pattern[1..], &path[1..]),
+        Some(c) => path.first() == Some(c) && matches_from(&pattern[1..], &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_patterns() {
+        assert!(matches("*.psd", "concept/hero.PSD"));
+        assert!(matches("*.psd", "hero.psd"));
+        assert!(!matches("*.psd", "hero.psd.bak"));
+        assert!(matches("hero_??.fbx", "models/hero_01.fbx"));
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        assert!(matches("textures/*.png", "textures/wall.png"));
+        assert!(!matches("textures/*.png", "textures/old/wall.png"));
+        assert!(matches("textures/**/*.png", "textures/old/wall.png"));
+        assert!(matches("textures/**/*.png", "textures/wall.png"));
+        assert!(matches("**/*.json", "levels/one/spawn.json"));
+    }
+}
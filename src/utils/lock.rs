@@ -0,0 +1,51 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// File in `.buckets` that commands changing the repository lock while they run.
+const LOCK_FILE: &str = "lock";
+
+/// An exclusive lock on a repository, it is released when the lock is dropped or the process exits.
+pub(crate) struct RepositoryLock {
+    _file: File,
+}
+
+/// Takes the exclusive lock on the repository at `repo_root`, waiting for another command that holds it.
+///
+/// Only one command at a time writes blobs, packs or cleans up the storage, so recovering from an interrupted
+/// command never removes the temporary files of a command that is still running.
+pub(crate) fn lock_repository(repo_root: &Path) -> io::Result<RepositoryLock> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(repo_root.join(".buckets").join(LOCK_FILE))?;
+
+    if file.try_lock().is_err() {
+        eprintln!("Waiting for another bucket command to finish...");
+        file.lock()?;
+    }
+
+    Ok(RepositoryLock { _file: file })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_repository() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        fs::create_dir(temp_dir.path().join(".buckets"))?;
+
+        let lock = lock_repository(temp_dir.path())?;
+        let other = File::open(temp_dir.path().join(".buckets").join(LOCK_FILE))?;
+        assert!(other.try_lock().is_err());
+
+        drop(lock);
+        assert!(other.try_lock().is_ok());
+        Ok(())
+    }
+}
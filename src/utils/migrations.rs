@@ -0,0 +1,647 @@
+use crate::data::commit::{commit_id, ChangeKind, Commit, CommittedFile};
+use crate::data::manifest::{file_name, Manifest, ManifestEntry};
+use crate::utils::config::open_db;
+use crate::utils::errors::BucketError;
+use crate::utils::storage::upgrade_legacy_blob;
+use blake3::Hash;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::io;
+use std::path::Path;
+
+/// A change to the database schema. Migrations are applied in order and each one is applied only once,
+/// the schema version stored in the database is the version of the last applied migration.
+struct Migration {
+    version: i64,
+    description: &'static str,
+    apply: fn(&Connection, &Path) -> Result<(), BucketError>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create buckets, commits and files",
+        apply: create_initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "Add stashes, finalized versions, expectations, links and inputs",
+        apply: add_workflow_tables,
+    },
+    Migration {
+        version: 3,
+        description: "Record commit history per bucket with manifests and content addressed ids",
+        apply: add_commit_history,
+    },
+    Migration {
+        version: 4,
+        description: "Fix the types of commits.bucket_id and files.commit_id and add indexes",
+        apply: fix_column_types,
+    },
+    Migration {
+        version: 5,
+        description: "Count the references of every bucket to the blobs it stores",
+        apply: add_object_refs,
+    },
+    Migration {
+        version: 6,
+        description: "Add the chunk lists of files that are split into chunks",
+        apply: add_file_chunks,
+    },
+    Migration {
+        version: 7,
+        description: "Record when retention rules pruned the files of a commit",
+        apply: add_commit_pruning,
+    },
+    Migration {
+        version: 8,
+        description: "Record the commit a stash was saved on",
+        apply: add_stash_commits,
+    },
+];
+
+/// The schema version this version of buckets creates and understands.
+pub(crate) const SCHEMA_VERSION: i64 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// Reads the schema version of a database.
+///
+/// The version is stored in the `user_version` of the database. Repositories created before schema versions
+/// were introduced have version 0 but already contain the tables of the first migration.
+pub(crate) fn schema_version(conn: &Connection) -> Result<i64, BucketError> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version > 0 {
+        return Ok(version);
+    }
+
+    let has_buckets = conn
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'buckets'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    Ok(if has_buckets.is_some() { 1 } else { 0 })
+}
+
+/// Applies every migration newer than the schema version of the database, each in its own transaction.
+///
+/// Returns the version and description of the migrations that were applied. A database with a newer schema
+/// than this version of buckets understands is refused, it is never modified.
+pub(crate) fn migrate(conn: &mut Connection, repo_root: &Path) -> Result<Vec<(i64, &'static str)>, BucketError> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(BucketError::SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+
+    // Some migrations rebuild tables, which SQLite only allows while foreign keys are not enforced.
+    // The foreign keys are checked before each migration is committed instead.
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply_migrations(conn, repo_root, version);
+    conn.pragma_update(None, "foreign_keys", true)?;
+    let applied = result?;
+
+    // Write ahead logging keeps reads fast while a commit is written, the mode is stored in the database
+    // file and can't be changed inside a migration's transaction
+    if !applied.is_empty() {
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+    }
+
+    Ok(applied)
+}
+
+fn apply_migrations(conn: &mut Connection, repo_root: &Path, version: i64) -> Result<Vec<(i64, &'static str)>, BucketError> {
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > version) {
+        let tx = conn.transaction()?;
+        (migration.apply)(&tx, repo_root)?;
+
+        let violations: i64 = tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| row.get(0))?;
+        if violations > 0 {
+            return Err(BucketError::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Migration {} leaves {} rows with a broken reference", migration.version, violations),
+            )));
+        }
+
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        applied.push((migration.version, migration.description));
+    }
+
+    Ok(applied)
+}
+
+/// Reads the schema version of the database of a repository, refusing a newer schema than this version of
+/// buckets understands.
+pub(crate) fn repository_schema_version(repo_root: &Path) -> Result<i64, BucketError> {
+    let conn = open_db(&repo_root.join(".buckets").join("buckets.db"))?;
+    let version = schema_version(&conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(BucketError::SchemaTooNew {
+            found: version,
+            supported: SCHEMA_VERSION,
+        });
+    }
+    Ok(version)
+}
+
+/// Opens the database of a repository and brings its schema up to date.
+pub(crate) fn migrate_repository(repo_root: &Path) -> Result<Vec<(i64, &'static str)>, BucketError> {
+    let mut conn = open_db(&repo_root.join(".buckets").join("buckets.db"))?;
+    migrate(&mut conn, repo_root)
+}
+
+fn create_initial_schema(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "CREATE TABLE buckets (
+            id CHAR(36) PRIMARY KEY,
+            name TEXT NOT NULL,
+            path TEXT NOT NULL
+        );
+
+        CREATE TABLE commits (
+            id CHAR(36) PRIMARY KEY,
+            bucket_id INTEGER NOT NULL,
+            message TEXT NOT NULL,
+            created_at TEXT,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id)
+        );
+
+        CREATE TRIGGER set_timestamp_after_insert
+         AFTER INSERT ON commits
+         BEGIN
+             UPDATE commits SET created_at = CURRENT_TIMESTAMP WHERE rowid = NEW.rowid;
+         END;
+
+        CREATE TABLE files (
+            id CHAR(36) PRIMARY KEY,
+            commit_id INTEGER NOT NULL,
+            file_path TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            FOREIGN KEY (commit_id) REFERENCES commits (id),
+            UNIQUE (commit_id, file_path, hash)
+        );
+
+        CREATE TRIGGER AutoGenBucketsGUID
+             AFTER INSERT ON buckets
+             FOR EACH ROW
+             WHEN (NEW.id IS NULL)
+             BEGIN
+               UPDATE buckets SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
+                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
+                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
+                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
+             END;
+
+        CREATE TRIGGER AutoGenCommitsGUID
+             AFTER INSERT ON commits
+             FOR EACH ROW
+             WHEN (NEW.id IS NULL)
+             BEGIN
+               UPDATE commits SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
+                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
+                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
+                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
+             END;
+
+        CREATE TRIGGER AutoGenFilesGUID
+             AFTER INSERT ON files
+             FOR EACH ROW
+             WHEN (NEW.id IS NULL)
+             BEGIN
+               UPDATE files SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
+                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
+                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
+                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
+             END;",
+    )?;
+
+    Ok(())
+}
+
+fn add_workflow_tables(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "CREATE TABLE stashes (
+            id CHAR(36) PRIMARY KEY,
+            bucket_id CHAR(36) NOT NULL,
+            name TEXT NOT NULL,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            UNIQUE (bucket_id, name)
+        );
+
+        CREATE TABLE stash_files (
+            stash_id CHAR(36) NOT NULL,
+            file_path TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            FOREIGN KEY (stash_id) REFERENCES stashes (id),
+            UNIQUE (stash_id, file_path)
+        );
+
+        CREATE TABLE finalized_versions (
+            bucket_id CHAR(36) NOT NULL,
+            version TEXT NOT NULL,
+            commit_id TEXT NOT NULL,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            FOREIGN KEY (commit_id) REFERENCES commits (id),
+            UNIQUE (bucket_id, version)
+        );
+
+        CREATE TABLE expectations (
+            id CHAR(36) PRIMARY KEY,
+            bucket_id CHAR(36) NOT NULL,
+            kind TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            directory TEXT,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id)
+        );
+
+        CREATE TABLE links (
+            from_bucket_id CHAR(36) NOT NULL,
+            to_bucket_id CHAR(36) NOT NULL,
+            created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (from_bucket_id) REFERENCES buckets (id),
+            FOREIGN KEY (to_bucket_id) REFERENCES buckets (id),
+            UNIQUE (from_bucket_id, to_bucket_id)
+        );
+
+        CREATE TABLE inputs (
+            bucket_id CHAR(36) NOT NULL,
+            upstream_bucket_id CHAR(36) NOT NULL,
+            version TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            received_at TEXT DEFAULT CURRENT_TIMESTAMP,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            FOREIGN KEY (upstream_bucket_id) REFERENCES buckets (id),
+            UNIQUE (bucket_id, upstream_bucket_id, file_path)
+        );
+
+        CREATE TRIGGER finalized_versions_no_update
+         BEFORE UPDATE ON finalized_versions
+         BEGIN
+             SELECT RAISE(ABORT, 'finalized versions are immutable');
+         END;
+
+        CREATE TRIGGER finalized_versions_no_delete
+         BEFORE DELETE ON finalized_versions
+         BEGIN
+             SELECT RAISE(ABORT, 'finalized versions are immutable');
+         END;",
+    )?;
+
+    Ok(())
+}
+
+/// Adds parents, heads, change kinds, manifests and content addressed ids to the commits.
+///
+/// Existing commits are rewritten bucket by bucket, oldest first. Before this migration every commit
+/// stored the full list of files of the bucket, which becomes its manifest, and the files table keeps only
+/// the changes against the previous commit. Finalized versions didn't exist before this migration, so no
+/// other table refers to the old commit ids.
+fn add_commit_history(conn: &Connection, repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "ALTER TABLE buckets ADD COLUMN head_commit_id TEXT;
+        ALTER TABLE files ADD COLUMN change_kind TEXT NOT NULL DEFAULT 'unchanged';
+        ALTER TABLE files ADD COLUMN renamed_from TEXT;
+
+        DROP TRIGGER set_timestamp_after_insert;
+        DROP TRIGGER AutoGenCommitsGUID;
+
+        CREATE TABLE manifests (
+            id TEXT PRIMARY KEY
+        );
+
+        CREATE TABLE trees (
+            id TEXT PRIMARY KEY
+        );
+
+        CREATE TABLE manifest_trees (
+            manifest_id TEXT NOT NULL,
+            directory TEXT NOT NULL,
+            tree_id TEXT NOT NULL,
+            FOREIGN KEY (manifest_id) REFERENCES manifests (id),
+            FOREIGN KEY (tree_id) REFERENCES trees (id),
+            PRIMARY KEY (manifest_id, directory)
+        );
+
+        CREATE TABLE tree_entries (
+            tree_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            mode INTEGER NOT NULL,
+            FOREIGN KEY (tree_id) REFERENCES trees (id),
+            PRIMARY KEY (tree_id, name)
+        );
+
+        CREATE TABLE commits_new (
+            id TEXT PRIMARY KEY,
+            bucket_id INTEGER NOT NULL,
+            parent_id TEXT,
+            manifest_id TEXT NOT NULL,
+            author TEXT NOT NULL,
+            message TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            FOREIGN KEY (parent_id) REFERENCES commits (id),
+            FOREIGN KEY (manifest_id) REFERENCES manifests (id)
+        );",
+    )?;
+
+    let buckets: Vec<(String, String)> = conn
+        .prepare("SELECT id, path FROM buckets")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for (bucket_id, bucket_path) in buckets {
+        let storage_path = repo_root.join(bucket_path).join(".b").join("storage");
+        let commits: Vec<(String, String, Option<String>)> = conn
+            .prepare("SELECT id, message, created_at FROM commits WHERE bucket_id = ?1 ORDER BY created_at, rowid")?
+            .query_map(params![bucket_id], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<_, _>>()?;
+
+        let mut parent: Option<String> = None;
+        let mut previous = Commit::empty("");
+        for (old_id, message, created_at) in commits {
+            let current = Commit {
+                files: legacy_commit_files(conn, &old_id)?,
+                ..Commit::empty("")
+            };
+
+            let mut entries = Vec::new();
+            for file in current.files.iter() {
+                entries.push(ManifestEntry {
+                    path: file.name.clone(),
+                    hash: file.hash,
+                    size: upgrade_legacy_blob(&storage_path, &file.hash)?.unwrap_or(0),
+                    mode: 0o644,
+                });
+            }
+            let manifest_id = legacy_store_manifest(conn, &Manifest::new(entries))?;
+
+            let created_at = created_at.unwrap_or_default();
+            let id = commit_id(&manifest_id, parent.as_deref(), LEGACY_AUTHOR, &created_at, &message).to_string();
+            conn.execute(
+                "INSERT INTO commits_new (id, bucket_id, parent_id, manifest_id, author, message, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![id, bucket_id, parent, manifest_id, LEGACY_AUTHOR, message, created_at],
+            )?;
+
+            conn.execute("DELETE FROM files WHERE commit_id = ?1", params![old_id])?;
+            for file in current.compare(&previous).unwrap_or_default() {
+                if file.change != ChangeKind::Unchanged {
+                    legacy_insert_file(conn, &id, &file)?;
+                }
+            }
+
+            parent = Some(id);
+            previous = current;
+        }
+
+        conn.execute("UPDATE buckets SET head_commit_id = ?1 WHERE id = ?2", params![parent, bucket_id])?;
+    }
+
+    conn.execute_batch(
+        "DROP TABLE commits;
+        ALTER TABLE commits_new RENAME TO commits;",
+    )?;
+
+    Ok(())
+}
+
+/// Rebuilds the commits and files tables with the id columns typed as the text they hold, and adds
+/// indexes for looking up the commits of a bucket and the files of a commit.
+fn fix_column_types(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "CREATE TABLE commits_new (
+            id TEXT PRIMARY KEY,
+            bucket_id CHAR(36) NOT NULL,
+            parent_id TEXT,
+            manifest_id TEXT NOT NULL,
+            author TEXT NOT NULL,
+            message TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            FOREIGN KEY (parent_id) REFERENCES commits (id),
+            FOREIGN KEY (manifest_id) REFERENCES manifests (id)
+        );
+        INSERT INTO commits_new (id, bucket_id, parent_id, manifest_id, author, message, created_at)
+            SELECT id, bucket_id, parent_id, manifest_id, author, message, created_at FROM commits;
+        DROP TABLE commits;
+        ALTER TABLE commits_new RENAME TO commits;
+
+        CREATE TABLE files_new (
+            id CHAR(36) PRIMARY KEY,
+            commit_id TEXT NOT NULL,
+            file_path TEXT NOT NULL,
+            hash TEXT NOT NULL,
+            change_kind TEXT NOT NULL DEFAULT 'unchanged',
+            renamed_from TEXT,
+            FOREIGN KEY (commit_id) REFERENCES commits (id),
+            UNIQUE (commit_id, file_path, hash)
+        );
+        INSERT INTO files_new (id, commit_id, file_path, hash, change_kind, renamed_from)
+            SELECT id, commit_id, file_path, hash, change_kind, renamed_from FROM files;
+        DROP TABLE files;
+        ALTER TABLE files_new RENAME TO files;
+
+        CREATE TRIGGER AutoGenFilesGUID
+             AFTER INSERT ON files
+             FOR EACH ROW
+             WHEN (NEW.id IS NULL)
+             BEGIN
+               UPDATE files SET id = (select hex( randomblob(4)) || '-' || hex( randomblob(2))
+                         || '-' || '4' || substr( hex( randomblob(2)), 2) || '-'
+                         || substr('AB89', 1 + (abs(random()) % 4) , 1)  ||
+                         substr(hex(randomblob(2)), 2) || '-' || hex(randomblob(6)) ) WHERE rowid = NEW.rowid;
+             END;
+
+        CREATE INDEX commits_bucket_id ON commits (bucket_id);
+        CREATE INDEX commits_parent_id ON commits (parent_id);
+        CREATE INDEX files_commit_id ON files (commit_id);",
+    )?;
+
+    Ok(())
+}
+
+/// Adds the blob references of every bucket, filled from the manifests of its commits and its stashes.
+///
+/// A blob in the repository object store is shared by all buckets that reference it, the number of
+/// buckets referencing a hash is its reference count.
+fn add_object_refs(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "CREATE TABLE object_refs (
+            hash TEXT NOT NULL,
+            bucket_id CHAR(36) NOT NULL,
+            FOREIGN KEY (bucket_id) REFERENCES buckets (id),
+            PRIMARY KEY (hash, bucket_id)
+        );
+
+        INSERT OR IGNORE INTO object_refs (hash, bucket_id)
+            SELECT te.hash, c.bucket_id
+            FROM commits c
+            JOIN manifest_trees mt ON mt.manifest_id = c.manifest_id
+            JOIN tree_entries te ON te.tree_id = mt.tree_id;
+
+        INSERT OR IGNORE INTO object_refs (hash, bucket_id)
+            SELECT sf.hash, s.bucket_id
+            FROM stash_files sf
+            JOIN stashes s ON s.id = sf.stash_id;",
+    )?;
+
+    Ok(())
+}
+
+/// Adds the chunk lists of large files, which are split into chunks that are stored as blobs of their own.
+fn add_file_chunks(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "CREATE TABLE file_chunks (
+            hash TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            chunk_hash TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            PRIMARY KEY (hash, position)
+        );
+        CREATE INDEX file_chunks_chunk_hash ON file_chunks (chunk_hash);",
+    )?;
+
+    Ok(())
+}
+
+// Commits keep their row when their files are pruned, pruned_at records that their blobs may be gone
+fn add_commit_pruning(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch("ALTER TABLE commits ADD COLUMN pruned_at TEXT;")?;
+    Ok(())
+}
+
+/// Records the commit a stash was saved on, so restoring it only applies the changes it saved. Earlier stashes
+/// were saved on the head of their bucket, as far as it can be told now.
+fn add_stash_commits(conn: &Connection, _repo_root: &Path) -> Result<(), BucketError> {
+    conn.execute_batch(
+        "ALTER TABLE stashes ADD COLUMN commit_id TEXT REFERENCES commits (id);
+        UPDATE stashes SET commit_id = (SELECT head_commit_id FROM buckets WHERE buckets.id = stashes.bucket_id);",
+    )?;
+    Ok(())
+}
+
+/// Author recorded for commits that were made before commits had an author.
+const LEGACY_AUTHOR: &str = "unknown";
+
+// Loads the files of a commit as stored before change kinds existed
+fn legacy_commit_files(conn: &Connection, commit_id: &str) -> Result<Vec<CommittedFile>, BucketError> {
+    let mut stmt = conn.prepare("SELECT file_path, hash FROM files WHERE commit_id = ?1 ORDER BY file_path")?;
+    let mut rows = stmt.query(params![commit_id])?;
+
+    let mut files = Vec::new();
+    while let Some(row) = rows.next()? {
+        let hex_string: String = row.get(1)?;
+        files.push(CommittedFile {
+            id: Default::default(),
+            name: row.get(0)?,
+            hash: Hash::from_hex(&hex_string).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?,
+            change: ChangeKind::Unchanged,
+        });
+    }
+
+    Ok(files)
+}
+
+// Stores a manifest in the tables as created by add_commit_history, later migrations may change them
+fn legacy_store_manifest(conn: &Connection, manifest: &Manifest) -> Result<String, BucketError> {
+    let manifest_id = manifest.id().to_string();
+    if conn.execute("INSERT OR IGNORE INTO manifests (id) VALUES (?1)", params![manifest_id])? == 0 {
+        return Ok(manifest_id);
+    }
+
+    for tree in manifest.trees() {
+        let tree_id = tree.id().to_string();
+        if conn.execute("INSERT OR IGNORE INTO trees (id) VALUES (?1)", params![tree_id])? > 0 {
+            for entry in tree.entries.iter() {
+                conn.execute(
+                    "INSERT INTO tree_entries (tree_id, name, hash, size, mode) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![tree_id, file_name(&entry.path), entry.hash.to_string(), entry.size as i64, entry.mode],
+                )?;
+            }
+        }
+        conn.execute(
+            "INSERT INTO manifest_trees (manifest_id, directory, tree_id) VALUES (?1, ?2, ?3)",
+            params![manifest_id, tree.directory, tree_id],
+        )?;
+    }
+
+    Ok(manifest_id)
+}
+
+// Records a changed file of a commit in the files table as created by add_commit_history
+fn legacy_insert_file(conn: &Connection, commit_id: &str, file: &CommittedFile) -> Result<(), BucketError> {
+    conn.execute(
+        "INSERT INTO files (commit_id, file_path, hash, change_kind, renamed_from) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![commit_id, file.name, file.hash.to_string(), file.change.as_str(), file.change.renamed_from()],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_migrate_new_database() -> Result<(), BucketError> {
+        let temp_dir = tempdir()?;
+        let mut conn = Connection::open(temp_dir.path().join("buckets.db"))?;
+
+        let applied = migrate(&mut conn, temp_dir.path())?;
+        assert_eq!(applied.len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION);
+
+        // Migrating again doesn't change anything
+        assert!(migrate(&mut conn, temp_dir.path())?.is_empty());
+
+        let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        assert!(foreign_keys);
+        let bucket_id_type: String = conn.query_row(
+            "SELECT type FROM pragma_table_info('commits') WHERE name = 'bucket_id'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(bucket_id_type, "CHAR(36)");
+        let indexes: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'index' AND name IN ('commits_bucket_id', 'files_commit_id')",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(indexes, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_refuse_newer_schema() -> Result<(), BucketError> {
+        let temp_dir = tempdir()?;
+        let mut conn = Connection::open(temp_dir.path().join("buckets.db"))?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)?;
+
+        match migrate(&mut conn, temp_dir.path()) {
+            Err(BucketError::SchemaTooNew { found, supported }) => {
+                assert_eq!(found, SCHEMA_VERSION + 1);
+                assert_eq!(supported, SCHEMA_VERSION);
+            }
+            _ => panic!("A newer schema was not refused"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1);
+        }
+    }
+}
@@ -1,4 +1,12 @@
 pub mod checks;
+pub mod chunking;
 pub mod config;
+pub mod delta;
 pub mod errors;
+pub mod glob;
+pub mod lock;
+pub mod migrations;
+pub mod packs;
+pub mod storage;
+#[allow(clippy::module_inception)]
 pub mod utils;
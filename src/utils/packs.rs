@@ -0,0 +1,464 @@
+use crate::utils::storage::{is_stale, sync_directory, write_atomically, PARTIAL_PREFIX};
+use blake3::Hash;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Directory inside a storage directory that holds the packfiles.
+const PACKS_DIRECTORY: &str = "packs";
+
+/// First bytes of a packfile, followed by the stored bytes of its blobs one after the other.
+const PACK_HEADER: &[u8; 8] = b"BKTPACK1";
+
+/// First bytes of a pack index, followed by the number of blobs and one entry per blob sorted by hash.
+const INDEX_HEADER: &[u8; 8] = b"BKTIDX1\n";
+
+/// Size of the header of a pack index, the magic bytes and the number of entries.
+const INDEX_HEADER_SIZE: u64 = 16;
+
+/// Size of an entry in a pack index, the hash of the blob and its offset and length in the packfile.
+const INDEX_ENTRY_SIZE: u64 = 48;
+
+/// Number of packfiles a storage directory holds before packing merges them into one, every packfile is
+/// searched for a blob that isn't loose.
+const MAX_PACKS: usize = 8;
+
+/// Where a blob is stored in a packfile.
+#[derive(Debug, PartialEq)]
+pub(crate) struct PackedBlob {
+    pub hash: Hash,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// The result of packing the loose blobs of a storage directory.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct PackSummary {
+    pub blobs: usize,
+    pub bytes: u64,
+    /// Number of existing packfiles that were merged into the new one
+    pub merged_packs: usize,
+    pub pack_path: Option<PathBuf>,
+}
+
+// Where the stored bytes of a blob that goes into a new packfile are read from
+enum Source {
+    Loose(PathBuf),
+    Packed(PathBuf, PackedBlob),
+}
+
+/// The directory with the packfiles of a storage directory.
+pub(crate) fn packs_path(storage_path: &Path) -> PathBuf {
+    storage_path.join(PACKS_DIRECTORY)
+}
+
+/// Lists the packfiles of a storage directory, a packfile is only used once its index has been written.
+pub(crate) fn list_packs(storage_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let packs_path = packs_path(storage_path);
+    if !packs_path.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut packs = Vec::new();
+    for entry in fs::read_dir(&packs_path)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "pack") && path.with_extension("idx").is_file() {
+            packs.push(path);
+        }
+    }
+    packs.sort();
+
+    Ok(packs)
+}
+
+/// Finds a blob in the packfiles of a storage directory, returns the packfile and where the blob is in it.
+///
+/// The index of every packfile is searched with a binary search, only a few entries of each index are read.
+pub(crate) fn find_packed(storage_path: &Path, hash: &Hash) -> io::Result<Option<(PathBuf, PackedBlob)>> {
+    for pack_path in list_packs(storage_path)? {
+        if let Some(blob) = search_index(&pack_path.with_extension("idx"), hash)? {
+            return Ok(Some((pack_path, blob)));
+        }
+    }
+    Ok(None)
+}
+
+/// Opens a packed blob, the reader ends at the end of the blob.
+pub(crate) fn open_packed(pack_path: &Path, blob: &PackedBlob) -> io::Result<io::Take<File>> {
+    let mut file = File::open(pack_path)?;
+    file.seek(SeekFrom::Start(blob.offset))?;
+    Ok(file.take(blob.length))
+}
+
+// Reads the header of a pack index and returns the number of entries
+fn read_index_header(file: &mut File, index_path: &Path) -> io::Result<u64> {
+    let mut header = [0u8; INDEX_HEADER_SIZE as usize];
+    file.read_exact(&mut header)?;
+    if &header[..8] != INDEX_HEADER {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} is not a pack index", index_path.display()),
+        ));
+    }
+    Ok(u64::from_le_bytes(header[8..].try_into().unwrap()))
+}
+
+// Decodes an entry of a pack index
+fn parse_index_entry(entry: &[u8; INDEX_ENTRY_SIZE as usize]) -> PackedBlob {
+    PackedBlob {
+        hash: Hash::from_bytes(entry[..32].try_into().unwrap()),
+        offset: u64::from_le_bytes(entry[32..40].try_into().unwrap()),
+        length: u64::from_le_bytes(entry[40..].try_into().unwrap()),
+    }
+}
+
+fn search_index(index_path: &Path, hash: &Hash) -> io::Result<Option<PackedBlob>> {
+    let mut file = File::open(index_path)?;
+    let count = read_index_header(&mut file, index_path)?;
+
+    let mut entry = [0u8; INDEX_ENTRY_SIZE as usize];
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = (low + high) / 2;
+        file.seek(SeekFrom::Start(INDEX_HEADER_SIZE + middle * INDEX_ENTRY_SIZE))?;
+        file.read_exact(&mut entry)?;
+
+        match entry[..32].cmp(hash.as_bytes()) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => return Ok(Some(parse_index_entry(&entry))),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads every entry of the index of a packfile, sorted by hash.
+pub(crate) fn read_index(pack_path: &Path) -> io::Result<Vec<PackedBlob>> {
+    let index_path = pack_path.with_extension("idx");
+    let mut file = File::open(&index_path)?;
+    let count = read_index_header(&mut file, &index_path)?;
+
+    let mut blobs = Vec::new();
+    let mut entry = [0u8; INDEX_ENTRY_SIZE as usize];
+    for _ in 0..count {
+        file.read_exact(&mut entry)?;
+        blobs.push(parse_index_entry(&entry));
+    }
+
+    Ok(blobs)
+}
+
+/// Gathers the loose blobs of a storage directory into a new packfile, so the storage doesn't hold a file per
+/// blob. Blobs larger than `max_blob_size` bytes stay loose. Once the storage holds `MAX_PACKS` packfiles they
+/// are merged into the new packfile as well, so finding a packed blob never has to search many indexes.
+///
+/// The packfile is written to a temporary file, then its index is moved in place and only then the packfile,
+/// after which the loose blobs and merged packfiles are removed. An interrupted pack leaves every blob
+/// readable: either the old copy or the newly packed copy exists. Loose blobs that are already packed are
+/// removed without packing them again.
+pub(crate) fn pack_storage(storage_path: &Path, max_blob_size: u64) -> io::Result<PackSummary> {
+    let mut loose = Vec::new();
+    for (hash, path) in list_loose_blobs(storage_path)? {
+        if find_packed(storage_path, &hash)?.is_some() {
+            fs::remove_file(&path)?;
+        } else if fs::metadata(&path)?.len() <= max_blob_size {
+            loose.push((hash, path));
+        }
+    }
+
+    let packs = list_packs(storage_path)?;
+    let merged: Vec<PathBuf> = if packs.len() >= MAX_PACKS { packs } else { Vec::new() };
+    if loose.is_empty() && merged.is_empty() {
+        return Ok(PackSummary::default());
+    }
+
+    let mut blobs: Vec<(Hash, Source)> = loose.iter().map(|(hash, path)| (*hash, Source::Loose(path.clone()))).collect();
+    for pack_path in merged.iter() {
+        for blob in read_index(pack_path)? {
+            blobs.push((blob.hash, Source::Packed(pack_path.clone(), blob)));
+        }
+    }
+    blobs.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+    blobs.dedup_by(|a, b| a.0 == b.0);
+
+    let packs_path = packs_path(storage_path);
+    fs::create_dir_all(&packs_path)?;
+
+    let (pack_file, index) = write_pack(&packs_path, &blobs)?;
+    let pack_path = pack_path_for(&packs_path, &index);
+    write_atomically(&pack_path.with_extension("idx"), &index)?;
+    pack_file.persist(&pack_path).map_err(|e| e.error)?;
+    sync_directory(&packs_path)?;
+
+    let mut summary = PackSummary {
+        blobs: loose.len(),
+        bytes: 0,
+        merged_packs: 0,
+        pack_path: Some(pack_path.clone()),
+    };
+    for (_, path) in loose.iter() {
+        summary.bytes += fs::metadata(path)?.len();
+        fs::remove_file(path)?;
+    }
+    sync_directory(storage_path)?;
+
+    // Without its index a packfile is no longer used, so the index goes first
+    for merged_path in merged.iter().filter(|merged_path| **merged_path != pack_path) {
+        fs::remove_file(merged_path.with_extension("idx"))?;
+        fs::remove_file(merged_path)?;
+        summary.merged_packs += 1;
+    }
+    sync_directory(&packs_path)?;
+
+    Ok(summary)
+}
+
+// Writes the blobs, sorted by hash, into a temporary packfile and returns it together with its index
+fn write_pack(packs_path: &Path, blobs: &[(Hash, Source)]) -> io::Result<(NamedTempFile, Vec<u8>)> {
+    let pack_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(packs_path)?;
+    let mut index = INDEX_HEADER.to_vec();
+    index.extend_from_slice(&(blobs.len() as u64).to_le_bytes());
+    {
+        let mut writer = BufWriter::new(pack_file.as_file());
+        writer.write_all(PACK_HEADER)?;
+        let mut offset = PACK_HEADER.len() as u64;
+        for (hash, source) in blobs.iter() {
+            let length = match source {
+                Source::Loose(path) => io::copy(&mut File::open(path)?, &mut writer)?,
+                Source::Packed(pack_path, blob) => io::copy(&mut open_packed(pack_path, blob)?, &mut writer)?,
+            };
+            index.extend_from_slice(hash.as_bytes());
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&length.to_le_bytes());
+            offset += length;
+        }
+        writer.flush()?;
+    }
+    pack_file.as_file().sync_all()?;
+
+    Ok((pack_file, index))
+}
+
+// The packfile is named after its index, a pack is only used once both files exist
+fn pack_path_for(packs_path: &Path, index: &[u8]) -> PathBuf {
+    packs_path.join(format!("pack-{}.pack", blake3::hash(index)))
+}
+
+/// Removes a packfile, the blobs of the pack for which `keep` returns true are written back as loose blobs
+/// first. Returns the number of blobs that were dropped.
+///
+/// The kept blobs are on disk before the pack is removed, an interrupted unpack leaves them both loose and
+/// packed and the next pack removes the loose copies.
+pub(crate) fn unpack<F: Fn(&Hash) -> bool>(storage_path: &Path, pack_path: &Path, keep: F) -> io::Result<usize> {
+    let mut dropped = 0;
+    for blob in read_index(pack_path)? {
+        if !keep(&blob.hash) {
+            dropped += 1;
+            continue;
+        }
+        let blob_path = storage_path.join(blob.hash.to_string());
+        if !blob_path.is_file() {
+            let mut data = Vec::new();
+            open_packed(pack_path, &blob)?.read_to_end(&mut data)?;
+            write_atomically(&blob_path, &data)?;
+        }
+    }
+
+    fs::remove_file(pack_path.with_extension("idx"))?;
+    fs::remove_file(pack_path)?;
+    sync_directory(&packs_path(storage_path))?;
+
+    Ok(dropped)
+}
+
+/// Lists the loose blobs of a storage directory, the files that are named after a hash.
+pub(crate) fn list_loose_blobs(storage_path: &Path) -> io::Result<Vec<(Hash, PathBuf)>> {
+    if !storage_path.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut blobs = Vec::new();
+    for entry in fs::read_dir(storage_path)? {
+        let entry = entry?;
+        if let Ok(hash) = Hash::from_hex(entry.file_name().to_string_lossy().as_bytes()) {
+            if entry.path().is_file() {
+                blobs.push((hash, entry.path()));
+            }
+        }
+    }
+
+    Ok(blobs)
+}
+
+/// Removes what an interrupted pack left behind, temporary files and packfiles or indexes without the other.
+///
+/// Only files older than `STALE_AFTER` are removed, a pack that is being written has its index before its
+/// packfile for a moment. Returns the number of files that were removed.
+pub(crate) fn recover_packs(storage_path: &Path) -> io::Result<usize> {
+    let packs_path = packs_path(storage_path);
+    if !packs_path.is_dir() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for entry in fs::read_dir(&packs_path)? {
+        let path = entry?.path();
+        let partial = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(PARTIAL_PREFIX));
+        let unindexed = path.extension().is_some_and(|extension| extension == "pack") && !path.with_extension("idx").exists();
+        let unpacked = path.extension().is_some_and(|extension| extension == "idx") && !path.with_extension("pack").exists();
+        if path.is_file() && (partial || unindexed || unpacked) && is_stale(&path)? {
+            fs::remove_file(&path)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::config::DEFAULT_CODEC;
+    use crate::utils::storage::{compress_and_store_file, restore_blob};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pack_and_read() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        fs::create_dir(&storage)?;
+
+        let mut hashes = Vec::new();
+        for i in 0..20 {
+            let content = format!("material {}", i);
+            let input = temp_dir.path().join("input");
+            fs::write(&input, &content)?;
+            let hash = blake3::hash(content.as_bytes());
+            compress_and_store_file(&input, &storage.join(hash.to_string()), DEFAULT_CODEC)?;
+            hashes.push((hash, content));
+        }
+
+        let summary = pack_storage(&storage, 1024)?;
+        assert_eq!(summary.blobs, 20);
+        assert!(list_loose_blobs(&storage)?.is_empty());
+        assert_eq!(read_index(summary.pack_path.as_ref().unwrap())?.len(), 20);
+
+        for (hash, content) in hashes.iter() {
+            let output = temp_dir.path().join("output");
+            restore_blob(&storage, hash, &output)?;
+            assert_eq!(fs::read_to_string(&output)?, *content);
+        }
+        assert!(find_packed(&storage, &blake3::hash(b"missing"))?.is_none());
+
+        // nothing left to pack
+        assert_eq!(pack_storage(&storage, 1024)?, PackSummary::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_packs() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        fs::create_dir(&storage)?;
+
+        let input = temp_dir.path().join("input");
+        let mut hashes = Vec::new();
+        for i in 0..MAX_PACKS + 1 {
+            let content = format!("texture {}", i);
+            fs::write(&input, &content)?;
+            let hash = blake3::hash(content.as_bytes());
+            compress_and_store_file(&input, &storage.join(hash.to_string()), DEFAULT_CODEC)?;
+            hashes.push((hash, content));
+
+            let summary = pack_storage(&storage, 1024)?;
+            assert_eq!(summary.merged_packs, if i == MAX_PACKS { MAX_PACKS } else { 0 });
+        }
+
+        assert_eq!(list_packs(&storage)?.len(), 1);
+        for (hash, content) in hashes.iter() {
+            restore_blob(&storage, hash, &temp_dir.path().join("output"))?;
+            assert_eq!(fs::read_to_string(temp_dir.path().join("output"))?, *content);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        fs::create_dir(&storage)?;
+
+        let input = temp_dir.path().join("input");
+        let mut hashes = Vec::new();
+        for content in ["kept", "dropped"] {
+            fs::write(&input, content)?;
+            let hash = blake3::hash(content.as_bytes());
+            compress_and_store_file(&input, &storage.join(hash.to_string()), DEFAULT_CODEC)?;
+            hashes.push(hash);
+        }
+        let pack_path = pack_storage(&storage, 1024)?.pack_path.unwrap();
+
+        assert_eq!(unpack(&storage, &pack_path, |hash| *hash == hashes[0])?, 1);
+        assert!(list_packs(&storage)?.is_empty());
+        assert_eq!(list_loose_blobs(&storage)?, vec![(hashes[0], storage.join(hashes[0].to_string()))]);
+
+        let output = temp_dir.path().join("output");
+        restore_blob(&storage, &hashes[0], &output)?;
+        assert_eq!(fs::read_to_string(&output)?, "kept");
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_packs() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let packs = packs_path(temp_dir.path());
+        fs::create_dir_all(&packs)?;
+        fs::write(packs.join("pack-1.pack"), PACK_HEADER)?;
+        fs::write(packs.join("pack-2.pack"), PACK_HEADER)?;
+        fs::write(packs.join("pack-2.idx"), INDEX_HEADER)?;
+        fs::write(packs.join("pack-3.idx"), INDEX_HEADER)?;
+        fs::write(packs.join(format!("{}interrupted", PARTIAL_PREFIX)), b"half")?;
+
+        // files of a pack that can still be written by another command are kept
+        assert_eq!(recover_packs(temp_dir.path())?, 0);
+
+        for name in ["pack-1.pack", "pack-3.idx", ".partial-interrupted"] {
+            let two_hours_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 60 * 60);
+            File::options().write(true).open(packs.join(name))?.set_modified(two_hours_ago)?;
+        }
+        assert_eq!(recover_packs(temp_dir.path())?, 3);
+        assert_eq!(list_packs(temp_dir.path())?, vec![packs.join("pack-2.pack")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_while_packing() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        let packs = packs_path(&storage);
+        fs::create_dir_all(&packs)?;
+
+        let input = temp_dir.path().join("input");
+        fs::write(&input, "material")?;
+        let hash = blake3::hash(b"material");
+        compress_and_store_file(&input, &storage.join(hash.to_string()), DEFAULT_CODEC)?;
+
+        // recovery runs after the index is in place and before the packfile is
+        let blobs = vec![(hash, Source::Loose(storage.join(hash.to_string())))];
+        let (pack_file, index) = write_pack(&packs, &blobs)?;
+        let pack_path = pack_path_for(&packs, &index);
+        write_atomically(&pack_path.with_extension("idx"), &index)?;
+        assert_eq!(recover_packs(&storage)?, 0);
+        pack_file.persist(&pack_path).map_err(|e| e.error)?;
+
+        assert_eq!(list_packs(&storage)?, vec![pack_path]);
+        fs::remove_file(storage.join(hash.to_string()))?;
+        restore_blob(&storage, &hash, &temp_dir.path().join("output"))?;
+        assert_eq!(fs::read_to_string(temp_dir.path().join("output"))?, "material");
+        Ok(())
+    }
+}
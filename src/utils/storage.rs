@@ -0,0 +1,909 @@
+use crate::utils::checks::find_repo_root;
+use crate::utils::chunking::{max_chunk_size, Chunker};
+use crate::utils::config::{open_db, ChunkingConfig, Codec, RepositoryConfig, StorageMode, DEFAULT_CODEC};
+use crate::utils::delta::{apply_delta, read_delta_header, write_delta, DeltaHeader, MAX_DELTA_SIZE};
+use crate::utils::packs::{find_packed, list_loose_blobs, list_packs, open_packed, read_index, recover_packs};
+use crate::utils::errors::BucketError;
+use blake3::{Hash, Hasher};
+use rusqlite::{params, Connection};
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+use zstd::stream::copy_decode;
+
+/// Compresses a file from a specified input path and stores the compressed data at an output path.
+///
+/// This function reads a file from `input_path`, compresses it using the specified `codec`, and writes
+/// the compressed data to a file located at `output_path`. The blob starts with a header that records the
+/// codec, so every blob can be decoded whatever the compression rules were when it was written.
+///
+/// The compressed data is first written to a temporary file next to `output_path` and flushed to disk.
+/// Only then is it renamed to `output_path`, so a crash or a full disk never leaves a half-written file
+/// under the final name. Temporary files left behind by a crash are removed by `recover_storage`, which
+/// `bucket gc` and `bucket fsck --repair` run.
+///
+/// # Arguments
+/// * `input_path` - A reference to a `Path` that specifies the input file to be compressed.
+/// * `output_path` - A reference to a `Path` that specifies where the compressed file should be stored.
+/// * `codec` - The codec to compress the file with, no compression for formats that are compressed already
+///   or zstd at a level from 1 (fastest, less compression) to 22 (slowest, best compression).
+///
+/// # Returns
+/// This function returns an `io::Result<()>`. On successful execution, it returns `Ok(())`. If it encounters
+/// any I/O errors during file operations or compression, it returns an `Err` variant containing an `io::Error`.
+///
+/// # Errors
+/// Errors can arise from:
+/// - Problems opening the input file, such as file not found, lacking read permissions, or the file being locked.
+/// - Issues creating the output file, like inadequate write permissions or disk space issues.
+/// - Failures during the read, write, or compression processes, such as corrupted data or an interrupted process.
+///
+/// # Example Usage
+/// ```
+/// use std::path::Path;
+/// let result = compress_and_store_file(Path::new("path/to/input/file.txt"), Path::new("path/to/output/file.zst"), Codec::Zstd(3));
+/// match result {
+///     Ok(_) => println!("File compressed and stored successfully."),
+///     Err(e) => eprintln!("Failed to compress and store file: {}", e),
+/// }
+/// ```
+pub(crate) fn compress_and_store_file(input_path: &Path, output_path: &Path, codec: Codec) -> io::Result<()> {
+    let directory = output_path.parent().unwrap_or(Path::new("."));
+    let input_file = File::open(input_path)?;
+    let mut reader = BufReader::new(input_file);
+    let temp_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(directory)?;
+
+    // Compress the file data and write it to the temporary file
+    {
+        let mut writer = BufWriter::new(temp_file.as_file());
+        encode(&mut reader, &mut writer, codec)?;
+        writer.flush()?;
+    }
+    temp_file.as_file().sync_all()?;
+
+    // Move the complete file in place and make the rename itself durable
+    temp_file.persist(output_path).map_err(|e| e.error)?;
+    sync_directory(directory)
+}
+
+/// First line of a blob with the content of a file, the second line is the codec the content is encoded with.
+///
+/// Blobs written before codecs were recorded have no header and are compressed with zstd.
+const BLOB_HEADER: &[u8] = b"buckets blob 1\n";
+
+/// Window of the zstd long mode in bits, matches can reach back 128 MiB.
+const LONG_WINDOW_LOG: u32 = 27;
+
+/// Writes the blob header and the content read from `reader` encoded with `codec`.
+fn encode<R: Read, W: Write>(reader: &mut R, writer: &mut W, codec: Codec) -> io::Result<()> {
+    let mut encoder = BlobEncoder::new(writer, codec)?;
+    io::copy(reader, &mut encoder)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+/// Writes a blob, the content written to it is encoded with the codec of the blob.
+enum BlobEncoder<W: Write> {
+    Plain(W),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> BlobEncoder<W> {
+    /// Writes the blob header to `writer`.
+    fn new(mut writer: W, codec: Codec) -> io::Result<Self> {
+        writer.write_all(BLOB_HEADER)?;
+        writer.write_all(format!("{}\n", codec).as_bytes())?;
+
+        Ok(match codec {
+            Codec::None => BlobEncoder::Plain(writer),
+            Codec::Zstd(level) => BlobEncoder::Zstd(zstd::stream::write::Encoder::new(writer, level)?),
+            Codec::ZstdLong(level) => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level)?;
+                encoder.long_distance_matching(true)?;
+                encoder.window_log(LONG_WINDOW_LOG)?;
+                BlobEncoder::Zstd(encoder)
+            }
+        })
+    }
+
+    /// Writes the end of the encoded content and returns the writer.
+    fn finish(self) -> io::Result<W> {
+        match self {
+            BlobEncoder::Plain(writer) => Ok(writer),
+            BlobEncoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for BlobEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            BlobEncoder::Plain(writer) => writer.write(buf),
+            BlobEncoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            BlobEncoder::Plain(writer) => writer.flush(),
+            BlobEncoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// Reads the codec line after the blob header and decodes the rest of the blob with it
+fn decode<R: BufRead, W: Write>(mut reader: R, writer: &mut W) -> io::Result<()> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    line.clear();
+    reader.read_line(&mut line)?;
+    let codec: Codec = line
+        .trim_end()
+        .parse()
+        .map_err(|e: String| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    match codec {
+        Codec::None => {
+            io::copy(&mut reader, writer)?;
+        }
+        Codec::Zstd(_) | Codec::ZstdLong(_) => {
+            let mut decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+            decoder.window_log_max(LONG_WINDOW_LOG)?;
+            io::copy(&mut decoder, writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prefix of the temporary files blobs are written to before they are moved in place.
+pub(crate) const PARTIAL_PREFIX: &str = ".partial-";
+
+/// Flushes the entries of a directory to disk so a rename into it survives a crash.
+#[cfg(unix)]
+pub(crate) fn sync_directory(directory: &Path) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+/// Directories can't be opened as files on this platform, renames are durable once the file is synced.
+#[cfg(not(unix))]
+pub(crate) fn sync_directory(_directory: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Age after which a temporary file can only have been left behind by an interrupted command.
+const STALE_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Checks if a file was last written longer than `STALE_AFTER` ago.
+pub(crate) fn is_stale(path: &Path) -> io::Result<bool> {
+    let modified = fs::metadata(path)?.modified()?;
+    Ok(SystemTime::now().duration_since(modified).is_ok_and(|age| age > STALE_AFTER))
+}
+
+/// Removes the temporary files that were left in a storage directory by an interrupted write or pack.
+///
+/// Only files older than `STALE_AFTER` are removed, younger ones can belong to a command that is still
+/// writing them. Returns the number of files that were removed. A storage directory that doesn't exist
+/// yet has nothing to recover.
+pub(crate) fn recover_storage(storage_path: &Path) -> io::Result<usize> {
+    if !storage_path.is_dir() {
+        return Ok(0);
+    }
+
+    let mut removed = 0;
+    for entry in fs::read_dir(storage_path)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with(PARTIAL_PREFIX) && entry.path().is_file() && is_stale(&entry.path())? {
+            fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed + recover_packs(storage_path)?)
+}
+
+/// Cleans up what interrupted commands left behind in the storage of every bucket of a repository.
+///
+/// Database changes are made in transactions which SQLite rolls back by itself, only the temporary
+/// files of interrupted blob writes need to be removed. Returns the number of files that were removed.
+/// Run it while holding the repository lock.
+pub(crate) fn recover_repository(repo_root: &Path) -> Result<usize, BucketError> {
+    let conn = open_db(&repo_root.join(".buckets").join("buckets.db"))?;
+    let mut stmt = conn.prepare("SELECT path FROM buckets")?;
+    let paths = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut removed = recover_storage(&objects_path(repo_root))?;
+    for path in paths {
+        removed += recover_storage(&bucket_storage_path(&repo_root.join(path)))?;
+    }
+
+    Ok(removed)
+}
+
+/// Returns the directory the blobs of the bucket at `bucket_path` are stored in.
+///
+/// Depending on the storage mode of the repository that is the storage directory of the bucket itself or
+/// the object store shared by all buckets of the repository.
+pub(crate) fn storage_path(bucket_path: &Path) -> io::Result<PathBuf> {
+    let repo_root = find_repo_root(bucket_path)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No .buckets directory found"))?;
+
+    Ok(match RepositoryConfig::from_file(repo_root.clone())?.storage {
+        StorageMode::Bucket => bucket_storage_path(bucket_path),
+        StorageMode::Repository => objects_path(&repo_root),
+    })
+}
+
+/// Returns every storage directory of the repository at `repo_root`, the storage of each bucket or the object
+/// store shared by all buckets.
+pub(crate) fn storage_paths(repo_root: &Path) -> Result<Vec<PathBuf>, BucketError> {
+    if RepositoryConfig::from_file(repo_root.to_path_buf())?.storage == StorageMode::Repository {
+        return Ok(vec![objects_path(repo_root)]);
+    }
+
+    let conn = open_db(&repo_root.join(".buckets").join("buckets.db"))?;
+    let mut stmt = conn.prepare("SELECT path FROM buckets ORDER BY name")?;
+    let paths = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .map(|path| path.map(|path| bucket_storage_path(&repo_root.join(path))))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(paths)
+}
+
+/// The storage directory of a single bucket.
+pub(crate) fn bucket_storage_path(bucket_path: &Path) -> PathBuf {
+    bucket_path.join(".b").join("storage")
+}
+
+/// The object store shared by all buckets of the repository at `repo_root`.
+pub(crate) fn objects_path(repo_root: &Path) -> PathBuf {
+    repo_root.join(".buckets").join("objects")
+}
+
+/// Records that a bucket references the blob with `hash`, a blob is referenced once per bucket.
+///
+/// The number of buckets referencing a blob is its reference count, a blob in the object store can only be
+/// removed when no bucket references it anymore.
+pub(crate) fn add_object_ref(conn: &Connection, bucket_id: &str, hash: &Hash) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO object_refs (hash, bucket_id) VALUES (?1, ?2)",
+        params![hash.to_string(), bucket_id],
+    )?;
+    Ok(())
+}
+
+/// A chunk of a large file, stored as a blob of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Chunk {
+    pub hash: Hash,
+    pub size: u64,
+}
+
+/// First line of a blob that holds the chunk list of a large file instead of its content.
+const CHUNK_LIST_HEADER: &[u8] = b"buckets chunk list 1\n";
+
+/// How `store_blob` stores a file.
+pub(crate) struct StoreOptions<'a> {
+    pub chunking: &'a ChunkingConfig,
+    /// Codec the file, or each of its chunks, is compressed with
+    pub codec: Codec,
+    /// The blob of the previous version of the file, the file is stored as a delta against it
+    pub delta_base: Option<Hash>,
+    /// Most deltas applied after each other to restore a file, beyond that the file is stored in full
+    pub max_delta_depth: usize,
+    /// Files and bases larger than this many bytes are not stored as a delta
+    pub max_delta_size: u64,
+}
+
+/// Stores the content of a file in the bucket storage under its hash, unless a blob with that hash
+/// already exists.
+///
+/// With a `delta_base` the file is stored as a delta against that blob, as long as the base is stored and the
+/// chain of deltas stays within `max_delta_depth`. Files of at least `chunking.threshold` bytes are split into chunks with content defined chunking. Every
+/// chunk is stored as a blob under its own hash, so chunks that didn't change since an earlier version of the
+/// file are not stored again, and the blob of the file holds the list of its chunks. Only files larger than
+/// the largest chunk are split, so a chunk never has the same hash as a chunked file.
+///
+/// Returns the chunks of the file when it is stored as a chunk list.
+pub(crate) fn store_blob(storage_path: &Path, input_path: &Path, hash: &Hash, options: &StoreOptions) -> io::Result<Option<Vec<Chunk>>> {
+    if blob_exists(storage_path, hash)? {
+        return read_chunk_list(storage_path, hash);
+    }
+    fs::create_dir_all(storage_path)?;
+    let blob_path = storage_path.join(hash.to_string());
+
+    if let Some(base) = options.delta_base {
+        let max_size = options.max_delta_size.min(MAX_DELTA_SIZE);
+        if store_delta(storage_path, input_path, &blob_path, &base, options.max_delta_depth, max_size)? {
+            return Ok(None);
+        }
+    }
+
+    let chunking = options.chunking;
+    let size = fs::metadata(input_path)?.len();
+    let average_size = chunking.average_size as usize;
+    if size < chunking.threshold || size <= max_chunk_size(average_size) as u64 {
+        compress_and_store_file(input_path, &blob_path, options.codec)?;
+        return Ok(None);
+    }
+
+    let mut chunks = Vec::new();
+    for data in Chunker::new(BufReader::new(File::open(input_path)?), average_size) {
+        let data = data?;
+        let chunk = Chunk {
+            hash: blake3::hash(&data),
+            size: data.len() as u64,
+        };
+        if !blob_exists(storage_path, &chunk.hash)? {
+            let mut blob = Vec::new();
+            encode(&mut data.as_slice(), &mut blob, options.codec)?;
+            write_atomically(&storage_path.join(chunk.hash.to_string()), &blob)?;
+        }
+        chunks.push(chunk);
+    }
+
+    let mut list = CHUNK_LIST_HEADER.to_vec();
+    for chunk in chunks.iter() {
+        list.extend_from_slice(format!("{} {}\n", chunk.hash, chunk.size).as_bytes());
+    }
+    write_atomically(&blob_path, &list)?;
+
+    Ok(Some(chunks))
+}
+
+// Stores a file as a delta against the blob base, returns false when the file has to be stored in full instead.
+// The base is held in memory, the delta is streamed to a temporary file.
+fn store_delta(storage_path: &Path, input_path: &Path, blob_path: &Path, base: &Hash, max_depth: usize, max_size: u64) -> io::Result<bool> {
+    let size = fs::metadata(input_path)?.len();
+    if size > max_size || !blob_exists(storage_path, base)? {
+        return Ok(false);
+    }
+    let depth = delta_header(storage_path, base)?.map_or(0, |header| header.depth) + 1;
+    if depth > max_depth {
+        return Ok(false);
+    }
+
+    let mut base_content = BoundedBuffer {
+        data: Vec::new(),
+        limit: max_size as usize,
+    };
+    match decode_blob(storage_path, base, &mut base_content) {
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => return Ok(false),
+        result => result?,
+    }
+
+    let header = DeltaHeader {
+        base: *base,
+        depth,
+        size,
+    };
+    let directory = blob_path.parent().unwrap_or(Path::new("."));
+    let temp_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(directory)?;
+    {
+        let mut writer = BufWriter::new(temp_file.as_file());
+        write_delta(&mut writer, &mut BufReader::new(File::open(input_path)?), &base_content.data, &header)?;
+        writer.flush()?;
+    }
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(blob_path).map_err(|e| e.error)?;
+    sync_directory(directory)?;
+
+    Ok(true)
+}
+
+// Collects written data in memory and fails with FileTooLarge once more than limit bytes are written
+struct BoundedBuffer {
+    data: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for BoundedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.data.len() + buf.len() > self.limit {
+            return Err(io::Error::new(io::ErrorKind::FileTooLarge, "Base is too large for a delta"));
+        }
+        self.data.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the header of a blob that is stored as a delta, returns `None` for any other blob.
+pub(crate) fn delta_header(storage_path: &Path, hash: &Hash) -> io::Result<Option<DeltaHeader>> {
+    read_delta_header(&mut open_blob(storage_path, hash)?)
+}
+
+/// Reads the chunk list of a blob, returns `None` when the blob holds the content of a file instead.
+pub(crate) fn read_chunk_list(storage_path: &Path, hash: &Hash) -> io::Result<Option<Vec<Chunk>>> {
+    let mut reader = open_blob(storage_path, hash)?;
+    if !reader.fill_buf()?.starts_with(CHUNK_LIST_HEADER) {
+        return Ok(None);
+    }
+    parse_chunk_list(reader).map(Some)
+}
+
+// Parses the chunks of a chunk list blob, one chunk per line after the header
+fn parse_chunk_list<R: BufRead>(reader: R) -> io::Result<Vec<Chunk>> {
+    let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid chunk list entry: {}", line));
+
+    let mut chunks = Vec::new();
+    for line in reader.lines().skip(1) {
+        let line = line?;
+        let (hash, size) = line.split_once(' ').ok_or_else(|| invalid(&line))?;
+        chunks.push(Chunk {
+            hash: Hash::from_hex(hash).map_err(|_| invalid(&line))?,
+            size: size.parse().map_err(|_| invalid(&line))?,
+        });
+    }
+
+    Ok(chunks)
+}
+
+/// Writes data to a temporary file next to `path` and moves it in place once it is on disk.
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let mut temp_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(directory)?;
+    temp_file.write_all(data)?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path).map_err(|e| e.error)?;
+    sync_directory(directory)
+}
+
+/// Adds a blob from another storage directory to `storage_path`, unless a blob with that hash already exists.
+///
+/// The blob is hard linked when both directories are on the same file system and copied otherwise. Returns
+/// `false` when the blob already existed in `storage_path`.
+pub(crate) fn import_blob(blob_path: &Path, storage_path: &Path, hash: &Hash) -> io::Result<bool> {
+    if blob_exists(storage_path, hash)? {
+        return Ok(false);
+    }
+    let target = storage_path.join(hash.to_string());
+    fs::create_dir_all(storage_path)?;
+
+    if fs::hard_link(blob_path, &target).is_err() {
+        copy_atomically(blob_path, &target)?;
+    }
+    sync_directory(storage_path)?;
+
+    Ok(true)
+}
+
+/// Copies a file through a temporary file next to `target` that is moved in place once it is on disk, so an
+/// interrupted copy never leaves a truncated file under the name of the target.
+pub(crate) fn copy_atomically(source: &Path, target: &Path) -> io::Result<()> {
+    let directory = target.parent().unwrap_or(Path::new("."));
+    let mut temp_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(directory)?;
+    io::copy(&mut File::open(source)?, temp_file.as_file_mut())?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(target).map_err(|e| e.error)?;
+    sync_directory(directory)
+}
+
+/// Decompresses a stored blob and writes the original file content to an output path.
+///
+/// This is the inverse of `compress_and_store_file`. The blob named after `hash` in `storage_path` is
+/// decoded with zstd into a temporary file next to `output_path` while its blake3 hash is calculated, the
+/// chunks of a chunk list blob are decoded one after the other.
+/// Only when the hash matches is the temporary file renamed over `output_path`, so a corrupted blob
+/// never replaces a working file. Missing parent directories of `output_path` are created.
+///
+/// # Arguments
+/// * `storage_path` - A reference to a `Path` of the bucket storage directory.
+/// * `hash` - The blake3 hash of the original file content, which is also the name of the blob.
+/// * `output_path` - A reference to a `Path` where the decompressed file should be written.
+///
+/// # Errors
+/// Returns an `io::Error` if the blob can not be read, is not valid zstd data, does not match `hash`
+/// or the output file can not be written.
+pub(crate) fn restore_blob(storage_path: &Path, hash: &Hash, output_path: &Path) -> io::Result<()> {
+    let parent = output_path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+
+    // Decompress the blob into a temporary file and hash the content while writing
+    let temp_file = NamedTempFile::new_in(parent)?;
+    let restored_hash = {
+        let mut writer = HashingWriter {
+            inner: BufWriter::new(temp_file.as_file()),
+            hasher: Hasher::new(),
+        };
+        decode_blob(storage_path, hash, &mut writer)?;
+        writer.flush()?;
+        writer.hasher.finalize()
+    };
+    if restored_hash != *hash {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Blob {} is corrupted, its content hashes to {}", hash, restored_hash),
+        ));
+    }
+
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(output_path).map_err(|e| e.error)?;
+
+    Ok(())
+}
+
+/// Checks that a blob decodes and that its content hashes to `hash`, the name of the blob.
+///
+/// A blob that can't be decoded, because it is damaged or a chunk or delta base it needs is missing, fails
+/// the check.
+pub(crate) fn verify_blob(storage_path: &Path, hash: &Hash) -> bool {
+    let mut writer = HashingWriter {
+        inner: io::sink(),
+        hasher: Hasher::new(),
+    };
+    decode_blob(storage_path, hash, &mut writer).is_ok() && writer.hasher.finalize() == *hash
+}
+
+/// Returns the number of bytes the content of a blob would take up as a full blob compressed with `codec`.
+///
+/// The content is encoded while it is decoded, only the number of encoded bytes is kept.
+pub(crate) fn full_blob_size(storage_path: &Path, hash: &Hash, codec: Codec) -> io::Result<u64> {
+    let mut encoder = BlobEncoder::new(CountingWriter { count: 0 }, codec)?;
+    decode_blob(storage_path, hash, &mut encoder)?;
+    Ok(encoder.finish()?.count)
+}
+
+/// Opens the stored bytes of a blob, which is either a loose file named after its hash or part of a packfile.
+pub(crate) fn open_blob(storage_path: &Path, hash: &Hash) -> io::Result<BufReader<io::Take<File>>> {
+    let blob_path = storage_path.join(hash.to_string());
+    let error = match File::open(&blob_path) {
+        Ok(file) => return Ok(BufReader::new(file.take(u64::MAX))),
+        Err(e) => e,
+    };
+
+    if error.kind() == io::ErrorKind::NotFound {
+        if let Some((pack_path, blob)) = find_packed(storage_path, hash)? {
+            return Ok(BufReader::new(open_packed(&pack_path, &blob)?));
+        }
+    }
+    Err(io::Error::new(
+        error.kind(),
+        format!("Failed to open blob {}: {}", blob_path.display(), error),
+    ))
+}
+
+/// Lists every blob of a storage directory with the number of bytes it takes up, loose and packed blobs.
+pub(crate) fn list_stored_blobs(storage_path: &Path) -> io::Result<Vec<(Hash, u64)>> {
+    let mut blobs = Vec::new();
+    for (hash, path) in list_loose_blobs(storage_path)? {
+        blobs.push((hash, fs::metadata(path)?.len()));
+    }
+    for pack_path in list_packs(storage_path)? {
+        blobs.extend(read_index(&pack_path)?.into_iter().map(|blob| (blob.hash, blob.length)));
+    }
+    Ok(blobs)
+}
+
+/// Checks if a blob is stored, either loose or in a packfile.
+pub(crate) fn blob_exists(storage_path: &Path, hash: &Hash) -> io::Result<bool> {
+    Ok(storage_path.join(hash.to_string()).is_file() || find_packed(storage_path, hash)?.is_some())
+}
+
+/// Writes the content of the blob with `hash` to `writer`, following the chunk list of a chunked file and
+/// applying the deltas of a file that is stored as a delta against an earlier version.
+///
+/// A chain of deltas is applied oldest first, holding no more than two versions of the file in memory, and
+/// the last delta is written straight to `writer`.
+pub(crate) fn decode_blob<W: Write>(storage_path: &Path, hash: &Hash, writer: &mut W) -> io::Result<()> {
+    let mut reader = open_blob(storage_path, hash)?;
+    let header = match read_delta_header(&mut reader)? {
+        Some(header) => header,
+        None => return decode_stored(storage_path, reader, writer),
+    };
+
+    // Follow the chain down to the version that is stored in full
+    let mut deltas = Vec::new();
+    let mut base = header.base;
+    while let Some(header) = delta_header(storage_path, &base)? {
+        if base == *hash || deltas.contains(&base) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Delta chain of blob {} refers back to itself", hash),
+            ));
+        }
+        deltas.push(base);
+        base = header.base;
+    }
+
+    let mut content = Vec::new();
+    decode_stored(storage_path, open_blob(storage_path, &base)?, &mut content)?;
+    let mut next = Vec::new();
+    for delta in deltas.iter().rev() {
+        let mut delta_reader = open_blob(storage_path, delta)?;
+        read_delta_header(&mut delta_reader)?;
+        next.clear();
+        apply_delta(delta_reader, &content, &mut next)?;
+        std::mem::swap(&mut content, &mut next);
+    }
+    apply_delta(reader, &content, writer)
+}
+
+// Decodes a blob that is no delta: a chunk list, a blob with a header or a legacy zstd blob
+fn decode_stored<R: BufRead, W: Write>(storage_path: &Path, mut reader: R, writer: &mut W) -> io::Result<()> {
+    if reader.fill_buf()?.starts_with(CHUNK_LIST_HEADER) {
+        for chunk in parse_chunk_list(reader)? {
+            decode_blob(storage_path, &chunk.hash, writer)?;
+        }
+        Ok(())
+    } else if reader.fill_buf()?.starts_with(BLOB_HEADER) {
+        decode(reader, writer)
+    } else {
+        copy_decode(reader, writer)
+    }
+}
+
+/// Returns the size of the content of a blob written by an earlier version of buckets.
+///
+/// Early versions compressed blobs twice, such a blob is rewritten in the current format so it can be
+/// restored. Returns `None` when the blob is missing or its content doesn't match `hash` either way.
+pub(crate) fn upgrade_legacy_blob(storage_path: &Path, hash: &Hash) -> io::Result<Option<u64>> {
+    let blob_path = storage_path.join(hash.to_string());
+    if !blob_path.is_file() {
+        return Ok(None);
+    }
+
+    // A blob that was already upgraded, or never needed it, decodes to its hash
+    let mut writer = HashingWriter {
+        inner: io::sink(),
+        hasher: Hasher::new(),
+    };
+    if decode_blob(storage_path, hash, &mut writer).is_ok() && writer.hasher.finalize() == *hash {
+        return Ok(Some(writer.hasher.count()));
+    }
+
+    let (decoded, _, _) = match decode_to_temp_file(&blob_path, storage_path) {
+        Ok(decoded) => decoded,
+        Err(_) => return Ok(None),
+    };
+
+    let (content, content_hash, size) = match decode_to_temp_file(decoded.path(), storage_path) {
+        Ok(content) => content,
+        Err(_) => return Ok(None),
+    };
+    if content_hash != *hash {
+        return Ok(None);
+    }
+
+    compress_and_store_file(content.path(), &blob_path, DEFAULT_CODEC)?;
+    Ok(Some(size))
+}
+
+// Decodes a zstd file into a temporary file, returning the temporary file with the hash and size of its content
+fn decode_to_temp_file(input_path: &Path, directory: &Path) -> io::Result<(NamedTempFile, Hash, u64)> {
+    let temp_file = tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(directory)?;
+    let mut writer = HashingWriter {
+        inner: BufWriter::new(temp_file.as_file()),
+        hasher: Hasher::new(),
+    };
+    let size = io::copy(&mut zstd::Decoder::new(File::open(input_path)?)?, &mut writer)?;
+    writer.flush()?;
+    let hash = writer.hasher.finalize();
+    drop(writer);
+
+    Ok((temp_file, hash, size))
+}
+
+// Passes written data through to the inner writer and keeps a running blake3 hash of it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Counts the bytes written to it and discards them
+struct CountingWriter {
+    count: u64,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_compress_and_restore_file() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let input = temp_dir.path().join("input.txt");
+        let output = temp_dir.path().join("restored").join("output.txt");
+        fs::write(&input, b"Some content")?;
+        let hash = blake3::hash(b"Some content");
+
+        compress_and_store_file(&input, &temp_dir.path().join(hash.to_string()), DEFAULT_CODEC)?;
+        restore_blob(temp_dir.path(), &hash, &output)?;
+
+        assert_eq!(fs::read(&output)?, b"Some content");
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_corrupted_blob() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let input = temp_dir.path().join("input.txt");
+        let output = temp_dir.path().join("output.txt");
+        fs::write(&input, b"Other content")?;
+        fs::write(&output, b"Working copy")?;
+        let hash = blake3::hash(b"Some content");
+
+        // store different content under the hash of "Some content"
+        compress_and_store_file(&input, &temp_dir.path().join(hash.to_string()), DEFAULT_CODEC)?;
+        let result = restore_blob(temp_dir.path(), &hash, &output);
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&output)?, b"Working copy");
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_chunked_blob() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        let chunking = ChunkingConfig {
+            threshold: 16 * 1024,
+            average_size: 1024,
+        };
+        let options = StoreOptions {
+            chunking: &chunking,
+            codec: DEFAULT_CODEC,
+            delta_base: None,
+            max_delta_depth: 0,
+            max_delta_size: 0,
+        };
+
+        let mut state: u32 = 1;
+        let mut content: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let input = temp_dir.path().join("large.uasset");
+        fs::write(&input, &content)?;
+        let hash = blake3::hash(&content);
+
+        let chunks = store_blob(&storage, &input, &hash, &options)?.unwrap();
+        assert_eq!(chunks.iter().map(|c| c.size).sum::<u64>(), content.len() as u64);
+        assert_eq!(read_chunk_list(&storage, &hash)?, Some(chunks.clone()));
+        restore_blob(&storage, &hash, &temp_dir.path().join("restored"))?;
+        assert_eq!(fs::read(temp_dir.path().join("restored"))?, content);
+
+        // a one byte change only stores the chunk it is in
+        content[30_000] ^= 0xff;
+        fs::write(&input, &content)?;
+        let blobs = fs::read_dir(&storage)?.count();
+        let edited = store_blob(&storage, &input, &blake3::hash(&content), &options)?.unwrap();
+        assert_eq!(fs::read_dir(&storage)?.count(), blobs + 2);
+        assert_eq!(edited.iter().filter(|c| !chunks.contains(c)).count(), 1);
+
+        // small files are stored whole
+        fs::write(&input, b"small")?;
+        assert_eq!(store_blob(&storage, &input, &blake3::hash(b"small"), &options)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_delta_chain() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let storage = temp_dir.path().join("storage");
+        let input = temp_dir.path().join("hero.psd");
+        let chunking = ChunkingConfig::default();
+
+        let mut content: Vec<u8> = (0..50_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let mut previous: Option<Hash> = None;
+        let mut depths = Vec::new();
+        for version in 0..4u8 {
+            content[1000 * version as usize] ^= 0xff;
+            fs::write(&input, &content)?;
+            let hash = blake3::hash(&content);
+            let options = StoreOptions {
+                chunking: &chunking,
+                codec: DEFAULT_CODEC,
+                delta_base: previous,
+                max_delta_depth: 2,
+                max_delta_size: 1024 * 1024,
+            };
+            store_blob(&storage, &input, &hash, &options)?;
+            depths.push(delta_header(&storage, &hash)?.map_or(0, |header| header.depth));
+
+            restore_blob(&storage, &hash, &temp_dir.path().join("restored.psd"))?;
+            assert_eq!(fs::read(temp_dir.path().join("restored.psd"))?, content);
+            previous = Some(hash);
+        }
+
+        // the chain is cut after two deltas
+        assert_eq!(depths, vec![0, 1, 2, 0]);
+
+        // files larger than the delta size limit are stored in full
+        content[0] ^= 0xff;
+        fs::write(&input, &content)?;
+        let hash = blake3::hash(&content);
+        let options = StoreOptions {
+            chunking: &chunking,
+            codec: DEFAULT_CODEC,
+            delta_base: previous,
+            max_delta_depth: 2,
+            max_delta_size: 10_000,
+        };
+        store_blob(&storage, &input, &hash, &options)?;
+        assert_eq!(delta_header(&storage, &hash)?, None);
+
+        // a full blob takes up as many bytes as its content encoded again
+        let stored = fs::metadata(storage.join(hash.to_string()))?.len();
+        assert_eq!(full_blob_size(&storage, &hash, DEFAULT_CODEC)?, stored);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_legacy_blob() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let hash = blake3::hash(b"Some content");
+
+        // a blob compressed twice, as written by early versions
+        let once = zstd::encode_all(&b"Some content"[..], 0)?;
+        fs::write(temp_dir.path().join(hash.to_string()), zstd::encode_all(once.as_slice(), 0)?)?;
+
+        assert_eq!(upgrade_legacy_blob(temp_dir.path(), &hash)?, Some(12));
+        restore_blob(temp_dir.path(), &hash, &temp_dir.path().join("output.txt"))?;
+        assert_eq!(upgrade_legacy_blob(temp_dir.path(), &blake3::hash(b"missing"))?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_storage_removes_partial_files() -> io::Result<()> {
+        let temp_dir = tempdir()?;
+        let input = temp_dir.path().join("input.txt");
+        fs::write(&input, b"Some content")?;
+        let storage = temp_dir.path().join("storage");
+        fs::create_dir(&storage)?;
+
+        let hash = blake3::hash(b"Some content");
+        compress_and_store_file(&input, &storage.join(hash.to_string()), DEFAULT_CODEC)?;
+        let interrupted = storage.join(format!("{}interrupted", PARTIAL_PREFIX));
+        fs::write(&interrupted, b"half")?;
+        fs::write(storage.join(format!("{}writing", PARTIAL_PREFIX)), b"half")?;
+        File::options()
+            .write(true)
+            .open(&interrupted)?
+            .set_modified(SystemTime::now() - 2 * STALE_AFTER)?;
+
+        // a partial file that is still being written is kept
+        assert_eq!(recover_storage(&storage)?, 1);
+        assert!(!interrupted.exists());
+        assert_eq!(fs::read_dir(&storage)?.count(), 2);
+        assert!(storage.join(hash.to_string()).is_file());
+        assert_eq!(recover_storage(&temp_dir.path().join("missing"))?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the command line tests, each suite uses some of them.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// Runs `buckets` with `args` in `dir`.
+pub fn buckets(dir: &Path, args: &[&str]) -> assert_cmd::assert::Assert {
+    let mut cmd = assert_cmd::Command::cargo_bin("buckets").unwrap();
+    cmd.current_dir(dir).args(args).assert()
+}
+
+/// Creates the repository `test_repo` in `base` with the bucket `test_bucket` and returns the bucket directory.
+pub fn create_repo_with_bucket(base: &Path) -> PathBuf {
+    buckets(base, &["init", "test_repo"]).success();
+    let repo_dir = base.join("test_repo");
+    buckets(&repo_dir, &["create", "test_bucket"]).success();
+    repo_dir.join("test_bucket")
+}
+
+/// Commits the bucket in `bucket_dir` with `message`.
+pub fn commit(bucket_dir: &Path, message: &str) {
+    buckets(bucket_dir, &["commit", "-m", message]).success();
+}
+
+/// The id of the head commit of `test_bucket` in the repository `repo_dir`.
+pub fn head_commit_id(repo_dir: &Path) -> String {
+    let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+    conn.query_row("SELECT head_commit_id FROM buckets WHERE name = 'test_bucket'", [], |row| row.get(0))
+        .unwrap()
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, create_repo_with_bucket};
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test checking expectations before and after they are met.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets expect bucket concept_art`
+    /// 1. `$ buckets expect set file png concept`
+    /// 1. `$ buckets check`
+    /// 1. `$ buckets create concept_art`
+    /// 1. `$ echo "png" > test_bucket/concept/hero.png`
+    /// 1. `$ buckets check`
+    ///
+    /// # Expected output
+    /// The first check fails with both expectations missing, the second check succeeds.
+    ///
+    #[test]
+    fn test_check_expectations() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["check"])
+            .success()
+            .stdout(predicate::str::contains("Bucket test_bucket has no expectations."));
+
+        buckets(&bucket_dir, &["expect", "bucket", "concept_art"]).success();
+        buckets(&bucket_dir, &["expect", "set", "file", "png", "concept"]).success();
+
+        buckets(&bucket_dir, &["check"])
+            .code(1)
+            .stdout(predicate::str::contains("missing:   bucket concept_art exists"))
+            .stdout(predicate::str::contains("missing:   file of type png in concept"));
+
+        buckets(bucket_dir.parent().unwrap(), &["create", "concept_art"]).success();
+        fs::create_dir(bucket_dir.join("concept")).unwrap();
+        fs::write(bucket_dir.join("concept").join("hero.png"), b"png").unwrap();
+
+        buckets(&bucket_dir, &["check"])
+            .success()
+            .stdout(predicate::str::contains("satisfied: bucket concept_art exists"))
+            .stdout(predicate::str::contains("All expectations are met."));
+    }
+
+    /// Test listing and removing expectations.
+    ///
+    /// # Commands
+    /// 1. `$ buckets expect set file blend`
+    /// 1. `$ buckets expect list`
+    /// 1. `$ buckets expect remove 1`
+    /// 1. `$ buckets check`
+    ///
+    /// # Expected output
+    /// The expectation is listed and no expectations remain after removing it.
+    ///
+    #[test]
+    fn test_expect_list_and_remove() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["expect", "set", "file", "blend"]).success();
+        buckets(&bucket_dir, &["expect", "list"])
+            .success()
+            .stdout(predicate::str::contains("1. file of type blend in bucket"));
+
+        buckets(&bucket_dir, &["expect", "remove", "2"])
+            .failure()
+            .stderr(predicate::str::contains("Expectation 2 not found"));
+        buckets(&bucket_dir, &["expect", "remove", "1"]).success();
+
+        buckets(&bucket_dir, &["check"])
+            .success()
+            .stdout(predicate::str::contains("Bucket test_bucket has no expectations."));
+    }
+}
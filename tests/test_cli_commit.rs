@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod common;
+
 #[cfg(test)]
 use tempfile::tempdir;
 
@@ -8,6 +11,7 @@ mod tests {
     use std::path::PathBuf;
     use predicates::str::contains;
     use super::*;
+    use crate::common::{buckets, create_repo_with_bucket};
 
     /// Test the `commit` command with no files in the bucket.
     ///
@@ -22,26 +26,9 @@ mod tests {
     #[test]
     fn test_commit_no_files() {
         let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
 
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init.arg("init").arg("test_repo").assert().success();
-        let repo_dir = temp_dir.path().join("test_repo");
-
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(repo_dir.clone());
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .success();
-        let bucket_dir = repo_dir.join("test_bucket");
-
-        let mut cmd_commit = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_commit.current_dir(bucket_dir);
-        cmd_commit
-            .arg("commit")
-            .assert()
+        buckets(&bucket_dir, &["commit"])
             .stderr(contains("No files found in bucket."));
     }
 
@@ -60,54 +47,20 @@ mod tests {
     #[test]
     fn  test_commit_one_file() {
         let temp_dir = tempdir().unwrap();
-
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init.arg("init").arg("test_repo").assert().success();
-        let repo_dir = temp_dir.path().join("test_repo");
-
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(repo_dir.clone());
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .success();
-        let bucket_dir = repo_dir.join("test_bucket");
-
-        let mut cmd_commit = assert_cmd::Command::cargo_bin("buckets").unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
 
         // write a single file
         let file_path = bucket_dir.join("test_file");
         let mut file = File::create(file_path).unwrap();
         file.write_all(b"test").unwrap();
 
-        cmd_commit.current_dir(bucket_dir);
-        cmd_commit
-            .arg("commit")
-            .assert()
-            .success();
+        buckets(&bucket_dir, &["commit"]).success();
     }
 
     #[test]
     fn test_commit_multiple_files() {
         let temp_dir = tempdir().unwrap();
-
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init.arg("init").arg("test_repo").assert().success();
-        let repo_dir = temp_dir.path().join("test_repo");
-
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(repo_dir.clone());
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .success();
-        let bucket_dir = repo_dir.join("test_bucket");
-
-        let mut cmd_commit = assert_cmd::Command::cargo_bin("buckets").unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
 
         // write a single file
         let file_path = bucket_dir.join("test_file");
@@ -119,84 +72,37 @@ mod tests {
         let mut file = File::create(file_path).unwrap();
         file.write_all(b"test2").unwrap();
 
-        cmd_commit.current_dir(bucket_dir);
-        cmd_commit
-            .arg("commit")
-            .assert()
-            .success();
+        buckets(&bucket_dir, &["commit"]).success();
     }
 
     #[test]
     fn test_commit_second_commit() {
         let temp_dir = tempdir().unwrap();
-
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init.arg("init").arg("test_repo").assert().success();
-        let repo_dir = temp_dir.path().join("test_repo");
-
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(repo_dir.clone());
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .success();
-        let bucket_dir = repo_dir.join("test_bucket");
-
-        let mut cmd_commit = assert_cmd::Command::cargo_bin("buckets").unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
 
         // write a single file
         let file_path = bucket_dir.join("test_file");
         let mut file = File::create(file_path).unwrap();
         file.write_all(b"test").unwrap();
 
-        cmd_commit.current_dir(&bucket_dir);
-        cmd_commit
-            .arg("commit")
-            .assert()
-            .success();
-
-        let mut cmd_commit_2 = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_commit_2.current_dir(bucket_dir);
-        cmd_commit_2
-            .arg("commit")
-            .assert()
+        buckets(&bucket_dir, &["commit"]).success();
+
+        buckets(&bucket_dir, &["commit"])
             .stdout("No changes detected. Commit cancelled.\n");
     }
 
     #[test]
     fn test_commit_one_file_with_message() {
         let temp_dir = tempdir().unwrap();
-
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init.arg("init").arg("test_repo").assert().success();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
         let repo_dir = temp_dir.path().join("test_repo");
 
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(&repo_dir);
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .success();
-        let bucket_dir = repo_dir.join("test_bucket");
-
-        let mut cmd_commit = assert_cmd::Command::cargo_bin("buckets").unwrap();
-
         // write a single file
         let file_path = bucket_dir.join("test_file");
         let mut file = File::create(file_path).unwrap();
         file.write_all(b"test").unwrap();
 
-        cmd_commit.current_dir(bucket_dir);
-        cmd_commit
-            .arg("commit")
-            .arg("-m")
-            .arg("test commit")
-            .assert()
-            .success();
+        buckets(&bucket_dir, &["commit", "-m", "test commit"]).success();
 
         let message = match get_message_from_database(repo_dir) {
             Some(message) => message,
@@ -206,6 +112,157 @@ mod tests {
         assert_eq!(message, "test commit");
     }
 
+    /// Test that every commit references a manifest with the complete snapshot of the bucket.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "wall" > test_bucket/textures/wall.png`
+    /// 1. `$ echo "scene" > test_bucket/scene.blend`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "scene2" > test_bucket/scene.blend`
+    /// 1. `$ buckets commit -m "second commit"`
+    ///
+    /// # Expected output
+    /// Both manifests contain both files, the unchanged textures directory is stored once.
+    ///
+    #[test]
+    fn test_commit_stores_snapshot_manifest() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        let repo_dir = temp_dir.path().join("test_repo");
+
+        std::fs::create_dir_all(bucket_dir.join("textures")).unwrap();
+        std::fs::write(bucket_dir.join("textures").join("wall.png"), b"wall").unwrap();
+        std::fs::write(bucket_dir.join("scene.blend"), b"scene").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "first commit"]).success();
+
+        std::fs::write(bucket_dir.join("scene.blend"), b"scene2").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "second commit"]).success();
+
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets/buckets.db")).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT COUNT(*), SUM(te.size)
+                 FROM commits c
+                 JOIN manifest_trees mt ON mt.manifest_id = c.manifest_id
+                 JOIN tree_entries te ON te.tree_id = mt.tree_id
+                 GROUP BY c.id
+                 ORDER BY c.created_at",
+            )
+            .unwrap();
+        let snapshots: Vec<(i64, i64)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(snapshots, vec![(2, 9), (2, 10)]);
+
+        let trees: i64 = conn.query_row("SELECT COUNT(*) FROM trees", [], |row| row.get(0)).unwrap();
+        assert_eq!(trees, 3);
+    }
+
+    /// Test that a large file is stored in chunks and a small edit only stores the changed chunks.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo` with a chunking threshold of 16 KiB
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets commit` with a 64 KiB file
+    /// 1. `$ buckets commit` after changing one byte of the file
+    /// 1. `$ buckets revert all --yes` after deleting the file
+    ///
+    /// # Expected output
+    /// The second commit reuses all but one chunk and the file is restored from its chunks.
+    ///
+    #[test]
+    fn test_commit_large_file_in_chunks() {
+        let temp_dir = tempdir().unwrap();
+
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        let config_path = repo_dir.join(".buckets").join("config");
+        let config = std::fs::read_to_string(&config_path).unwrap();
+        let config = config
+            .replace("threshold = 8388608", "threshold = 16384")
+            .replace("average_size = 1048576", "average_size = 1024");
+        std::fs::write(&config_path, config).unwrap();
+
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        let mut state: u32 = 7;
+        let mut content: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        std::fs::write(bucket_dir.join("level.uasset"), &content).unwrap();
+        buckets(&bucket_dir, &["commit"]).success();
+
+        content[40_000] ^= 0xff;
+        std::fs::write(bucket_dir.join("level.uasset"), &content).unwrap();
+        buckets(&bucket_dir, &["commit"]).success();
+
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        let files: i64 = conn
+            .query_row("SELECT COUNT(DISTINCT hash) FROM file_chunks", [], |row| row.get(0))
+            .unwrap();
+        let chunks: i64 = conn
+            .query_row("SELECT COUNT(*) FROM file_chunks WHERE hash = ?1", [blake3::hash(&content).to_string()], |row| row.get(0))
+            .unwrap();
+        let distinct_chunks: i64 = conn
+            .query_row("SELECT COUNT(DISTINCT chunk_hash) FROM file_chunks", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 2);
+        assert!(chunks > 10);
+        assert!(distinct_chunks <= chunks + 2);
+
+        std::fs::remove_file(bucket_dir.join("level.uasset")).unwrap();
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+        assert_eq!(std::fs::read(bucket_dir.join("level.uasset")).unwrap(), content);
+    }
+
+    /// Test that files are compressed with the codec of the first matching compression rule.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets commit` with a PNG, a JSON and a BIN file
+    /// 1. `$ buckets revert all --yes` after deleting the files
+    ///
+    /// # Expected output
+    /// Every blob records its codec: none for the PNG, zstd 19 for the JSON and the default zstd 3 for the
+    /// BIN file. All three files are restored.
+    ///
+    #[test]
+    fn test_commit_compression_rules() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        let files: [(&str, &[u8], &[u8]); 3] = [
+            ("wall.png", b"\x89PNG already compressed", b"buckets blob 1\nnone\n"),
+            ("spawn.json", b"{\"x\": 1, \"y\": 2}", b"buckets blob 1\nzstd 19\n"),
+            ("data.bin", b"raw data", b"buckets blob 1\nzstd 3\n"),
+        ];
+        for (name, content, _) in files.iter() {
+            std::fs::write(bucket_dir.join(name), content).unwrap();
+        }
+        buckets(&bucket_dir, &["commit"]).success();
+
+        let storage = bucket_dir.join(".b").join("storage");
+        for (name, content, header) in files.iter() {
+            let blob = std::fs::read(storage.join(blake3::hash(content).to_string())).unwrap();
+            assert!(blob.starts_with(header), "{} has no {:?} header", name, String::from_utf8_lossy(header));
+            std::fs::remove_file(bucket_dir.join(name)).unwrap();
+        }
+
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+        for (name, content, _) in files.iter() {
+            assert_eq!(std::fs::read(bucket_dir.join(name)).unwrap(), *content);
+        }
+    }
+
     fn get_message_from_database(repo_dir: PathBuf) -> Option<String> {
         let db_location = repo_dir.join(".buckets/buckets.db");
         let conn = rusqlite::Connection::open(db_location).unwrap();
@@ -225,10 +282,6 @@ mod tests {
             Err(e) => panic!("Error getting the next row: {}", e),
         };
 
-        return match row.get(0).unwrap() {
-            Some(message) => Some(message),
-            None => None,
-        };
-
+        row.get(0).unwrap()
     }
 }
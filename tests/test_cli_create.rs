@@ -1,9 +1,13 @@
+#[cfg(test)]
+mod common;
+
 #[cfg(test)]
 use tempfile::tempdir;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::buckets;
     use predicates::prelude::predicate;
 
     /// Test the `create` command with no repository initialized.
@@ -17,13 +21,7 @@ mod tests {
     #[test]
     fn test_create_fail() {
         let temp_dir = tempdir().unwrap();
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(temp_dir.path());
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
-            .failure();
+        buckets(temp_dir.path(), &["create", "test_bucket"]).failure();
     }
 
     /// Test the `create` command with a repository initialized.
@@ -45,12 +43,7 @@ mod tests {
     fn test_create() {
         let temp_dir = tempdir().unwrap();
 
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init
-            .arg("init")
-            .arg("test_repo")
-            .assert()
+        buckets(temp_dir.path(), &["init", "test_repo"])
             .success()
             .stdout(predicate::str::contains(""))
             .stderr(predicate::str::is_empty());
@@ -59,12 +52,7 @@ mod tests {
         assert!(repo_dir.exists());
         assert!(repo_dir.is_dir());
 
-        let mut cmd_create = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_create.current_dir(temp_dir.path().join("test_repo"));
-        cmd_create
-            .arg("create")
-            .arg("test_bucket")
-            .assert()
+        buckets(&temp_dir.path().join("test_repo"), &["create", "test_bucket"])
             .success()
             .stdout(predicate::str::contains(""))
             .stderr(predicate::str::is_empty());
@@ -0,0 +1,82 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, create_repo_with_bucket};
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test finalizing a bucket with and without a version number.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets finalize`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit`
+    /// 1. `$ buckets finalize`
+    /// 1. `$ buckets finalize 1`
+    /// 1. `$ buckets finalize 2.0.0`
+    /// 1. `$ buckets versions`
+    ///
+    /// # Expected output
+    /// Versions 1 and 2.0.0 are finalized, the duplicate version 1 is refused.
+    ///
+    #[test]
+    fn test_finalize_versions() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["finalize"])
+            .failure()
+            .stderr(predicate::str::contains("No commits found in bucket"));
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        buckets(&bucket_dir, &["commit"]).success();
+
+        buckets(&bucket_dir, &["finalize"])
+            .success()
+            .stdout(predicate::str::contains("Finalized test_bucket version 1 at commit"));
+        buckets(&bucket_dir, &["finalize", "1"])
+            .failure()
+            .stderr(predicate::str::contains("Version 1 of bucket test_bucket already exists."));
+        buckets(&bucket_dir, &["finalize", "2.0.0"]).success();
+        buckets(&bucket_dir, &["finalize", "final"])
+            .failure()
+            .stderr(predicate::str::contains("Invalid version final"));
+
+        buckets(&bucket_dir, &["versions"])
+            .success()
+            .stdout(predicate::str::is_match(r"(?s)2\.0\.0\s+[0-9a-f]{12}.*\n1\s+[0-9a-f]{12}").unwrap());
+    }
+
+    /// Test finalizing a bucket with uncommitted changes.
+    ///
+    /// # Commands
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit`
+    /// 1. `$ echo "changed" > test_bucket/test_file`
+    /// 1. `$ buckets finalize`
+    ///
+    /// # Expected output
+    /// Error: Bucket has uncommitted changes.
+    ///
+    #[test]
+    fn test_finalize_uncommitted_changes() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        buckets(&bucket_dir, &["commit"]).success();
+        fs::write(bucket_dir.join("test_file"), b"changed").unwrap();
+
+        buckets(&bucket_dir, &["finalize"])
+            .failure()
+            .stderr(predicate::str::contains("Bucket has uncommitted changes"));
+    }
+}
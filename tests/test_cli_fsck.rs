@@ -0,0 +1,149 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Creates a repository with a bucket with two committed level files
+    fn create_committed_bucket(base: &Path) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        fs::write(bucket_dir.join("one.json"), b"{\"level\": 1}").unwrap();
+        fs::write(bucket_dir.join("two.json"), b"{\"level\": 2}").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "levels"]).success();
+        bucket_dir
+    }
+
+    /// Test finding and repairing a missing and a corrupt blob.
+    ///
+    /// # Commands
+    /// 1. `$ buckets fsck` on a healthy repository
+    /// 1. `$ buckets fsck` after deleting one blob and overwriting the other
+    /// 1. `$ buckets fsck --repair`
+    /// 1. `$ buckets fsck`
+    ///
+    /// # Expected output
+    /// Both problems are reported and fail the check, the corrupt blob is moved to the quarantine and both
+    /// blobs are restored from the unchanged files in the bucket.
+    ///
+    #[test]
+    fn test_fsck_repairs_blobs() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        let storage = bucket_dir.join(".b").join("storage");
+        let one = blake3::hash(b"{\"level\": 1}").to_string();
+        let two = blake3::hash(b"{\"level\": 2}").to_string();
+
+        buckets(&bucket_dir, &["fsck"])
+            .success()
+            .stdout(predicate::str::contains("No problems found."));
+
+        fs::remove_file(storage.join(&one)).unwrap();
+        fs::write(storage.join(&two), b"bad network drive").unwrap();
+        buckets(&bucket_dir, &["fsck"])
+            .code(1)
+            .stdout(predicate::str::contains("Missing blobs: 1"))
+            .stdout(predicate::str::contains(format!("{} of test_bucket/one.json", one)))
+            .stdout(predicate::str::contains("Corrupt blobs: 1"))
+            .stdout(predicate::str::contains("Found 2 problem(s)"));
+
+        buckets(&bucket_dir, &["fsck", "--repair"])
+            .success()
+            .stdout(predicate::str::contains("restored from test_bucket/one.json"))
+            .stdout(predicate::str::contains("moved to .buckets/quarantine"))
+            .stdout(predicate::str::contains("restored from test_bucket/two.json"))
+            .stdout(predicate::str::contains("Found 3 problem(s), repaired 3."));
+        assert!(temp_dir.path().join("test_repo/.buckets/quarantine").join(&two).is_file());
+
+        buckets(&bucket_dir, &["fsck"])
+            .success()
+            .stdout(predicate::str::contains("No problems found."));
+    }
+
+    /// Test that a repaired blob is compressed with the rule matching the path of its file in the bucket.
+    ///
+    /// # Commands
+    /// 1. Add a `[[compression]]` rule `raw/*.json` with codec `none` before the other rules
+    /// 1. `$ buckets commit` with `raw/three.json`
+    /// 1. `$ buckets fsck --repair` after deleting its blob
+    ///
+    /// # Expected output
+    /// The blob is restored with the codec `none` of the `raw/*.json` rule, not `zstd 19` of `*.json`.
+    ///
+    #[test]
+    fn test_fsck_repair_uses_codec_of_path() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        let storage = bucket_dir.join(".b").join("storage");
+        let config_path = temp_dir.path().join("test_repo/.buckets/config");
+        let config = fs::read_to_string(&config_path).unwrap().replacen(
+            "[[compression]]",
+            "[[compression]]\npattern = \"raw/*.json\"\ncodec = \"none\"\n\n[[compression]]",
+            1,
+        );
+        fs::write(&config_path, config).unwrap();
+
+        fs::create_dir_all(bucket_dir.join("raw")).unwrap();
+        fs::write(bucket_dir.join("raw").join("three.json"), b"{\"level\": 3}").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "raw level"]).success();
+        let three = blake3::hash(b"{\"level\": 3}").to_string();
+        assert!(fs::read(storage.join(&three)).unwrap().starts_with(b"buckets blob 1\nnone\n"));
+
+        fs::remove_file(storage.join(&three)).unwrap();
+        buckets(&bucket_dir, &["fsck", "--repair"])
+            .success()
+            .stdout(predicate::str::contains("restored from test_bucket/raw/three.json"));
+        assert!(fs::read(storage.join(&three)).unwrap().starts_with(b"buckets blob 1\nnone\n"));
+    }
+
+    /// Test finding a bucket without `.b/info` and a commit of a bucket that doesn't exist.
+    ///
+    /// # Commands
+    /// 1. `$ buckets fsck` after removing `.b/info` and adding a commit of a missing bucket to the database
+    /// 1. `$ buckets fsck --repair`
+    ///
+    /// # Expected output
+    /// The `.b/info` is written again, the orphaned commit can't be repaired and fails the check.
+    ///
+    #[test]
+    fn test_fsck_bucket_info_and_orphaned_commits() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        let repo_dir = temp_dir.path().join("test_repo");
+
+        fs::remove_file(bucket_dir.join(".b").join("info")).unwrap();
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        // repositories created before foreign keys were enforced can hold such a commit
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO commits (id, bucket_id, parent_id, manifest_id, author, message, created_at)
+             SELECT 'ORPHANED', 'MISSING', NULL, manifest_id, author, message, created_at FROM commits",
+            [],
+        )
+        .unwrap();
+
+        buckets(&repo_dir, &["fsck"])
+            .code(1)
+            .stdout(predicate::str::contains("Bucket info: 1"))
+            .stdout(predicate::str::contains("test_bucket at test_bucket: missing"))
+            .stdout(predicate::str::contains("Orphaned commits: 1"))
+            .stdout(predicate::str::contains("ORPHANED references missing bucket MISSING"));
+
+        buckets(&repo_dir, &["fsck", "--repair"])
+            .code(1)
+            .stdout(predicate::str::contains("Found 2 problem(s), repaired 1."));
+        assert!(bucket_dir.join(".b").join("info").is_file());
+        buckets(&bucket_dir, &["status"]).success();
+    }
+}
@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, commit, create_repo_with_bucket};
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Creates a repository with a committed bucket and a dropped stash, which leaves one unreachable blob
+    fn create_bucket_with_dropped_stash(base: &Path) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        fs::write(bucket_dir.join("level.json"), b"{\"enemies\": 3}").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "level"]).success();
+
+        fs::write(bucket_dir.join("level.json"), b"{\"enemies\": 300}").unwrap();
+        buckets(&bucket_dir, &["stash", "--name", "wip"]).success();
+        buckets(&bucket_dir, &["stash", "drop", "wip"]).success();
+        bucket_dir
+    }
+
+    /// Test removing the blob of a dropped stash.
+    ///
+    /// # Commands
+    /// 1. `$ buckets commit`, `$ buckets stash` and `$ buckets stash drop`
+    /// 1. `$ buckets gc`
+    /// 1. `$ buckets gc --dry-run --grace-period 0`
+    /// 1. `$ buckets gc --grace-period 0`
+    /// 1. `$ buckets revert all --yes` after deleting the committed file
+    ///
+    /// # Expected output
+    /// The blob is kept within the grace period and by a dry run, then removed. The committed file is
+    /// still restored.
+    ///
+    #[test]
+    fn test_gc_removes_unreachable_blobs() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_bucket_with_dropped_stash(temp_dir.path());
+        let stashed = bucket_dir.join(".b").join("storage").join(blake3::hash(b"{\"enemies\": 300}").to_string());
+        assert!(stashed.is_file());
+
+        buckets(&bucket_dir, &["gc"])
+            .success()
+            .stdout(predicate::str::contains("No unreachable blobs to remove."))
+            .stdout(predicate::str::contains("Kept 1 unreachable blob(s) younger than 14 day(s)."));
+
+        buckets(&bucket_dir, &["gc", "--dry-run", "--grace-period", "0"])
+            .success()
+            .stdout(predicate::str::is_match(r"1 unreachable blob\(s\) of \d+ bytes can be reclaimed.").unwrap());
+        assert!(stashed.is_file());
+
+        buckets(&bucket_dir, &["gc", "--grace-period", "0"])
+            .success()
+            .stdout(predicate::str::contains("Removed 1 unreachable blob(s)"));
+        assert!(!stashed.exists());
+
+        fs::remove_file(bucket_dir.join("level.json")).unwrap();
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+        assert_eq!(fs::read(bucket_dir.join("level.json")).unwrap(), b"{\"enemies\": 3}");
+    }
+
+    /// Test quarantining an unreachable blob that is stored in a packfile.
+    ///
+    /// # Commands
+    /// 1. `$ buckets commit`, `$ buckets stash` and `$ buckets stash drop`
+    /// 1. `$ buckets pack`
+    /// 1. `$ buckets gc --quarantine --grace-period 0`
+    /// 1. `$ buckets revert all --yes` after deleting the committed file
+    ///
+    /// # Expected output
+    /// The blob is moved to `.buckets/quarantine`, the committed blob is packed again and its file restored.
+    ///
+    #[test]
+    fn test_gc_quarantines_packed_blobs() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_bucket_with_dropped_stash(temp_dir.path());
+        let repo_dir = temp_dir.path().join("test_repo");
+        buckets(&bucket_dir, &["pack"]).success();
+
+        buckets(&bucket_dir, &["gc", "--quarantine", "--grace-period", "0"])
+            .success()
+            .stdout(predicate::str::contains("Moved 1 unreachable blob(s)"));
+
+        let quarantine = repo_dir.join(".buckets").join("quarantine");
+        assert!(quarantine.join(blake3::hash(b"{\"enemies\": 300}").to_string()).is_file());
+        buckets(&bucket_dir, &["gc", "--grace-period", "0"])
+            .success()
+            .stdout(predicate::str::contains("No unreachable blobs to remove."));
+
+        fs::remove_file(bucket_dir.join("level.json")).unwrap();
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+        assert_eq!(fs::read(bucket_dir.join("level.json")).unwrap(), b"{\"enemies\": 3}");
+    }
+
+    /// Test that files left behind by an interrupted blob write are removed by gc.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit`
+    /// 1. `$ echo "half" > test_bucket/.b/storage/.partial-interrupted`, two hours old
+    /// 1. `$ echo "half" > test_bucket/.b/storage/.partial-writing`
+    /// 1. `$ buckets status`
+    /// 1. `$ buckets gc`
+    ///
+    /// # Expected output
+    /// Status leaves the storage alone. Gc removes the old partial file and keeps the one that can still be
+    /// written by another command, the stored blob is kept.
+    ///
+    #[test]
+    fn test_gc_recovers_partial_blobs() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "test");
+
+        let storage = bucket_dir.join(".b").join("storage");
+        let partial = storage.join(".partial-interrupted");
+        let writing = storage.join(".partial-writing");
+        fs::write(&partial, b"half").unwrap();
+        fs::write(&writing, b"half").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&partial)
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - std::time::Duration::from_secs(2 * 60 * 60))
+            .unwrap();
+
+        buckets(&bucket_dir, &["status"]).success();
+        assert!(partial.exists());
+
+        buckets(&bucket_dir, &["gc"])
+            .success()
+            .stdout(predicate::str::contains("Removed 1 partially written file(s)"));
+
+        assert!(!partial.exists());
+        assert!(writing.exists());
+        assert!(storage.join(blake3::hash(b"test").to_string()).is_file());
+    }
+}
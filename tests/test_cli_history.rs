@@ -0,0 +1,258 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, commit, create_repo_with_bucket, head_commit_id};
+    use predicates::prelude::{predicate, PredicateBooleanExt};
+    use std::fs;
+
+    /// Test the `history` command on a bucket without commits.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets history`
+    ///
+    /// # Expected output
+    /// No commits found in bucket test_bucket.
+    ///
+    #[test]
+    fn test_history_no_commits() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["history"])
+            .success()
+            .stdout(predicate::str::contains("No commits found in bucket test_bucket."));
+    }
+
+    /// Test the `history` command with two commits.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "test2" > test_bucket/test_file2`
+    /// 1. `$ buckets commit -m "second commit"`
+    /// 1. `$ buckets history --files`
+    /// 1. `$ buckets history -n 1`
+    ///
+    /// # Expected output
+    /// Both commits newest first, and only the newest commit when limited.
+    ///
+    #[test]
+    fn test_history_two_commits() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::write(bucket_dir.join("test_file2"), b"test2").unwrap();
+        commit(&bucket_dir, "second commit");
+
+        let output = buckets(&bucket_dir, &["history", "--files"])
+            .success()
+            .stdout(predicate::str::contains("added:    test_file2"))
+            .get_output()
+            .stdout
+            .clone();
+        let output = String::from_utf8(output).unwrap();
+        let second = output.find("second commit").unwrap();
+        let first = output.find("first commit").unwrap();
+        assert!(second < first, "History is not ordered newest first");
+
+        buckets(&bucket_dir, &["history", "-n", "1"])
+            .success()
+            .stdout(predicate::str::contains("second commit"))
+            .stdout(predicate::str::contains("first commit").not())
+            .stdout(predicate::str::contains("Changed: 1 file(s)"));
+    }
+
+    /// Test that commits in one bucket do not become the parent of commits in another bucket.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets create other_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"` in test_bucket
+    /// 1. `$ echo "other" > other_bucket/other_file`
+    /// 1. `$ buckets commit -m "other commit"` in other_bucket
+    /// 1. `$ echo "test2" > test_bucket/test_file2`
+    /// 1. `$ buckets commit -m "second commit"` in test_bucket
+    /// 1. `$ buckets history --files` in test_bucket
+    ///
+    /// # Expected output
+    /// The second commit only adds test_file2 and the history doesn't contain the other bucket's commit.
+    ///
+    #[test]
+    fn test_history_is_scoped_to_bucket() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        let repo_dir = bucket_dir.parent().unwrap();
+
+        buckets(repo_dir, &["create", "other_bucket"]).success();
+        let other_dir = repo_dir.join("other_bucket");
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::write(other_dir.join("other_file"), b"other").unwrap();
+        commit(&other_dir, "other commit");
+        fs::write(bucket_dir.join("test_file2"), b"test2").unwrap();
+        commit(&bucket_dir, "second commit");
+
+        buckets(&bucket_dir, &["history", "--files"])
+            .success()
+            .stdout(predicate::str::contains("first commit"))
+            .stdout(predicate::str::contains("second commit"))
+            .stdout(predicate::str::contains("other commit").not())
+            .stdout(predicate::str::contains("Changed: 1 file(s)"))
+            .stdout(predicate::str::contains("other_file").not());
+
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        let parents: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM commits c JOIN commits p ON p.id = c.parent_id WHERE p.bucket_id = c.bucket_id",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(parents, 1);
+    }
+
+    /// Test that modified, deleted and moved files are recorded in the history.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "wall" > test_bucket/old/wall.png`
+    /// 1. `$ echo "floor" > test_bucket/floor.png`
+    /// 1. `$ echo "roof" > test_bucket/roof.png`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "floor2" > test_bucket/floor.png`
+    /// 1. `$ buckets commit -m "modify floor"`
+    /// 1. `$ mv test_bucket/old/wall.png test_bucket/new/wall.png`
+    /// 1. `$ rm test_bucket/roof.png`
+    /// 1. `$ buckets status`
+    /// 1. `$ buckets commit -m "reorganise"`
+    /// 1. `$ buckets history --files`
+    ///
+    /// # Expected output
+    /// The move is shown as a rename in status and history, the removal as a deletion.
+    ///
+    #[test]
+    fn test_history_modified_deleted_and_renamed() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::create_dir_all(bucket_dir.join("old")).unwrap();
+        fs::write(bucket_dir.join("old").join("wall.png"), b"wall").unwrap();
+        fs::write(bucket_dir.join("floor.png"), b"floor").unwrap();
+        fs::write(bucket_dir.join("roof.png"), b"roof").unwrap();
+        commit(&bucket_dir, "first commit");
+
+        fs::write(bucket_dir.join("floor.png"), b"floor2").unwrap();
+        commit(&bucket_dir, "modify floor");
+
+        fs::create_dir_all(bucket_dir.join("new")).unwrap();
+        fs::rename(bucket_dir.join("old").join("wall.png"), bucket_dir.join("new").join("wall.png")).unwrap();
+        fs::remove_file(bucket_dir.join("roof.png")).unwrap();
+
+        buckets(&bucket_dir, &["status"])
+            .success()
+            .stdout(predicate::str::contains("Renamed files:\n    old/wall.png -> new/wall.png"))
+            .stdout(predicate::str::contains("Deleted files:\n    roof.png"))
+            .stdout(predicate::str::contains("New files:").not());
+
+        commit(&bucket_dir, "reorganise");
+
+        buckets(&bucket_dir, &["history", "--files"])
+            .success()
+            .stdout(predicate::str::contains("modified: floor.png"))
+            .stdout(predicate::str::contains("deleted:  roof.png"))
+            .stdout(predicate::str::contains("renamed:  old/wall.png -> new/wall.png"));
+    }
+
+    /// Test that the `history` command stops walking the history at the limit.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "test2" > test_bucket/test_file2`
+    /// 1. `$ buckets commit -m "second commit"`
+    /// 1. Remove the first commit from the database
+    /// 1. `$ buckets history -n 1`
+    /// 1. `$ buckets history`
+    ///
+    /// # Expected output
+    /// The limited history never reads the first commit, the full history reports it missing.
+    ///
+    #[test]
+    fn test_history_limit_stops_at_limit() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::write(bucket_dir.join("test_file2"), b"test2").unwrap();
+        commit(&bucket_dir, "second commit");
+
+        let conn = rusqlite::Connection::open(bucket_dir.parent().unwrap().join(".buckets").join("buckets.db")).unwrap();
+        conn.execute_batch("PRAGMA foreign_keys = OFF; DELETE FROM commits WHERE message = 'first commit';")
+            .unwrap();
+
+        buckets(&bucket_dir, &["history", "-n", "1"])
+            .success()
+            .stdout(predicate::str::contains("second commit"));
+
+        buckets(&bucket_dir, &["history"])
+            .failure()
+            .stderr(predicate::str::contains("is missing from the database"));
+    }
+
+    /// Test that the `history` command reports a cycle in the parents of the commits.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "test2" > test_bucket/test_file2`
+    /// 1. `$ buckets commit -m "second commit"`
+    /// 1. Make the second commit the parent of the first commit in the database
+    /// 1. `$ buckets history`
+    ///
+    /// # Expected output
+    /// Can not show history of the bucket: ... the history has a cycle.
+    ///
+    #[test]
+    fn test_history_with_cycle() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        let repo_dir = temp_dir.path().join("test_repo");
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::write(bucket_dir.join("test_file2"), b"test2").unwrap();
+        commit(&bucket_dir, "second commit");
+
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        conn.execute(
+            "UPDATE commits SET parent_id = ?1 WHERE message = 'first commit'",
+            [head_commit_id(&repo_dir)],
+        )
+        .unwrap();
+
+        buckets(&bucket_dir, &["history"])
+            .failure()
+            .stderr(predicate::str::contains("the history has a cycle"));
+    }
+}
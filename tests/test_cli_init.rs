@@ -1,9 +1,13 @@
+#[cfg(test)]
+mod common;
+
 #[cfg(test)]
 use tempfile::tempdir;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::buckets;
 
     /// Test the `init` command.
     ///
@@ -20,12 +24,7 @@ mod tests {
     #[test]
     fn test_cli_init() {
         let temp_dir = tempdir().unwrap();
-        let mut cmd = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd.current_dir(temp_dir.path())
-            .arg("init")
-            .arg("test_repo")
-            .assert()
-            .success();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
 
         let repo_dir = temp_dir.path().join("test_repo");
         assert!(repo_dir.exists());
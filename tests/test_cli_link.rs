@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::predicate;
+    use std::path::{Path, PathBuf};
+
+    fn create_repo_with_buckets(base: &Path, names: &[&str]) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+
+        for name in names {
+            buckets(&repo_dir, &["create", name]).success();
+        }
+        repo_dir
+    }
+
+    /// Test linking buckets into a workflow.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create concept`, `model` and `texture`
+    /// 1. `$ buckets link concept model`
+    /// 1. `$ buckets link model texture` from inside `model`, using directories
+    /// 1. `$ buckets link texture concept`
+    /// 1. `$ buckets links` from inside `model`
+    ///
+    /// # Expected output
+    /// The cycle is refused and `model` shows one upstream and one downstream bucket.
+    ///
+    #[test]
+    fn test_link_buckets() {
+        let temp_dir = tempdir().unwrap();
+        let repo_dir = create_repo_with_buckets(temp_dir.path(), &["concept", "model", "texture"]);
+
+        buckets(&repo_dir, &["link", "concept", "model"])
+            .success()
+            .stdout(predicate::str::contains("Linked concept to model"));
+        buckets(&repo_dir.join("model"), &["link", ".", "../texture"])
+            .success()
+            .stdout(predicate::str::contains("Linked model to texture"));
+
+        buckets(&repo_dir, &["link", "texture", "concept"])
+            .failure()
+            .stderr(predicate::str::contains("would create a cycle"));
+        buckets(&repo_dir, &["link", "concept", "unknown"])
+            .failure()
+            .stderr(predicate::str::contains("Unknown bucket unknown."));
+
+        buckets(&repo_dir.join("model"), &["links"])
+            .success()
+            .stdout(predicate::str::contains("Upstream of model:\n    concept\n"))
+            .stdout(predicate::str::contains("Downstream of model:\n    texture\n"));
+    }
+
+    /// Test removing a link.
+    ///
+    /// # Commands
+    /// 1. `$ buckets link concept model`
+    /// 1. `$ buckets unlink concept model`
+    /// 1. `$ buckets unlink concept model`
+    ///
+    /// # Expected output
+    /// The second unlink fails because the buckets are no longer linked.
+    ///
+    #[test]
+    fn test_unlink_buckets() {
+        let temp_dir = tempdir().unwrap();
+        let repo_dir = create_repo_with_buckets(temp_dir.path(), &["concept", "model"]);
+
+        buckets(&repo_dir, &["link", "concept", "model"]).success();
+        buckets(&repo_dir, &["unlink", "concept", "model"])
+            .success()
+            .stdout(predicate::str::contains("Unlinked concept from model"));
+        buckets(&repo_dir, &["unlink", "concept", "model"])
+            .failure()
+            .stderr(predicate::str::contains("Bucket concept is not linked to model."));
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test the `list` command with two buckets, one of which was removed from disk.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets create other_bucket`
+    /// 1. `$ rm -r other_bucket`
+    /// 1. `$ buckets list`
+    ///
+    /// # Expected output
+    /// Both buckets are listed, `other_bucket` is reported missing.
+    ///
+    #[test]
+    fn test_list_buckets() {
+        let temp_dir = tempdir().unwrap();
+
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+
+        for name in ["test_bucket", "other_bucket"] {
+            buckets(&repo_dir, &["create", name]).success();
+        }
+        fs::remove_dir_all(repo_dir.join("other_bucket")).unwrap();
+
+        buckets(&repo_dir.join("test_bucket"), &["list"])
+            .success()
+            .stdout(predicate::str::is_match(r"test_bucket\s+test_bucket\s+[0-9A-F-]{36}\s+-\s+ok").unwrap())
+            .stdout(predicate::str::is_match(r"other_bucket\s+other_bucket\s+[0-9A-F-]{36}\s+-\s+missing").unwrap());
+    }
+
+    /// Test the `list` command outside of a repository.
+    ///
+    /// # Commands
+    /// `$ buckets list`
+    ///
+    /// # Expected output
+    /// Error: Not in a bucket repository.
+    ///
+    #[test]
+    fn test_list_not_in_repo() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["list"])
+            .failure()
+            .stderr(predicate::str::contains("Not in a bucket repository"));
+    }
+}
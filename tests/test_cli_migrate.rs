@@ -0,0 +1,194 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Blobs written by early versions were compressed twice
+    fn write_legacy_blob(storage: &Path, content: &[u8]) -> String {
+        let hash = blake3::hash(content).to_string();
+        let once = zstd::stream::encode_all(content, 0).unwrap();
+        fs::write(storage.join(&hash), zstd::stream::encode_all(once.as_slice(), 0).unwrap()).unwrap();
+        hash
+    }
+
+    /// Creates a repository with the database layout from before schema versions existed, with two commits.
+    fn create_legacy_repo(base: &Path) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        let db_path = repo_dir.join(".buckets").join("buckets.db");
+        let (id, name, path): (String, String, String) = rusqlite::Connection::open(&db_path)
+            .unwrap()
+            .query_row("SELECT id, name, path FROM buckets", [], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap();
+        fs::remove_file(&db_path).unwrap();
+
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE buckets (id CHAR(36) PRIMARY KEY, name TEXT NOT NULL, path TEXT NOT NULL);
+             CREATE TABLE commits (id CHAR(36) PRIMARY KEY, bucket_id INTEGER NOT NULL, message TEXT NOT NULL,
+                 created_at TEXT, FOREIGN KEY (bucket_id) REFERENCES buckets (id));
+             CREATE TRIGGER set_timestamp_after_insert AFTER INSERT ON commits BEGIN
+                 UPDATE commits SET created_at = CURRENT_TIMESTAMP WHERE rowid = NEW.rowid; END;
+             CREATE TABLE files (id CHAR(36) PRIMARY KEY, commit_id INTEGER NOT NULL, file_path TEXT NOT NULL,
+                 hash TEXT NOT NULL, FOREIGN KEY (commit_id) REFERENCES commits (id), UNIQUE (commit_id, file_path, hash));
+             CREATE TRIGGER AutoGenCommitsGUID AFTER INSERT ON commits FOR EACH ROW WHEN (NEW.id IS NULL) BEGIN
+                 UPDATE commits SET id = hex(randomblob(16)) WHERE rowid = NEW.rowid; END;
+             CREATE TRIGGER AutoGenFilesGUID AFTER INSERT ON files FOR EACH ROW WHEN (NEW.id IS NULL) BEGIN
+                 UPDATE files SET id = hex(randomblob(16)) WHERE rowid = NEW.rowid; END;",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO buckets (id, name, path) VALUES (?1, ?2, ?3)", [&id, &name, &path])
+            .unwrap();
+
+        let storage = bucket_dir.join(".b").join("storage");
+        fs::create_dir_all(&storage).unwrap();
+        let wall = write_legacy_blob(&storage, b"wall");
+        let floor = write_legacy_blob(&storage, b"floor");
+        let roof = write_legacy_blob(&storage, b"roof");
+
+        conn.execute_batch(&format!(
+            "INSERT INTO commits (id, bucket_id, message) VALUES ('C1', '{id}', 'first commit');
+             UPDATE commits SET created_at = '2024-01-01 10:00:00' WHERE id = 'C1';
+             INSERT INTO files (id, commit_id, file_path, hash) VALUES ('F1', 'C1', 'wall.png', '{wall}');
+             INSERT INTO files (id, commit_id, file_path, hash) VALUES ('F2', 'C1', 'floor.png', '{floor}');
+             INSERT INTO commits (id, bucket_id, message) VALUES ('C2', '{id}', 'second commit');
+             UPDATE commits SET created_at = '2024-01-02 10:00:00' WHERE id = 'C2';
+             INSERT INTO files (id, commit_id, file_path, hash) VALUES ('F3', 'C2', 'wall.png', '{wall}');
+             INSERT INTO files (id, commit_id, file_path, hash) VALUES ('F4', 'C2', 'roof.png', '{roof}');"
+        ))
+        .unwrap();
+
+        fs::write(bucket_dir.join("wall.png"), b"wall").unwrap();
+        fs::write(bucket_dir.join("roof.png"), b"roof").unwrap();
+        bucket_dir
+    }
+
+    /// Test the `migrate` command on a repository created before schema versions existed.
+    ///
+    /// # Commands
+    /// 1. Create a repository with the original database layout and two commits
+    /// 1. `$ buckets migrate`
+    /// 1. `$ buckets migrate`
+    /// 1. `$ buckets history --files`
+    /// 1. `$ rm test_bucket/wall.png`
+    /// 1. `$ buckets revert all`
+    ///
+    /// # Expected output
+    /// The migrations are applied once, the history shows the changes of the old commits and files can be
+    /// restored from the old blobs.
+    ///
+    #[test]
+    fn test_migrate_legacy_repository() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_legacy_repo(temp_dir.path());
+
+        buckets(&bucket_dir, &["migrate"])
+            .success()
+            .stdout(predicate::str::contains("Applied migration 2:"))
+            .stdout(predicate::str::contains("Applied migration 3:"))
+            .stdout(predicate::str::contains("Applied migration 4:"));
+
+        buckets(&bucket_dir, &["migrate"])
+            .success()
+            .stdout(predicate::str::contains("Repository database is up to date at schema version"));
+
+        buckets(&bucket_dir, &["history", "--files"])
+            .success()
+            .stdout(predicate::str::is_match(r"commit [0-9a-f]{64}").unwrap())
+            .stdout(predicate::str::contains("added:    roof.png"))
+            .stdout(predicate::str::contains("deleted:  floor.png"))
+            .stdout(predicate::str::contains("added:    wall.png"));
+
+        buckets(&bucket_dir, &["status"])
+            .success()
+            .stdout(predicate::str::contains("No changes since last commit."));
+
+        fs::remove_file(bucket_dir.join("wall.png")).unwrap();
+        buckets(&bucket_dir, &["revert", "all", "-y"]).success();
+        assert_eq!(fs::read(bucket_dir.join("wall.png")).unwrap(), b"wall");
+    }
+
+    /// Test that a repository is migrated automatically by a command that changes it, and refused by a command
+    /// that only reads it.
+    ///
+    /// # Commands
+    /// 1. Create a repository with the original database layout and two commits
+    /// 1. `$ buckets status`
+    /// 1. `$ echo "door" > test_bucket/door.png`
+    /// 1. `$ buckets commit -m "third commit"`
+    /// 1. `$ buckets status`
+    ///
+    /// # Expected output
+    /// The first status is refused with a hint to run `bucket migrate`, the commit migrates the repository
+    /// after which the status is shown.
+    ///
+    #[test]
+    fn test_migrate_automatically() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_legacy_repo(temp_dir.path());
+        let db_path = temp_dir.path().join("test_repo").join(".buckets").join("buckets.db");
+        let user_version = || -> i64 {
+            rusqlite::Connection::open(&db_path)
+                .unwrap()
+                .query_row("PRAGMA user_version", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        buckets(&bucket_dir, &["status"])
+            .failure()
+            .stderr(predicate::str::contains("Repository database has schema version 1"))
+            .stderr(predicate::str::contains("Run `bucket migrate` to update it"));
+        assert_eq!(user_version(), 0);
+
+        fs::write(bucket_dir.join("door.png"), b"door").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "third commit"])
+            .success()
+            .stderr(predicate::str::contains("Migrated repository database to schema version"));
+
+        buckets(&bucket_dir, &["status"])
+            .success()
+            .stdout(predicate::str::contains("No changes since last commit."));
+    }
+
+    /// Test that a repository with a newer schema is refused.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. Set the schema version of the database to 99
+    /// 1. `$ buckets status`
+    ///
+    /// # Expected output
+    /// Can not open repository: Repository database has schema version 99, ...
+    ///
+    #[test]
+    fn test_refuse_newer_schema() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        conn.pragma_update(None, "user_version", 99).unwrap();
+
+        buckets(&repo_dir.join("test_bucket"), &["status"])
+            .failure()
+            .stderr(predicate::str::contains("Repository database has schema version 99"));
+
+        buckets(&repo_dir.join("test_bucket"), &["migrate"])
+            .failure()
+            .stderr(predicate::str::contains("Repository database has schema version 99"));
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::{predicate, PredicateBooleanExt};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Creates a repository with a bucket with a committed material file for every number
+    fn create_bucket_with_materials(base: &Path, count: usize) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        for i in 0..count {
+            fs::write(bucket_dir.join(format!("material_{}.json", i)), format!("{{\"roughness\": {}}}", i)).unwrap();
+        }
+        bucket_dir
+    }
+
+    fn loose_blobs(storage: &Path) -> usize {
+        fs::read_dir(storage)
+            .unwrap()
+            .filter(|entry| entry.as_ref().unwrap().path().is_file())
+            .count()
+    }
+
+    /// Test packing the loose blobs of a bucket and restoring files from the packfile.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets commit` with 20 small files
+    /// 1. `$ buckets pack`
+    /// 1. `$ buckets revert all --yes` after deleting the files
+    ///
+    /// # Expected output
+    /// The blobs are moved into one packfile and the files are restored from it.
+    ///
+    #[test]
+    fn test_pack() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_bucket_with_materials(temp_dir.path(), 20);
+        buckets(&bucket_dir, &["commit", "-m", "materials"]).success();
+
+        let storage = bucket_dir.join(".b").join("storage");
+        assert_eq!(loose_blobs(&storage), 20);
+
+        buckets(&bucket_dir, &["pack"])
+            .success()
+            .stdout(predicate::str::contains("Packed 20 blob(s)"));
+        assert_eq!(loose_blobs(&storage), 0);
+        assert_eq!(fs::read_dir(storage.join("packs")).unwrap().count(), 2);
+
+        buckets(&bucket_dir, &["pack"])
+            .success()
+            .stdout(predicate::str::contains("No loose blobs to pack."));
+        buckets(&bucket_dir, &["storage"])
+            .success()
+            .stdout(predicate::str::contains("Packed blobs: 20 in 1 pack(s)"));
+
+        for i in 0..20 {
+            fs::remove_file(bucket_dir.join(format!("material_{}.json", i))).unwrap();
+        }
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+        assert_eq!(fs::read_to_string(bucket_dir.join("material_7.json")).unwrap(), "{\"roughness\": 7}");
+    }
+
+    /// Test that a commit packs the storage once it holds too many loose blobs.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo` with `auto_pack = 10`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets commit` with 5 small files
+    /// 1. `$ buckets commit` with 10 more small files
+    ///
+    /// # Expected output
+    /// The second commit packs all 15 loose blobs.
+    ///
+    #[test]
+    fn test_auto_pack() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_bucket_with_materials(temp_dir.path(), 5);
+        let config_path = temp_dir.path().join("test_repo").join(".buckets").join("config");
+        let config = fs::read_to_string(&config_path).unwrap().replace("auto_pack = 1000", "auto_pack = 10");
+        fs::write(&config_path, config).unwrap();
+
+        buckets(&bucket_dir, &["commit", "-m", "first"])
+            .success()
+            .stdout(predicate::str::contains("Packed").not());
+
+        for i in 5..15 {
+            fs::write(bucket_dir.join(format!("material_{}.json", i)), format!("{{\"roughness\": {}}}", i)).unwrap();
+        }
+        buckets(&bucket_dir, &["commit", "-m", "second"])
+            .success()
+            .stdout(predicate::str::contains("Packed 15 loose blob(s) into a packfile."));
+        assert_eq!(loose_blobs(&bucket_dir.join(".b").join("storage")), 0);
+    }
+}
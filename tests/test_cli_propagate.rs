@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::{predicate, PredicateBooleanExt};
+    use std::fs;
+
+    /// Test delivering the output of a finalized bucket to a linked bucket.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create concept_art` and `$ buckets create model`
+    /// 1. `$ buckets link concept_art model`
+    /// 1. `$ buckets expect set file png` in `concept_art`
+    /// 1. `$ buckets expect set file png` in `model`
+    /// 1. write `hero.png` and `notes.txt` in `concept_art`, commit and finalize
+    /// 1. `$ buckets check` in `model`
+    /// 1. change `hero.png`, commit and finalize again
+    ///
+    /// # Expected output
+    /// Only `hero.png` is delivered to `model/inputs/concept_art`, the expectation of `model` is met
+    /// and the second version replaces the first.
+    ///
+    #[test]
+    fn test_finalize_delivers_output_downstream() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        buckets(&repo_dir, &["create", "concept_art"]).success();
+        buckets(&repo_dir, &["create", "model"]).success();
+        buckets(&repo_dir, &["link", "concept_art", "model"]).success();
+
+        let concept_dir = repo_dir.join("concept_art");
+        let model_dir = repo_dir.join("model");
+        buckets(&concept_dir, &["expect", "set", "file", "png"]).success();
+        buckets(&model_dir, &["expect", "set", "file", "png"]).success();
+
+        fs::write(concept_dir.join("hero.png"), b"version 1").unwrap();
+        fs::write(concept_dir.join("notes.txt"), b"notes").unwrap();
+        buckets(&concept_dir, &["commit"]).success();
+        buckets(&concept_dir, &["finalize"])
+            .success()
+            .stdout(predicate::str::contains("Delivered 1 file(s) of concept_art version 1 to model"));
+
+        let delivered = model_dir.join("inputs").join("concept_art");
+        assert_eq!(fs::read(delivered.join("hero.png")).unwrap(), b"version 1");
+        assert!(!delivered.join("notes.txt").exists());
+
+        buckets(&model_dir, &["check"]).success();
+        buckets(&model_dir, &["links"])
+            .success()
+            .stdout(predicate::str::contains("concept_art (received version 1)"));
+
+        fs::write(concept_dir.join("hero.png"), b"version 2").unwrap();
+        fs::write(concept_dir.join("sketch.png"), b"sketch").unwrap();
+        buckets(&concept_dir, &["commit"]).success();
+        buckets(&concept_dir, &["finalize"]).success();
+
+        assert_eq!(fs::read(delivered.join("hero.png")).unwrap(), b"version 2");
+        assert_eq!(fs::read(delivered.join("sketch.png")).unwrap(), b"sketch");
+        buckets(&model_dir, &["links"])
+            .success()
+            .stdout(predicate::str::contains("concept_art (received version 2)"));
+    }
+
+    /// Test finalizing a version again after delivering it downstream failed.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create concept_art` and `$ buckets create model`
+    /// 1. `$ buckets link concept_art model`
+    /// 1. `$ buckets expect set file png` in `concept_art`
+    /// 1. `$ touch model/inputs`, so nothing can be delivered to `model`
+    /// 1. write `hero.png` in `concept_art`, commit and `$ buckets finalize 1`
+    /// 1. `$ rm model/inputs` and `$ buckets finalize 1`
+    ///
+    /// # Expected output
+    /// The failed delivery doesn't record version 1, so finalizing it again delivers it.
+    ///
+    #[test]
+    fn test_finalize_after_failed_delivery() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        buckets(&repo_dir, &["create", "concept_art"]).success();
+        buckets(&repo_dir, &["create", "model"]).success();
+        buckets(&repo_dir, &["link", "concept_art", "model"]).success();
+
+        let concept_dir = repo_dir.join("concept_art");
+        let model_dir = repo_dir.join("model");
+        buckets(&concept_dir, &["expect", "set", "file", "png"]).success();
+        fs::write(model_dir.join("inputs"), b"in the way").unwrap();
+
+        fs::write(concept_dir.join("hero.png"), b"version 1").unwrap();
+        buckets(&concept_dir, &["commit"]).success();
+        buckets(&concept_dir, &["finalize", "1"]).failure();
+        buckets(&concept_dir, &["versions"])
+            .success()
+            .stdout(predicate::str::contains("No finalized versions of bucket concept_art."));
+
+        fs::remove_file(model_dir.join("inputs")).unwrap();
+        buckets(&concept_dir, &["finalize", "1"])
+            .success()
+            .stdout(predicate::str::contains("Delivered 1 file(s) of concept_art version 1 to model"))
+            .stdout(predicate::str::contains("Finalized concept_art version 1"));
+        assert_eq!(
+            fs::read(model_dir.join("inputs").join("concept_art").join("hero.png")).unwrap(),
+            b"version 1"
+        );
+    }
+
+    /// Test that a delivery failing on one downstream bucket leaves the other downstream buckets unchanged.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create concept_art`, `$ buckets create model_a` and `$ buckets create model_b`
+    /// 1. `$ buckets link concept_art model_a`
+    /// 1. `$ buckets expect set file png` in `concept_art`
+    /// 1. write `hero.png` in `concept_art`, commit and finalize
+    /// 1. `$ buckets link concept_art model_b` and `$ touch model_b/inputs`
+    /// 1. change `hero.png`, commit and finalize
+    ///
+    /// # Expected output
+    /// The second version fails on `model_b` and `model_a` keeps the first version, on disk and in the database.
+    ///
+    #[test]
+    fn test_finalize_failed_delivery_keeps_other_buckets() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        for name in ["concept_art", "model_a", "model_b"] {
+            buckets(&repo_dir, &["create", name]).success();
+        }
+        buckets(&repo_dir, &["link", "concept_art", "model_a"]).success();
+
+        let concept_dir = repo_dir.join("concept_art");
+        buckets(&concept_dir, &["expect", "set", "file", "png"]).success();
+        fs::write(concept_dir.join("hero.png"), b"version 1").unwrap();
+        buckets(&concept_dir, &["commit"]).success();
+        buckets(&concept_dir, &["finalize"]).success();
+
+        buckets(&repo_dir, &["link", "concept_art", "model_b"]).success();
+        fs::write(repo_dir.join("model_b").join("inputs"), b"in the way").unwrap();
+        fs::write(concept_dir.join("hero.png"), b"version 2").unwrap();
+        buckets(&concept_dir, &["commit"]).success();
+        buckets(&concept_dir, &["finalize"])
+            .failure()
+            .stdout(predicate::str::contains("Delivered").not());
+
+        let inputs = repo_dir.join("model_a").join("inputs");
+        assert_eq!(fs::read(inputs.join("concept_art").join("hero.png")).unwrap(), b"version 1");
+        assert_eq!(fs::read_dir(&inputs).unwrap().count(), 1);
+        buckets(&repo_dir.join("model_a"), &["links"])
+            .success()
+            .stdout(predicate::str::contains("concept_art (received version 1)"));
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, head_commit_id};
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test pruning the commits that a retention rule no longer keeps.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo` with a retention rule keeping the last commit of `test_bucket`
+    /// 1. `$ buckets commit` and `$ buckets finalize`, then two more commits
+    /// 1. `$ buckets prune --dry-run` and `$ buckets prune`
+    /// 1. `$ buckets history`
+    /// 1. `$ buckets rollback all <pruned commit> --yes`
+    /// 1. `$ buckets gc --grace-period 0`
+    /// 1. `$ buckets rollback all <finalized commit> --yes`
+    ///
+    /// # Expected output
+    /// Only the middle commit is pruned, the history still shows it. Rolling back to it is refused, gc removes
+    /// its blob and the finalized commit can still be restored.
+    ///
+    #[test]
+    fn test_prune_expired_commits() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        let config_path = repo_dir.join(".buckets").join("config");
+        let config = fs::read_to_string(&config_path)
+            .unwrap()
+            .replace("retention = []", "retention = [{ bucket = \"test_*\", keep_last = 1 }]");
+        fs::write(&config_path, config).unwrap();
+
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        let mut ids = Vec::new();
+        for version in 1..=3 {
+            fs::write(bucket_dir.join("level.json"), format!("{{\"version\": {}}}", version)).unwrap();
+            buckets(&bucket_dir, &["commit", "-m", &format!("version {}", version)]).success();
+            ids.push(head_commit_id(&repo_dir));
+            if version == 1 {
+                buckets(&bucket_dir, &["finalize"]).success();
+            }
+        }
+
+        buckets(&bucket_dir, &["prune", "--dry-run"])
+            .success()
+            .stdout(predicate::str::contains("test_bucket: 1 commit(s) can be pruned, keeping 2."))
+            .stdout(predicate::str::contains(ids[1].as_str()));
+        buckets(&bucket_dir, &["prune"])
+            .success()
+            .stdout(predicate::str::contains("test_bucket: pruned 1 commit(s), keeping 2."));
+        buckets(&bucket_dir, &["prune"])
+            .success()
+            .stdout(predicate::str::contains("No commits to prune."));
+
+        buckets(&bucket_dir, &["history"])
+            .success()
+            .stdout(predicate::str::contains(format!("commit {}", ids[1])))
+            .stdout(predicate::str::contains("Pruned:").count(1));
+
+        buckets(&bucket_dir, &["rollback", "all", &ids[1][..8], "--yes"])
+            .failure()
+            .stderr(predicate::str::contains("was pruned"));
+
+        buckets(&bucket_dir, &["gc", "--grace-period", "0"])
+            .success()
+            .stdout(predicate::str::contains("Removed 1 unreachable blob(s)"));
+        let storage = bucket_dir.join(".b").join("storage");
+        assert!(!storage.join(blake3::hash(b"{\"version\": 2}").to_string()).exists());
+
+        buckets(&bucket_dir, &["rollback", "all", &ids[0][..8], "--yes"]).success();
+        assert_eq!(fs::read_to_string(bucket_dir.join("level.json")).unwrap(), "{\"version\": 1}");
+        buckets(&bucket_dir, &["fsck"]).success();
+    }
+}
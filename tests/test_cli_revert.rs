@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, commit};
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn create_committed_bucket(base: &Path) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        fs::create_dir(bucket_dir.join("subdir")).unwrap();
+        fs::write(bucket_dir.join("subdir").join("test_file2"), b"test2").unwrap();
+
+        buckets(&bucket_dir, &["commit"]).success();
+        bucket_dir
+    }
+
+    /// Test reverting a single modified file.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit`
+    /// 1. `$ echo "changed" > test_bucket/test_file`
+    /// 1. `$ buckets revert test_file`, answering no and then yes
+    ///
+    /// # Expected output
+    /// The file is only restored after confirmation.
+    ///
+    #[test]
+    fn test_revert_modified_file() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        fs::write(bucket_dir.join("test_file"), b"changed").unwrap();
+
+        let mut cmd_revert = assert_cmd::Command::cargo_bin("buckets").unwrap();
+        cmd_revert
+            .current_dir(&bucket_dir)
+            .arg("revert")
+            .arg("test_file")
+            .write_stdin("n\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Restore cancelled."));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"changed");
+
+        let mut cmd_revert = assert_cmd::Command::cargo_bin("buckets").unwrap();
+        cmd_revert
+            .current_dir(&bucket_dir)
+            .arg("revert")
+            .arg("test_file")
+            .write_stdin("y\n")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Restored test_file"));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"test");
+    }
+
+    /// Test reverting all files of a bucket from a sub directory.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. write `test_file` and `subdir/test_file2`
+    /// 1. `$ buckets commit`
+    /// 1. change `test_file` and remove `subdir/test_file2`
+    /// 1. `$ buckets revert all --yes` from `subdir`
+    ///
+    /// # Expected output
+    /// Both files are restored.
+    ///
+    #[test]
+    fn test_revert_all() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        fs::write(bucket_dir.join("test_file"), b"changed").unwrap();
+        fs::remove_file(bucket_dir.join("subdir").join("test_file2")).unwrap();
+
+        buckets(&bucket_dir.join("subdir"), &["revert", "all", "--yes"]).success();
+
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"test");
+        assert_eq!(fs::read(bucket_dir.join("subdir").join("test_file2")).unwrap(), b"test2");
+    }
+
+    /// Test reverting a file which was never committed.
+    ///
+    /// # Expected output
+    /// Error: File not found in last commit.
+    ///
+    #[test]
+    fn test_revert_unknown_file() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["revert", "unknown_file"])
+            .failure()
+            .stderr(predicate::str::contains("File unknown_file not found in last commit."));
+    }
+
+    /// Test that a reverted file gets the permissions it was committed with.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. write `test_file` and `subdir/test_file2`
+    /// 1. `$ buckets commit`
+    /// 1. `$ echo "run" > test_bucket/run.sh && chmod 755 test_bucket/run.sh`
+    /// 1. `$ buckets commit -m "script"`
+    /// 1. `$ rm test_bucket/run.sh`
+    /// 1. `$ buckets revert all --yes`
+    ///
+    /// # Expected output
+    /// `run.sh` is restored executable.
+    ///
+    #[cfg(unix)]
+    #[test]
+    fn test_revert_restores_file_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        let script = bucket_dir.join("run.sh");
+        fs::write(&script, b"run").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        commit(&bucket_dir, "script");
+
+        fs::remove_file(&script).unwrap();
+        buckets(&bucket_dir, &["revert", "all", "--yes"]).success();
+
+        assert_eq!(fs::metadata(&script).unwrap().permissions().mode() & 0o7777, 0o755);
+    }
+}
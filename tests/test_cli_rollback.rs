@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, commit, create_repo_with_bucket};
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::Path;
+
+    fn first_commit_id(bucket_dir: &Path) -> String {
+        let db_location = bucket_dir.parent().unwrap().join(".buckets/buckets.db");
+        let conn = rusqlite::Connection::open(db_location).unwrap();
+        conn.query_row("SELECT id FROM commits ORDER BY rowid LIMIT 1", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    /// Test rolling back all files to the first commit.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ echo "changed" > test_bucket/test_file`
+    /// 1. `$ echo "test2" > test_bucket/test_file2`
+    /// 1. `$ buckets commit -m "second commit"`
+    /// 1. `$ buckets rollback all [first commit id prefix] --delete`
+    ///
+    /// # Expected output
+    /// `test_file` has its first content again and `test_file2` is deleted.
+    ///
+    #[test]
+    fn test_rollback_all() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::write(bucket_dir.join("test_file"), b"changed").unwrap();
+        fs::write(bucket_dir.join("test_file2"), b"test2").unwrap();
+        commit(&bucket_dir, "second commit");
+
+        let commit_id = first_commit_id(&bucket_dir);
+        buckets(&bucket_dir, &["rollback", "all", &commit_id[..8].to_lowercase(), "--delete"])
+            .success()
+            .stdout(predicate::str::contains(format!("Rolling back to commit {}", commit_id)));
+
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"test");
+        assert!(!bucket_dir.join("test_file2").exists());
+    }
+
+    /// Test rolling back a file from a corrupted blob.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. overwrite the stored blob with other content
+    /// 1. `$ buckets rollback test_file [commit id]`
+    ///
+    /// # Expected output
+    /// Error: Blob is corrupted. The working file is left untouched.
+    ///
+    #[test]
+    fn test_rollback_corrupted_blob() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        commit(&bucket_dir, "first commit");
+        fs::remove_file(bucket_dir.join("test_file")).unwrap();
+
+        let blob = bucket_dir.join(".b/storage").join(blake3::hash(b"test").to_string());
+        fs::write(&blob, zstd::encode_all(&b"corrupted"[..], 0).unwrap()).unwrap();
+
+        buckets(&bucket_dir, &["rollback", "test_file", &first_commit_id(&bucket_dir)])
+            .failure()
+            .stderr(predicate::str::contains("is corrupted"));
+
+        assert!(!bucket_dir.join("test_file").exists());
+    }
+
+    /// Test rolling back to a commit which does not exist.
+    ///
+    /// # Expected output
+    /// Error: Commit not found in bucket.
+    ///
+    #[test]
+    fn test_rollback_unknown_commit() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        buckets(&bucket_dir, &["rollback", "all", "0000"])
+            .failure()
+            .stderr(predicate::str::contains("Commit 0000 not found in bucket test_bucket."));
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, create_repo_with_bucket, head_commit_id};
+    use predicates::prelude::{predicate, PredicateBooleanExt};
+    use std::fs;
+
+    /// Test the `show` command with a short commit id.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "wall" > test_bucket/textures/wall.png`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ buckets show <first 5 characters of the commit id>`
+    ///
+    /// # Expected output
+    /// The commit with its message and the files of its snapshot.
+    ///
+    #[test]
+    fn test_show_short_id() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::create_dir_all(bucket_dir.join("textures")).unwrap();
+        fs::write(bucket_dir.join("textures").join("wall.png"), b"wall").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "first commit"]).success();
+
+        let id = head_commit_id(bucket_dir.parent().unwrap());
+        assert_eq!(id.len(), 64);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+
+        buckets(&bucket_dir, &["show", &id[..5]])
+            .success()
+            .stdout(predicate::str::contains(format!("commit {}", id)))
+            .stdout(predicate::str::contains("first commit"))
+            .stdout(predicate::str::contains("added:    textures/wall.png"))
+            .stdout(predicate::str::is_match(r"4  textures/wall\.png").unwrap())
+            .stderr(predicate::str::contains("tampered").not());
+    }
+
+    /// Test that the `show` command detects a commit that was changed after it was stored.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "wall" > test_bucket/wall.png`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. Change the message of the commit in the database
+    /// 1. `$ buckets show <commit id>`
+    ///
+    /// # Expected output
+    /// Warning: commit <id> does not match its content, it may have been tampered with.
+    ///
+    #[test]
+    fn test_show_tampered_commit() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        let repo_dir = bucket_dir.parent().unwrap();
+
+        fs::write(bucket_dir.join("wall.png"), b"wall").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "first commit"]).success();
+
+        let id = head_commit_id(repo_dir);
+        let conn = rusqlite::Connection::open(repo_dir.join(".buckets").join("buckets.db")).unwrap();
+        conn.execute("UPDATE commits SET message = 'rewritten' WHERE id = ?1", [&id])
+            .unwrap();
+
+        buckets(&bucket_dir, &["show", &id])
+            .success()
+            .stderr(predicate::str::contains(format!(
+                "Warning: commit {} does not match its content, it may have been tampered with.",
+                id
+            )));
+    }
+
+    /// Test that the `show` command only matches hexadecimal commit id prefixes.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "wall" > test_bucket/textures/wall.png`
+    /// 1. `$ buckets commit -m "first commit"`
+    /// 1. `$ buckets show %`
+    ///
+    /// # Expected output
+    /// An error that % is not a commit id, instead of the commit it would match as a pattern.
+    ///
+    #[test]
+    fn test_show_pattern_id() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+
+        fs::create_dir_all(bucket_dir.join("textures")).unwrap();
+        fs::write(bucket_dir.join("textures").join("wall.png"), b"wall").unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "first commit"]).success();
+
+        for pattern in ["%", "_", ""] {
+            buckets(&bucket_dir, &["show", pattern])
+                .failure()
+                .stdout(predicate::str::contains("first commit").not())
+                .stderr(predicate::str::contains(format!("Commit id {} is not a hexadecimal commit id or prefix.", pattern)));
+        }
+    }
+}
@@ -0,0 +1,162 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, commit};
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn create_committed_bucket(base: &Path) -> PathBuf {
+        buckets(base, &["init", "test_repo"]).success();
+        let repo_dir = base.join("test_repo");
+
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+        buckets(&bucket_dir, &["commit"]).success();
+        bucket_dir
+    }
+
+    fn stash(bucket_dir: &Path, args: &[&str]) -> assert_cmd::assert::Assert {
+        buckets(bucket_dir, &[&["stash"], args].concat())
+    }
+
+    /// Test stashing local changes and restoring them.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets commit`
+    /// 1. `$ echo "wip" > test_bucket/test_file`
+    /// 1. `$ echo "new" > test_bucket/new_file`
+    /// 1. `$ buckets stash`
+    /// 1. `$ buckets stash list`
+    /// 1. `$ buckets stash restore`
+    ///
+    /// # Expected output
+    /// The working directory is reset by the stash and the changes return on restore.
+    ///
+    #[test]
+    fn test_stash_and_restore() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+        fs::write(bucket_dir.join("test_file"), b"wip").unwrap();
+        fs::write(bucket_dir.join("new_file"), b"new").unwrap();
+
+        stash(&bucket_dir, &[])
+            .success()
+            .stdout(predicate::str::contains("Saved local changes as stash stash-1"));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"test");
+        assert!(!bucket_dir.join("new_file").exists());
+
+        stash(&bucket_dir, &["list"])
+            .success()
+            .stdout(predicate::str::contains("stash-1"));
+
+        stash(&bucket_dir, &["restore"])
+            .success()
+            .stdout(predicate::str::contains("Restored stash stash-1"));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"wip");
+        assert_eq!(fs::read(bucket_dir.join("new_file")).unwrap(), b"new");
+
+        stash(&bucket_dir, &["list"])
+            .success()
+            .stdout(predicate::str::contains("No stashes found"));
+    }
+
+    /// Test named stashes and dropping a stash.
+    ///
+    /// # Commands
+    /// 1. `$ buckets stash --name hotfix`
+    /// 1. `$ buckets stash --name hotfix` with new changes
+    /// 1. `$ buckets stash drop hotfix`
+    /// 1. `$ buckets stash restore hotfix`
+    ///
+    /// # Expected output
+    /// Duplicate names are refused and a dropped stash can not be restored.
+    ///
+    #[test]
+    fn test_stash_named_and_drop() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+
+        stash(&bucket_dir, &[])
+            .success()
+            .stdout(predicate::str::contains("No local changes to stash."));
+
+        fs::write(bucket_dir.join("test_file"), b"wip").unwrap();
+        stash(&bucket_dir, &["--name", "hotfix"]).success();
+
+        fs::write(bucket_dir.join("test_file"), b"other").unwrap();
+        stash(&bucket_dir, &["--name", "hotfix"])
+            .failure()
+            .stderr(predicate::str::contains("Stash hotfix already exists."));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"other");
+
+        stash(&bucket_dir, &["restore", "hotfix"])
+            .failure()
+            .stderr(predicate::str::contains("uncommitted changes"));
+
+        stash(&bucket_dir, &["drop", "hotfix"])
+            .success()
+            .stdout(predicate::str::contains("Dropped stash hotfix"));
+        stash(&bucket_dir, &["restore", "hotfix"])
+            .failure()
+            .stderr(predicate::str::contains("Stash hotfix not found."));
+    }
+
+    /// Test restoring a stash after the bucket has new commits.
+    ///
+    /// # Commands
+    /// 1. `$ echo "wip" > test_bucket/test_file`
+    /// 1. `$ buckets stash`
+    /// 1. `$ echo "other" > test_bucket/other_file`
+    /// 1. `$ buckets commit -m "other"`
+    /// 1. `$ buckets stash restore`
+    /// 1. `$ echo "wip" > test_bucket/test_file` and `$ buckets stash`
+    /// 1. `$ echo "fixed" > test_bucket/test_file` and `$ buckets commit -m "fixed"`
+    /// 1. `$ buckets stash restore`
+    ///
+    /// # Expected output
+    /// The stashed change is applied on top of the new commit, which keeps its files. A stash that changes a
+    /// file which was committed since is refused and kept.
+    ///
+    #[test]
+    fn test_stash_restore_after_commit() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_committed_bucket(temp_dir.path());
+
+        fs::write(bucket_dir.join("test_file"), b"wip").unwrap();
+        stash(&bucket_dir, &[]).success();
+        fs::write(bucket_dir.join("other_file"), b"other").unwrap();
+        commit(&bucket_dir, "other");
+
+        stash(&bucket_dir, &["restore"])
+            .success()
+            .stdout(predicate::str::contains("Restored stash stash-1"));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"wip");
+        assert_eq!(fs::read(bucket_dir.join("other_file")).unwrap(), b"other");
+
+        stash(&bucket_dir, &[]).success();
+        fs::write(bucket_dir.join("test_file"), b"fixed").unwrap();
+        commit(&bucket_dir, "fixed");
+
+        stash(&bucket_dir, &["restore"])
+            .failure()
+            .stderr(predicate::str::contains(
+                "Stash stash-1 changes files which were committed since it was saved: test_file",
+            ));
+        assert_eq!(fs::read(bucket_dir.join("test_file")).unwrap(), b"fixed");
+        stash(&bucket_dir, &["list"])
+            .success()
+            .stdout(predicate::str::contains("stash-1"));
+    }
+}
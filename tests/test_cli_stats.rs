@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, head_commit_id};
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test that a modified Photoshop file is stored as a delta and restored from it.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo` with `patterns = ["*.psd"]` in the `[delta]` section of the config
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ buckets commit` with a 200 KB file, then again after changing a few bytes
+    /// 1. `$ buckets stats`
+    /// 1. `$ buckets rollback all <first commit> --yes` and `$ buckets rollback all <second commit> --yes`
+    ///
+    /// # Expected output
+    /// One delta blob that is much smaller than the file, both versions are restored.
+    ///
+    #[test]
+    fn test_stats_delta_saving() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["init", "test_repo"]).success();
+        let repo_dir = temp_dir.path().join("test_repo");
+        let config_path = repo_dir.join(".buckets").join("config");
+        let config = fs::read_to_string(&config_path).unwrap().replace("patterns = []", "patterns = [\"*.psd\"]");
+        fs::write(&config_path, config).unwrap();
+
+        buckets(&repo_dir, &["create", "test_bucket"]).success();
+        let bucket_dir = repo_dir.join("test_bucket");
+
+        // random content doesn't compress, what is saved is saved by the delta
+        let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+        let first: Vec<u8> = (0..200_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        fs::write(bucket_dir.join("hero.psd"), &first).unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "first"]).success();
+        let first_id = head_commit_id(&repo_dir);
+
+        let mut second = first.clone();
+        second[120_000..120_010].copy_from_slice(b"new layer!");
+        fs::write(bucket_dir.join("hero.psd"), &second).unwrap();
+        buckets(&bucket_dir, &["commit", "-m", "second"]).success();
+        let second_id = head_commit_id(&repo_dir);
+
+        buckets(&bucket_dir, &["stats"])
+            .success()
+            .stdout(predicate::str::contains("Blobs:        2"))
+            .stdout(predicate::str::contains("Delta blobs:  1"))
+            .stdout(predicate::str::is_match(r"Delta saving: 1\d{5} bytes, the deltas take \d{1,4} bytes instead of 2\d{5} bytes").unwrap());
+
+        buckets(&bucket_dir, &["rollback", "all", &first_id[..8], "--yes"]).success();
+        assert_eq!(fs::read(bucket_dir.join("hero.psd")).unwrap(), first);
+        buckets(&bucket_dir, &["rollback", "all", &second_id[..8], "--yes"]).success();
+        assert_eq!(fs::read(bucket_dir.join("hero.psd")).unwrap(), second);
+    }
+}
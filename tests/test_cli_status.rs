@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{buckets, create_repo_with_bucket};
+    use predicates::prelude::predicate;
+    use std::fs;
+
+    /// Test the `status` command outside of a bucket.
+    ///
+    /// # Commands
+    /// `$ buckets status`
+    ///
+    /// # Expected output
+    /// Error: Not a valid bucket.
+    ///
+    #[test]
+    fn test_status_not_in_bucket() {
+        let temp_dir = tempdir().unwrap();
+        buckets(temp_dir.path(), &["status"])
+            .failure()
+            .stderr(predicate::str::contains("Not a valid bucket"));
+    }
+
+    /// Test the `status` command before the first commit.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. `$ echo "test" > test_bucket/test_file`
+    /// 1. `$ buckets status`
+    ///
+    /// # Expected output
+    /// `test_file` listed as a new file.
+    ///
+    #[test]
+    fn test_status_new_file() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        fs::write(bucket_dir.join("test_file"), b"test").unwrap();
+
+        buckets(&bucket_dir, &["status"])
+            .success()
+            .stdout(predicate::str::contains("New files:\n    test_file\n"));
+    }
+
+    /// Test the `status` command after changing a committed bucket.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create test_bucket`
+    /// 1. write `modified_file` and `deleted_file`
+    /// 1. `$ buckets commit`
+    /// 1. change `modified_file`, remove `deleted_file` and write `subdir/new_file`
+    /// 1. `$ buckets status` from `subdir`
+    ///
+    /// # Expected output
+    /// Each file is listed in its own section.
+    ///
+    #[test]
+    fn test_status_after_commit() {
+        let temp_dir = tempdir().unwrap();
+        let bucket_dir = create_repo_with_bucket(temp_dir.path());
+        fs::write(bucket_dir.join("modified_file"), b"test").unwrap();
+        fs::write(bucket_dir.join("deleted_file"), b"test2").unwrap();
+
+        buckets(&bucket_dir, &["commit"]).success();
+
+        buckets(&bucket_dir, &["status"])
+            .success()
+            .stdout(predicate::str::contains("No changes since last commit."));
+
+        fs::write(bucket_dir.join("modified_file"), b"changed").unwrap();
+        fs::remove_file(bucket_dir.join("deleted_file")).unwrap();
+        fs::create_dir(bucket_dir.join("subdir")).unwrap();
+        fs::write(bucket_dir.join("subdir").join("new_file"), b"new").unwrap();
+
+        buckets(&bucket_dir.join("subdir"), &["status"])
+            .success()
+            .stdout(predicate::str::contains("New files:\n    subdir/new_file\n"))
+            .stdout(predicate::str::contains("Modified files:\n    modified_file\n"))
+            .stdout(predicate::str::contains("Deleted files:\n    deleted_file\n"));
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod common;
+
+#[cfg(test)]
+use tempfile::tempdir;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::buckets;
+    use predicates::prelude::predicate;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    // Creates a repository with two buckets that both commit the same file
+    fn create_repo_with_buckets(base: &Path, init_args: &[&str]) -> PathBuf {
+        buckets(base, &[&["init", "test_repo"], init_args].concat()).success();
+        let repo_dir = base.join("test_repo");
+
+        for name in ["bucket_a", "bucket_b"] {
+            buckets(&repo_dir, &["create", name]).success();
+            fs::write(repo_dir.join(name).join("texture.png"), b"shared texture").unwrap();
+            buckets(&repo_dir.join(name), &["commit", "-m", "first commit"]).success();
+        }
+        repo_dir
+    }
+
+    /// Test that buckets of a repository in repository storage mode store a file only once.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo --storage repository`
+    /// 1. `$ buckets create bucket_a` and `$ buckets create bucket_b`
+    /// 1. `$ echo "shared texture" > texture.png` and `$ buckets commit` in both buckets
+    /// 1. `$ buckets storage`
+    ///
+    /// # Expected output
+    /// One blob in `.buckets/objects`, referenced by both buckets.
+    ///
+    #[test]
+    fn test_repository_storage_deduplicates() {
+        let temp_dir = tempdir().unwrap();
+        let repo_dir = create_repo_with_buckets(temp_dir.path(), &["--storage", "repository"]);
+
+        let objects = repo_dir.join(".buckets").join("objects");
+        assert!(objects.join(blake3::hash(b"shared texture").to_string()).is_file());
+        assert_eq!(fs::read_dir(&objects).unwrap().count(), 1);
+        assert!(!repo_dir.join("bucket_a").join(".b").join("storage").exists());
+
+        buckets(&repo_dir, &["storage"])
+            .success()
+            .stdout(predicate::str::contains("Storage mode: repository"))
+            .stdout(predicate::str::contains("Blobs:        1"))
+            .stdout(predicate::str::contains("Shared blobs: 1"));
+
+        fs::remove_file(repo_dir.join("bucket_b").join("texture.png")).unwrap();
+        buckets(&repo_dir.join("bucket_b"), &["revert", "all", "--yes"]).success();
+        assert_eq!(fs::read(repo_dir.join("bucket_b").join("texture.png")).unwrap(), b"shared texture");
+    }
+
+    /// Test moving the blobs of a repository in bucket storage mode into the object store.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`
+    /// 1. `$ buckets create bucket_a` and `$ buckets create bucket_b`
+    /// 1. `$ echo "shared texture" > texture.png` and `$ buckets commit` in both buckets
+    /// 1. `$ buckets storage migrate`
+    ///
+    /// # Expected output
+    /// The duplicate blob is removed, the buckets restore their files from `.buckets/objects`.
+    ///
+    #[test]
+    fn test_storage_migrate() {
+        let temp_dir = tempdir().unwrap();
+        let repo_dir = create_repo_with_buckets(temp_dir.path(), &[]);
+        let hash = blake3::hash(b"shared texture").to_string();
+        assert!(repo_dir.join("bucket_a").join(".b").join("storage").join(&hash).is_file());
+
+        buckets(&repo_dir, &["storage", "migrate"])
+            .success()
+            .stdout(predicate::str::contains("Moved 1 blob(s)"))
+            .stdout(predicate::str::contains("1 duplicate blob(s) removed"));
+
+        assert!(repo_dir.join(".buckets").join("objects").join(&hash).is_file());
+        for name in ["bucket_a", "bucket_b"] {
+            assert!(!repo_dir.join(name).join(".b").join("storage").join(&hash).exists());
+        }
+        assert!(fs::read_to_string(repo_dir.join(".buckets").join("config"))
+            .unwrap()
+            .contains("storage = \"repository\""));
+
+        fs::remove_file(repo_dir.join("bucket_a").join("texture.png")).unwrap();
+        buckets(&repo_dir.join("bucket_a"), &["revert", "all", "--yes"]).success();
+        assert_eq!(fs::read(repo_dir.join("bucket_a").join("texture.png")).unwrap(), b"shared texture");
+
+        buckets(&repo_dir, &["storage", "migrate"])
+            .success()
+            .stdout(predicate::str::contains("Repository already stores its blobs"));
+    }
+
+    /// Test finishing a migration that was interrupted while copying a packfile.
+    ///
+    /// # Commands
+    /// 1. `$ buckets init test_repo`, two buckets that commit the same file
+    /// 1. `$ buckets pack`
+    /// 1. `$ buckets storage migrate` with a truncated copy of the pack of `bucket_a` in `.buckets/objects`
+    ///
+    /// # Expected output
+    /// The truncated copy is copied again and the buckets restore their files from it.
+    ///
+    #[test]
+    fn test_storage_migrate_after_truncated_pack_copy() {
+        let temp_dir = tempdir().unwrap();
+        let repo_dir = create_repo_with_buckets(temp_dir.path(), &[]);
+        buckets(&repo_dir, &["pack"]).success();
+
+        let packs = repo_dir.join("bucket_a").join(".b").join("storage").join("packs");
+        let pack_path = fs::read_dir(&packs)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|extension| extension == "pack"))
+            .unwrap();
+        let objects_packs = repo_dir.join(".buckets").join("objects").join("packs");
+        fs::create_dir_all(&objects_packs).unwrap();
+        let pack = fs::read(&pack_path).unwrap();
+        fs::write(objects_packs.join(pack_path.file_name().unwrap()), &pack[..pack.len() / 2]).unwrap();
+
+        buckets(&repo_dir, &["storage", "migrate"]).success();
+        assert_eq!(fs::read(objects_packs.join(pack_path.file_name().unwrap())).unwrap(), pack);
+
+        for name in ["bucket_a", "bucket_b"] {
+            fs::remove_file(repo_dir.join(name).join("texture.png")).unwrap();
+            buckets(&repo_dir.join(name), &["revert", "all", "--yes"]).success();
+            assert_eq!(fs::read(repo_dir.join(name).join("texture.png")).unwrap(), b"shared texture");
+        }
+    }
+}
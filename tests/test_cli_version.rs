@@ -1,5 +1,9 @@
+#[cfg(test)]
+mod common;
+
 #[cfg(test)]
 mod tests {
+    use crate::common::buckets;
     use predicates::prelude::predicate;
     use tempfile::tempdir;
 
@@ -15,21 +19,12 @@ mod tests {
     fn test_cli_version() {
         let temp_dir = tempdir().unwrap();
 
-        let mut cmd_init = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_init.current_dir(temp_dir.path());
-        cmd_init
-            .arg("init")
-            .arg("test_repo")
-            .assert()
+        buckets(temp_dir.path(), &["init", "test_repo"])
             .success()
             .stdout(predicate::str::contains(""))
             .stderr(predicate::str::is_empty());
 
-        let mut cmd_system = assert_cmd::Command::cargo_bin("buckets").unwrap();
-        cmd_system.current_dir(temp_dir.path().join("test_repo"));
-        cmd_system
-            .arg("system")
-            .assert()
+        buckets(&temp_dir.path().join("test_repo"), &["system"])
             .stdout(predicate::str::contains("System Information:"))
             .success();
     }